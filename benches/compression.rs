@@ -1,5 +1,7 @@
 use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
-use sequitur_rs::{Sequitur, SequiturDocuments, SequiturDocumentsRle, SequiturRle};
+use sequitur_rs::{
+    Algorithm, InternedSequitur, Sequitur, SequiturDocuments, SequiturDocumentsRle, SequiturRle,
+};
 
 /// Generate repetitive text data
 fn generate_repetitive_text(size: usize) -> String {
@@ -75,9 +77,7 @@ fn generate_ab_pattern(k: usize) -> Vec<u8> {
 fn generate_difference_sequence(size: usize) -> Vec<u8> {
     let mut result = Vec::with_capacity(size);
     result.push(0);
-    for _ in 1..size {
-        result.push(1);
-    }
+    result.resize(size, 1);
     result
 }
 
@@ -190,17 +190,30 @@ fn bench_iteration(c: &mut Criterion) {
 
         group.bench_with_input(BenchmarkId::new("Sequitur", size), &seq, |b, seq| {
             b.iter(|| {
-                let count: usize = black_box(seq.iter().count());
+                let count: usize = seq.iter().map(black_box).count();
                 black_box(count)
             });
         });
 
+        let mut compacted = seq.clone();
+        compacted.compact();
+        group.bench_with_input(
+            BenchmarkId::new("Sequitur (compacted)", size),
+            &compacted,
+            |b, seq| {
+                b.iter(|| {
+                    let count: usize = seq.iter().map(black_box).count();
+                    black_box(count)
+                });
+            },
+        );
+
         group.bench_with_input(
             BenchmarkId::new("SequiturDocuments", size),
             &docs,
             |b, docs| {
                 b.iter(|| {
-                    let count: usize = black_box(docs.iter_document(&1).unwrap().count());
+                    let count: usize = docs.iter_document(&1).unwrap().map(black_box).count();
                     black_box(count)
                 });
             },
@@ -210,6 +223,74 @@ fn bench_iteration(c: &mut Criterion) {
     group.finish();
 }
 
+/// Split text into owned words, keeping the separating spaces attached
+fn split_words(text: &str) -> Vec<String> {
+    text.split_inclusive(' ').map(String::from).collect()
+}
+
+/// Benchmark interning expensive values against a plain `Sequitur<String>`
+fn bench_interned_strings(c: &mut Criterion) {
+    let sizes = [10_000, 100_000];
+    let mut group = c.benchmark_group("interned_strings");
+
+    for size in sizes.iter() {
+        let words = split_words(&generate_source_code(*size));
+
+        group.bench_with_input(BenchmarkId::new("Sequitur", size), &words, |b, words| {
+            b.iter(|| {
+                let mut seq = Sequitur::new();
+                seq.extend(black_box(words.iter().cloned()));
+                black_box(seq)
+            });
+        });
+
+        group.bench_with_input(
+            BenchmarkId::new("InternedSequitur", size),
+            &words,
+            |b, words| {
+                b.iter(|| {
+                    let mut seq = InternedSequitur::<String>::new();
+                    for word in black_box(words) {
+                        seq.push_ref(word.as_str());
+                    }
+                    black_box(seq)
+                });
+            },
+        );
+    }
+
+    group.finish();
+}
+
+fn bench_interned_iteration(c: &mut Criterion) {
+    let sizes = [10_000, 100_000];
+    let mut group = c.benchmark_group("interned_iteration");
+
+    for size in sizes.iter() {
+        let words = split_words(&generate_source_code(*size));
+
+        let mut seq = Sequitur::new();
+        seq.extend(words.iter().cloned());
+
+        let mut interned = InternedSequitur::new();
+        interned.extend(words.iter().cloned());
+
+        group.bench_with_input(BenchmarkId::new("Sequitur", size), &seq, |b, seq| {
+            b.iter(|| black_box(seq.iter().map(String::len).sum::<usize>()));
+        });
+
+        group.bench_with_input(
+            BenchmarkId::new("InternedSequitur", size),
+            &interned,
+            |b, interned| {
+                b.iter(|| black_box(interned.iter().map(String::len).sum::<usize>()));
+            },
+        );
+    }
+
+    group.finish();
+}
+
 // =============================================================================
 // RLE-specific benchmarks
 // =============================================================================
@@ -343,14 +424,14 @@ fn bench_rle_iteration(c: &mut Criterion) {
 
         group.bench_with_input(BenchmarkId::new("Sequitur", size), &seq, |b, seq| {
             b.iter(|| {
-                let count: usize = black_box(seq.iter().count());
+                let count: usize = seq.iter().map(black_box).count();
                 black_box(count)
             });
         });
 
         group.bench_with_input(BenchmarkId::new("SequiturRle", size), &rle, |b, rle| {
             b.iter(|| {
-                let count: usize = black_box(rle.iter().count());
+                let count: usize = rle.iter().map(black_box).count();
                 black_box(count)
             });
         });
@@ -402,6 +483,65 @@ fn bench_rle_documents(c: &mut Criterion) {
     group.finish();
 }
 
+/// Builds a test text of roughly the given length
+type TextGenerator = fn(usize) -> String;
+
+/// Text generators shared by the algorithm comparisons
+const TEXT_GENERATORS: [(&str, TextGenerator); 3] = [
+    ("repetitive_text", generate_repetitive_text),
+    ("source_code", generate_source_code),
+    ("low_repetition", generate_low_repetition),
+];
+
+/// Benchmark offline Re-Pair against online Sequitur on the same generators
+fn bench_repair(c: &mut Criterion) {
+    let sizes = [1_000, 10_000, 50_000];
+    let mut group = c.benchmark_group("repair");
+
+    for (name, generate) in TEXT_GENERATORS {
+        for size in sizes.iter() {
+            let data = generate(*size);
+
+            for algorithm in [Algorithm::Sequitur, Algorithm::RePair] {
+                group.bench_with_input(
+                    BenchmarkId::new(format!("{:?}/{}", algorithm, name), size),
+                    &data,
+                    |b, data| {
+                        b.iter(|| {
+                            black_box(Sequitur::compress(black_box(data.chars()), algorithm))
+                        });
+                    },
+                );
+            }
+        }
+    }
+
+    group.finish();
+}
+
+fn bench_parallel(c: &mut Criterion) {
+    let sizes = [10_000, 100_000];
+    let mut group = c.benchmark_group("parallel");
+
+    for (name, generate) in TEXT_GENERATORS {
+        for size in sizes.iter() {
+            let data: Vec<char> = generate(*size).chars().collect();
+
+            for threads in [1, 4] {
+                group.bench_with_input(
+                    BenchmarkId::new(format!("{}_threads/{}", threads, name), size),
+                    &data,
+                    |b, data| {
+                        b.iter(|| black_box(Sequitur::build_parallel(black_box(data), threads)));
+                    },
+                );
+            }
+        }
+    }
+
+    group.finish();
+}
+
 /// Print compression statistics comparison (not a timed benchmark)
 fn print_compression_stats(c: &mut Criterion) {
     // This benchmark exists just to print compression statistics
@@ -505,6 +645,31 @@ fn print_compression_stats(c: &mut Criterion) {
         );
     }
 
+    // Online vs offline grammar size
+    eprintln!("\n{:-^80}", " Sequitur vs Re-Pair ");
+    eprintln!(
+        "{:<25} {:>10} {:>12} {:>12} {:>12}",
+        "Dataset", "Input", "Seq Syms", "RePair Syms", "RePair Rules"
+    );
+    eprintln!("{:-<80}", "");
+
+    for (name, generate) in TEXT_GENERATORS {
+        for size in [1_000, 10_000, 100_000] {
+            let data = generate(size);
+            let seq_stats = Sequitur::compress(data.chars(), Algorithm::Sequitur).stats();
+            let repair_stats = Sequitur::compress(data.chars(), Algorithm::RePair).stats();
+
+            eprintln!(
+                "{:<25} {:>10} {:>12} {:>12} {:>12}",
+                format!("{}_{}", name, size),
+                data.len(),
+                seq_stats.grammar_symbols,
+                repair_stats.grammar_symbols,
+                repair_stats.num_rules
+            );
+        }
+    }
+
     // Multi-document comparison
     eprintln!("\n{:-^80}", " Multi-Document Compression ");
     eprintln!(
@@ -552,6 +717,10 @@ criterion_group!(
     bench_sequitur_source_code,
     bench_sequitur_low_repetition,
     bench_iteration,
+    bench_interned_strings,
+    bench_interned_iteration,
+    bench_repair,
+    bench_parallel,
     // RLE benchmarks
     bench_long_runs,
     bench_ab_pattern,
@@ -39,16 +39,16 @@ fn main() {
 
     // Print per-document statistics
     println!("=== Per-Document Statistics ===");
-    for doc_id in docs.document_ids() {
-        if let Some(doc_stats) = docs.document_stats(doc_id) {
-            println!(
-                "{}: {} chars -> {} symbols ({:.1}%)",
-                doc_id,
-                doc_stats.input_length,
-                doc_stats.document_symbols,
-                doc_stats.compression_ratio()
-            );
-        }
+    for (doc_id, doc_stats) in docs.all_document_stats() {
+        println!(
+            "{}: {} chars -> {} symbols ({:.1}%), {:.1} with rules ({:.1}%)",
+            doc_id,
+            doc_stats.input_length,
+            doc_stats.document_symbols,
+            doc_stats.compression_ratio(),
+            doc_stats.total_symbols(),
+            doc_stats.amortized_compression_ratio()
+        );
     }
 
     // Decompress individual documents
@@ -0,0 +1,164 @@
+use crate::documents::SequiturDocuments;
+use crate::sequitur::Sequitur;
+use std::hash::Hash;
+use std::marker::PhantomData;
+
+/// Grammar constraint parameters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct GrammarConfig {
+    /// Minimum number of uses for a rule to be kept
+    pub min_rule_uses: u32,
+    /// Minimum expanded length of a repeat before a rule is created for it
+    pub min_rule_length: usize,
+}
+
+impl GrammarConfig {
+    /// Returns true for the standard Sequitur constraints, which use the
+    /// specialized digram-uniqueness code path.
+    #[inline]
+    pub fn is_classic(&self) -> bool {
+        self.min_rule_uses == 2 && self.min_rule_length <= 2
+    }
+}
+
+impl Default for GrammarConfig {
+    fn default() -> Self {
+        Self {
+            min_rule_uses: 2,
+            min_rule_length: 2,
+        }
+    }
+}
+
+/// Builder for [`Sequitur`] and [`SequiturDocuments`] with non-default
+/// grammar constraints.
+///
+/// By default a rule is created as soon as a digram repeats and is kept while it
+/// is used at least twice. Raising the limits trades compression for fewer,
+/// more significant rules.
+///
+/// # Example
+///
+/// ```
+/// use sequitur_rs::Sequitur;
+///
+/// let mut seq = Sequitur::builder().min_rule_uses(3).build();
+/// seq.extend("abcabcxyzxyz".chars());
+///
+/// // "abc" and "xyz" each repeat only twice, so no rule survives.
+/// assert_eq!(seq.rules().len(), 1);
+/// assert_eq!(seq.iter().collect::<String>(), "abcabcxyzxyz");
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct SequiturBuilder<T> {
+    config: GrammarConfig,
+    _values: PhantomData<fn() -> T>,
+}
+
+impl<T: Hash + Eq + Clone> SequiturBuilder<T> {
+    pub(crate) fn new() -> Self {
+        Self {
+            config: GrammarConfig::default(),
+            _values: PhantomData,
+        }
+    }
+
+    /// Sets the minimum number of uses for a rule (the default is 2).
+    ///
+    /// A rule is only created once a repeat occurs `uses` times, and is inlined
+    /// as soon as it is used fewer times. Repeats occurring fewer than `uses`
+    /// times are left in place, so digrams may repeat up to `uses - 1` times.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `uses` is less than 2.
+    pub fn min_rule_uses(mut self, uses: u32) -> Self {
+        assert!(uses >= 2, "rules must be used at least twice");
+        self.config.min_rule_uses = uses;
+        self
+    }
+
+    /// Only creates rules for repeats whose expansion is at least `length`
+    /// values long (the default is 2). Experimental.
+    ///
+    /// Shorter repeated digrams are left in place until they grow into a long
+    /// enough repeat, which then becomes a rule with a body of several symbols.
+    pub fn min_rule_length(mut self, length: usize) -> Self {
+        self.config.min_rule_length = length;
+        self
+    }
+
+    /// Builds an empty [`Sequitur`] with the configured constraints.
+    pub fn build(self) -> Sequitur<T> {
+        Sequitur::with_config(self.config)
+    }
+
+    /// Builds an empty [`SequiturDocuments`] with the configured constraints.
+    pub fn build_documents<DocId: Hash + Eq + Clone>(self) -> SequiturDocuments<T, DocId> {
+        SequiturDocuments::with_config(self.config)
+    }
+}
+
+impl<T: Hash + Eq + Clone> Sequitur<T> {
+    /// Returns a builder for configuring the grammar constraints.
+    pub fn builder() -> SequiturBuilder<T> {
+        SequiturBuilder::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_builder_matches_new() {
+        let input = "abracadabra abracadabra";
+        let mut built = Sequitur::builder().build();
+        built.extend(input.chars());
+        let mut plain = Sequitur::new();
+        plain.extend(input.chars());
+
+        assert_eq!(built.stats().num_rules, plain.stats().num_rules);
+        assert_eq!(built.stats().grammar_symbols, plain.stats().grammar_symbols);
+    }
+
+    #[test]
+    fn test_min_rule_uses() {
+        let input = "abcabcabc xyzxyz";
+        let mut seq = Sequitur::builder().min_rule_uses(3).build();
+        seq.extend(input.chars());
+
+        assert_eq!(seq.iter().collect::<String>(), input);
+        // Only the repeat used three times becomes a rule.
+        assert_eq!(seq.rules().len(), 2);
+    }
+
+    #[test]
+    fn test_min_rule_length() {
+        let input = "abcdXabcdYabZ";
+        let mut seq = Sequitur::builder().min_rule_length(4).build();
+        seq.extend(input.chars());
+
+        assert_eq!(seq.iter().collect::<String>(), input);
+        let lengths = seq.grammar.rule_lengths();
+        assert_eq!(lengths.len(), 2);
+        assert!(lengths.iter().all(|(&id, &len)| id == 0 || len >= 4));
+    }
+
+    #[test]
+    fn test_documents_builder() {
+        let mut docs = Sequitur::builder().min_rule_uses(3).build_documents();
+        docs.extend_document(1, "hello hello".chars());
+        docs.extend_document(2, "hello".chars());
+
+        let doc1: String = docs.iter_document(&1).unwrap().collect();
+        assert_eq!(doc1, "hello hello");
+        assert!(!docs.rules().is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "at least twice")]
+    fn test_min_rule_uses_too_small() {
+        Sequitur::<u8>::builder().min_rule_uses(1);
+    }
+}
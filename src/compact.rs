@@ -0,0 +1,320 @@
+use crate::documents::SequiturDocuments;
+use crate::grammar::Grammar;
+use crate::id_gen::IdGenerator;
+use crate::sequitur::Sequitur;
+use crate::symbol::{Symbol, SymbolNode};
+use ahash::{AHashMap as HashMap, AHashSet as HashSet};
+use slotmap::{DefaultKey, SecondaryMap, SlotMap};
+use std::hash::Hash;
+use std::mem;
+
+impl<T: Hash + Eq + Clone> Sequitur<T> {
+    /// Rebuilds the grammar so it is laid out contiguously in memory.
+    ///
+    /// Creating and expanding rules leaves freed slots behind and scatters the
+    /// symbols of a body across the storage. Compacting copies Rule 0 and then
+    /// every other rule into fresh storage, one body after another, and
+    /// renumbers the rules densely in the order they are first used. The
+    /// sequence and the shape of the grammar are unchanged, and values can
+    /// still be pushed afterwards.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use sequitur_rs::Sequitur;
+    ///
+    /// let mut seq = Sequitur::new();
+    /// seq.extend("abcabdabcabd".chars());
+    /// seq.compact();
+    ///
+    /// let mut ids: Vec<_> = seq.rules().keys().copied().collect();
+    /// ids.sort();
+    /// assert_eq!(ids, (0..ids.len() as u32).collect::<Vec<_>>());
+    /// assert_eq!(seq.iter().collect::<String>(), "abcabdabcabd");
+    /// ```
+    pub fn compact(&mut self) {
+        let head = self.grammar.rule_index[&0];
+        let sequences = self.grammar.compact(&[head]);
+        self.sequence_end = sequences[0].1;
+    }
+}
+
+impl<T: Hash + Eq + Clone, DocId: Hash + Eq + Clone> SequiturDocuments<T, DocId> {
+    /// Rebuilds the shared grammar so it is laid out contiguously in memory.
+    ///
+    /// Works like [`Sequitur::compact`], with every document laid out before
+    /// the rules.
+    pub fn compact(&mut self) {
+        let heads: Vec<_> = self.documents.values().map(|info| info.head).collect();
+        let sequences = self.grammar.compact(&heads);
+        for (info, (head, tail)) in self.documents.values_mut().zip(sequences) {
+            info.head = head;
+            info.tail = tail;
+        }
+    }
+}
+
+impl<T: Hash + Eq + Clone> Grammar<T> {
+    /// Moves every symbol into fresh storage, the given sequences first and
+    /// then each rule body in order of its new ID, and carries the indexes
+    /// over to the new keys and IDs.
+    ///
+    /// Returns the new head and tail of each sequence.
+    fn compact(&mut self, sequences: &[DefaultKey]) -> Vec<(DefaultKey, DefaultKey)> {
+        let order = self.dense_rule_order(sequences);
+        let ids: HashMap<u32, u32> = order
+            .iter()
+            .enumerate()
+            .map(|(new_id, &rule_id)| (rule_id, new_id as u32))
+            .collect();
+
+        let capacity = self.symbols.len();
+        let mut old = mem::replace(&mut self.symbols, SlotMap::with_capacity(capacity));
+        let mut old_rules = mem::take(&mut self.rule_index);
+        self.rule_index.reserve(old_rules.len());
+        let mut keys = SecondaryMap::with_capacity(capacity);
+
+        let mut moved = Vec::with_capacity(sequences.len());
+        for &head in sequences {
+            if let Symbol::RuleHead { rule_id, .. } = old[head].symbol {
+                old_rules.remove(&rule_id);
+            }
+            moved.push(self.move_body(&mut old, head, &ids, &mut keys));
+        }
+        for rule_id in &order {
+            if let Some(head) = old_rules.remove(rule_id) {
+                self.move_body(&mut old, head, &ids, &mut keys);
+            }
+        }
+        self.id_gen = IdGenerator::starting_at(order.len() as u32);
+        self.remap_indexes(&ids, &keys);
+        moved
+    }
+
+    /// Rewrites the digram and relaxed-constraint indexes for renamed keys and
+    /// rules, keeping which occurrence of each digram is indexed so later
+    /// pushes build the same grammar as without compaction.
+    fn remap_indexes(
+        &mut self,
+        ids: &HashMap<u32, u32>,
+        keys: &SecondaryMap<DefaultKey, DefaultKey>,
+    ) {
+        // Indexed digrams are keyed by symbol hashes, which include rule IDs.
+        let indexed: Vec<_> = mem::take(&mut self.digram_index).into_values().collect();
+        for key in indexed {
+            if let Some(&key) = keys.get(key) {
+                if let Some(digram) = self.digram_at(key) {
+                    self.digram_index.insert(digram, key);
+                }
+            }
+        }
+
+        let pending: Vec<_> = mem::take(&mut self.pending_digrams).into_values().collect();
+        for stored in pending {
+            let stored: Vec<_> = stored
+                .iter()
+                .filter_map(|&key| keys.get(key).copied())
+                .collect();
+            if let Some(digram) = stored.first().and_then(|&key| self.digram_at(key)) {
+                self.pending_digrams.insert(digram, stored);
+            }
+        }
+
+        self.ref_locations = mem::take(&mut self.ref_locations)
+            .into_iter()
+            .filter_map(|(rule_id, locations)| {
+                let locations = locations.iter().filter_map(|&key| keys.get(key).copied());
+                Some((*ids.get(&rule_id)?, locations.collect()))
+            })
+            .collect();
+        self.expansion_lengths = mem::take(&mut self.expansion_lengths)
+            .into_iter()
+            .filter_map(|(rule_id, len)| Some((*ids.get(&rule_id)?, len)))
+            .collect();
+    }
+
+    /// Returns every rule ID in the order the rules are first reached by a
+    /// depth-first walk of the sequences, with any unreachable rules last.
+    fn dense_rule_order(&self, sequences: &[DefaultKey]) -> Vec<u32> {
+        let mut order = Vec::with_capacity(self.rule_index.len());
+        let mut seen = HashSet::default();
+
+        for &head in sequences {
+            if let Symbol::RuleHead { rule_id, .. } = self.symbols[head].symbol {
+                seen.insert(rule_id);
+                order.push(rule_id);
+            }
+            let mut stack = vec![self.body_keys(head)];
+            while let Some(keys) = stack.last_mut() {
+                let Some(key) = keys.next() else {
+                    stack.pop();
+                    continue;
+                };
+                if let Symbol::RuleRef { rule_id } = self.symbols[key].symbol {
+                    if seen.insert(rule_id) {
+                        order.push(rule_id);
+                        stack.push(self.body_keys(self.rule_index[&rule_id]));
+                    }
+                }
+            }
+        }
+
+        let mut unreachable: Vec<_> = self
+            .rule_index
+            .keys()
+            .copied()
+            .filter(|rule_id| !seen.contains(rule_id))
+            .collect();
+        unreachable.sort_unstable();
+        order.extend(unreachable);
+        order
+    }
+
+    /// Moves the body starting at `head` out of `old` into consecutive slots,
+    /// renaming rules by `ids` and recording new keys in `keys`, and returns
+    /// its new head and tail.
+    fn move_body(
+        &mut self,
+        old: &mut SlotMap<DefaultKey, SymbolNode<T>>,
+        head: DefaultKey,
+        ids: &HashMap<u32, u32>,
+        keys: &mut SecondaryMap<DefaultKey, DefaultKey>,
+    ) -> (DefaultKey, DefaultKey) {
+        let head_node = old.remove(head).expect("sequence heads should exist");
+        let new_head = self.symbols.insert(SymbolNode::new(head_node.symbol));
+        keys.insert(head, new_head);
+
+        let mut prev = new_head;
+        let mut current = head_node.next;
+        while let Some(key) = current {
+            let node = old.remove(key).expect("bodies should be linked");
+            current = node.next;
+            let end = matches!(node.symbol, Symbol::RuleTail | Symbol::DocTail);
+            let symbol = match node.symbol {
+                Symbol::RuleRef { rule_id } => Symbol::RuleRef {
+                    rule_id: ids[&rule_id],
+                },
+                symbol => symbol,
+            };
+            let new_key = self.symbols.insert(SymbolNode::new(symbol));
+            keys.insert(key, new_key);
+            self.symbols[new_key].prev = Some(prev);
+            self.symbols[prev].next = Some(new_key);
+            prev = new_key;
+            if end {
+                break;
+            }
+        }
+
+        match &mut self.symbols[new_head].symbol {
+            Symbol::RuleHead { rule_id, tail, .. } => {
+                *rule_id = ids[rule_id];
+                *tail = prev;
+                self.rule_index.insert(*rule_id, new_head);
+            }
+            Symbol::DocHead { tail } => *tail = prev,
+            _ => unreachable!("sequences should start with a RuleHead or DocHead"),
+        }
+        (new_head, prev)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dense(ids: impl Iterator<Item = u32>) -> bool {
+        let mut ids: Vec<_> = ids.collect();
+        ids.sort_unstable();
+        ids.iter().copied().eq(0..ids.len() as u32)
+    }
+
+    #[test]
+    fn test_compact_roundtrip() {
+        let input: Vec<u32> = (0..3000).map(|i| (i * 7 + i / 13) % 11).collect();
+        let mut seq = Sequitur::new();
+        seq.extend(input.iter().copied());
+        let num_rules = seq.rules().len();
+        let symbols = seq.grammar.symbols.len();
+
+        seq.compact();
+        assert!(seq.iter().eq(input.iter()));
+        assert_eq!(seq.rules().len(), num_rules);
+        assert_eq!(seq.grammar.symbols.len(), symbols);
+        assert!(dense(seq.rules().keys().copied()));
+        assert!(seq.try_iter().is_ok());
+    }
+
+    #[test]
+    fn test_compact_lays_out_rule_0_first() {
+        let mut seq = Sequitur::new();
+        seq.extend("abcabdabcabd".chars());
+        seq.compact();
+
+        // Slots are handed out in order from fresh storage, so walking Rule 0
+        // from its head visits the first slots one after another.
+        let mut keys: Vec<_> = seq.grammar.symbols.keys().collect();
+        keys.truncate(seq.grammar.body_keys(seq.rules()[&0]).count() + 2);
+        let walk: Vec<_> =
+            std::iter::successors(Some(seq.rules()[&0]), |&key| seq.grammar.symbols[key].next)
+                .collect();
+        assert_eq!(walk, keys);
+    }
+
+    #[test]
+    fn test_compact_resets_freed_ids() {
+        let mut seq = Sequitur::new();
+        seq.extend("abcdbcabcd".chars());
+        // Expanding rules frees their IDs.
+        assert!(!dense(seq.rules().keys().copied()));
+
+        seq.compact();
+        assert!(dense(seq.rules().keys().copied()));
+        let next = seq.grammar.id_gen.get();
+        assert_eq!(next as usize, seq.rules().len());
+    }
+
+    #[test]
+    fn test_push_after_compact() {
+        let input = "the cat sat on the mat, the cat sat on the hat";
+        let mut seq = Sequitur::new();
+        seq.extend(input[..20].chars());
+        let mut fresh = seq.clone();
+
+        seq.compact();
+        seq.extend(input[20..].chars());
+        fresh.extend(input[20..].chars());
+        assert_eq!(seq.iter().collect::<String>(), input);
+        assert_eq!(seq.stats().num_rules, fresh.stats().num_rules);
+        assert_eq!(seq.stats().grammar_symbols, fresh.stats().grammar_symbols);
+        assert!(seq.try_iter().is_ok());
+    }
+
+    #[test]
+    fn test_compact_relaxed() {
+        let input = "abcabcabcabcxyzxyzxyz".repeat(3);
+        let mut seq = Sequitur::builder().min_rule_uses(3).build();
+        seq.extend(input.chars());
+        seq.compact();
+        seq.extend("abcabc".chars());
+        assert_eq!(seq.iter().collect::<String>(), input + "abcabc");
+        assert!(seq.try_iter().is_ok());
+    }
+
+    #[test]
+    fn test_compact_documents() {
+        let mut docs = SequiturDocuments::new();
+        docs.extend_document(1, "hello world hello".chars());
+        docs.extend_document(2, "world hello world".chars());
+        docs.compact();
+
+        assert!(dense(docs.rules().keys().copied()));
+        docs.extend_document(2, " hello".chars());
+        docs.extend_document(3, "hello".chars());
+        let text = |id| docs.iter_document(&id).unwrap().collect::<String>();
+        assert_eq!(text(1), "hello world hello");
+        assert_eq!(text(2), "world hello world hello");
+        assert_eq!(text(3), "hello");
+        assert!(docs.try_iter_document(&2).unwrap().is_some());
+    }
+}
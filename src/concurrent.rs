@@ -0,0 +1,265 @@
+use crate::documents::SequiturDocuments;
+use crate::documents_iter::DocumentIter;
+use ahash::RandomState;
+use std::hash::Hash;
+use std::num::NonZeroUsize;
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::thread;
+
+/// Shards per available thread when the shard count isn't given.
+const SHARDS_PER_THREAD: usize = 4;
+
+/// A document collection that many threads can append to at once.
+///
+/// Documents are spread over shards by ID, and each shard is a
+/// [`SequiturDocuments`] behind its own lock, so writers to documents in
+/// different shards don't contend, and readers only wait for writers of the
+/// same shard. Rules are shared within a shard; [`consolidate`](Self::consolidate)
+/// merges the shards into a single grammar that shares rules across all
+/// documents.
+///
+/// # Example
+///
+/// ```
+/// use sequitur_rs::ConcurrentSequiturDocuments;
+/// use std::thread;
+///
+/// let docs = ConcurrentSequiturDocuments::new();
+/// thread::scope(|scope| {
+///     for host in 0..4 {
+///         let docs = &docs;
+///         scope.spawn(move || docs.extend_document(host, "GET /index ".repeat(10).chars()));
+///     }
+/// });
+///
+/// let log: String = docs.with_document(&2, |iter| iter.collect()).unwrap();
+/// assert_eq!(log, "GET /index ".repeat(10));
+///
+/// let merged = docs.consolidate();
+/// assert_eq!(merged.num_documents(), 4);
+/// ```
+pub struct ConcurrentSequiturDocuments<T, DocId> {
+    shards: Box<[RwLock<SequiturDocuments<T, DocId>>]>,
+    hasher: RandomState,
+}
+
+impl<T: Hash + Eq + Clone, DocId: Hash + Eq + Clone> ConcurrentSequiturDocuments<T, DocId> {
+    /// Creates an empty store with a few shards per available thread.
+    pub fn new() -> Self {
+        let threads = thread::available_parallelism().map_or(1, NonZeroUsize::get);
+        Self::with_shards(threads * SHARDS_PER_THREAD)
+    }
+
+    /// Creates an empty store with the given number of shards.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `shards` is zero.
+    pub fn with_shards(shards: usize) -> Self {
+        assert!(shards > 0, "at least one shard is required");
+        Self {
+            shards: (0..shards)
+                .map(|_| RwLock::new(SequiturDocuments::new()))
+                .collect(),
+            hasher: RandomState::new(),
+        }
+    }
+
+    /// Returns the number of shards.
+    pub fn num_shards(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// Adds a value to the specified document, creating it if needed.
+    pub fn push_to_document(&self, doc_id: DocId, value: T) {
+        self.write_shard(&doc_id).push_to_document(doc_id, value);
+    }
+
+    /// Extends the document with multiple values, holding the shard lock for the
+    /// whole batch.
+    pub fn extend_document<I: IntoIterator<Item = T>>(&self, doc_id: DocId, iter: I) {
+        self.write_shard(&doc_id).extend_document(doc_id, iter);
+    }
+
+    /// Returns the length of a document, or `None` if it doesn't exist.
+    pub fn document_len(&self, doc_id: &DocId) -> Option<usize> {
+        self.read_shard(doc_id).document_len(doc_id)
+    }
+
+    /// Returns the total number of documents.
+    pub fn num_documents(&self) -> usize {
+        (0..self.shards.len())
+            .map(|index| self.read_index(index).num_documents())
+            .sum()
+    }
+
+    /// Calls `f` with an iterator over a document, or returns `None` if the
+    /// document doesn't exist.
+    ///
+    /// Writers to the same shard wait until `f` returns.
+    pub fn with_document<R, F>(&self, doc_id: &DocId, f: F) -> Option<R>
+    where
+        F: FnOnce(DocumentIter<'_, T, DocId>) -> R,
+    {
+        self.read_shard(doc_id).iter_document(doc_id).map(f)
+    }
+
+    /// Returns a copy of a document's values, or `None` if it doesn't exist.
+    pub fn document(&self, doc_id: &DocId) -> Option<Vec<T>> {
+        self.with_document(doc_id, |iter| iter.cloned().collect())
+    }
+
+    /// Merges a snapshot of every shard into a single collection.
+    ///
+    /// Each shard is only locked while it is copied, so writers can continue;
+    /// documents written concurrently appear in the state they had when their
+    /// shard was copied.
+    pub fn consolidate(&self) -> SequiturDocuments<T, DocId> {
+        let snapshots = (0..self.shards.len()).map(|index| self.read_index(index).clone());
+        Self::merge_shards(snapshots)
+    }
+
+    /// Merges every shard into a single collection, consuming the store.
+    pub fn into_documents(self) -> SequiturDocuments<T, DocId> {
+        let shards = self.shards.into_vec().into_iter().map(|shard| {
+            shard
+                .into_inner()
+                .expect("shard lock poisoned by a panicking writer")
+        });
+        Self::merge_shards(shards)
+    }
+
+    fn merge_shards<I>(shards: I) -> SequiturDocuments<T, DocId>
+    where
+        I: Iterator<Item = SequiturDocuments<T, DocId>>,
+    {
+        // Each document lives in a single shard.
+        SequiturDocuments::merge_disjoint(shards)
+    }
+
+    fn shard_index(&self, doc_id: &DocId) -> usize {
+        (self.hasher.hash_one(doc_id) % self.shards.len() as u64) as usize
+    }
+
+    fn read_shard(&self, doc_id: &DocId) -> RwLockReadGuard<'_, SequiturDocuments<T, DocId>> {
+        self.read_index(self.shard_index(doc_id))
+    }
+
+    fn read_index(&self, index: usize) -> RwLockReadGuard<'_, SequiturDocuments<T, DocId>> {
+        self.shards[index]
+            .read()
+            .expect("shard lock poisoned by a panicking writer")
+    }
+
+    fn write_shard(&self, doc_id: &DocId) -> RwLockWriteGuard<'_, SequiturDocuments<T, DocId>> {
+        self.shards[self.shard_index(doc_id)]
+            .write()
+            .expect("shard lock poisoned by a panicking writer")
+    }
+}
+
+impl<T: Hash + Eq + Clone, DocId: Hash + Eq + Clone> Default
+    for ConcurrentSequiturDocuments<T, DocId>
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+
+    fn assert_send_sync<X: Send + Sync>() {}
+
+    #[test]
+    fn test_types_are_send_and_sync() {
+        assert_send_sync::<Sequitur<u8>>();
+        assert_send_sync::<SequiturIter<'_, u8>>();
+        assert_send_sync::<SequiturDocuments<u8, String>>();
+        assert_send_sync::<DocumentIter<'_, u8, String>>();
+        assert_send_sync::<SequiturRle<u8>>();
+        assert_send_sync::<RleSequiturIter<'_, u8>>();
+        assert_send_sync::<RleRunIter<'_, u8>>();
+        assert_send_sync::<SequiturDocumentsRle<u8, String>>();
+        assert_send_sync::<RleDocumentIter<'_, u8, String>>();
+        assert_send_sync::<RleDocumentRunIter<'_, u8, String>>();
+        assert_send_sync::<InternedSequitur<String>>();
+        assert_send_sync::<InternedIter<'_, String>>();
+        assert_send_sync::<TextSequitur>();
+        assert_send_sync::<SaxGrammar>();
+        assert_send_sync::<SequiturBuilder<u8>>();
+        assert_send_sync::<ConcurrentSequiturDocuments<u8, String>>();
+        assert_send_sync::<FrozenGrammar<u8>>();
+        assert_send_sync::<FrozenIter<u8>>();
+    }
+
+    #[test]
+    fn test_concurrent_writers() {
+        let docs = ConcurrentSequiturDocuments::with_shards(3);
+        let text = |doc: usize| format!("doc {doc}: abcabc xyz ").repeat(20);
+
+        thread::scope(|scope| {
+            for doc in 0..8 {
+                let docs = &docs;
+                scope.spawn(move || {
+                    for c in text(doc).chars() {
+                        docs.push_to_document(doc, c);
+                    }
+                });
+            }
+            // A reader running alongside the writers sees consistent prefixes.
+            scope.spawn(|| {
+                for _ in 0..50 {
+                    if let Some(partial) = docs.with_document(&0, |iter| iter.collect::<String>()) {
+                        assert!(text(0).starts_with(&partial));
+                    }
+                }
+            });
+        });
+
+        assert_eq!(docs.num_documents(), 8);
+        for doc in 0..8 {
+            let expected = text(doc);
+            assert_eq!(docs.document_len(&doc), Some(expected.len()));
+            assert_eq!(
+                docs.document(&doc).unwrap(),
+                expected.chars().collect::<Vec<_>>()
+            );
+        }
+    }
+
+    #[test]
+    fn test_consolidate() {
+        let docs = ConcurrentSequiturDocuments::with_shards(4);
+        for doc in 0..10 {
+            docs.extend_document(doc, "shared prefix, then ".chars());
+            docs.extend_document(doc, format!("tail {doc}").chars());
+        }
+
+        let merged = docs.consolidate();
+        assert_eq!(merged.num_documents(), 10);
+        for doc in 0..10 {
+            let text: String = merged.iter_document(&doc).unwrap().collect();
+            assert_eq!(text, format!("shared prefix, then tail {doc}"));
+        }
+        assert!(merged.try_iter_document(&0).is_ok());
+
+        // The store keeps working after a snapshot.
+        docs.push_to_document(0, '!');
+        assert_eq!(docs.document_len(&0), Some(27));
+
+        let owned = docs.into_documents();
+        assert_eq!(owned.document_len(&0), Some(27));
+        assert_eq!(owned.num_documents(), 10);
+    }
+
+    #[test]
+    fn test_missing_document() {
+        let docs = ConcurrentSequiturDocuments::<char, u32>::with_shards(2);
+        assert!(docs.document(&1).is_none());
+        assert!(docs.document_len(&1).is_none());
+        assert_eq!(docs.num_documents(), 0);
+    }
+}
@@ -0,0 +1,226 @@
+use crate::documents::SequiturDocuments;
+use crate::grammar::Grammar;
+use crate::symbol::Symbol;
+use ahash::AHashMap as HashMap;
+use slotmap::DefaultKey;
+use std::cmp::Reverse;
+use std::hash::Hash;
+
+/// Content shared between documents, as returned by
+/// [`SequiturDocuments::dedup_report`].
+///
+/// Lengths count values, which are bytes for `u8` documents.
+#[derive(Debug, Clone)]
+pub struct DedupReport<DocId> {
+    /// Rules used by two or more documents, largest savings first
+    pub shared_rules: Vec<SharedRule<DocId>>,
+    /// Values of each document not covered by any shared rule
+    pub unique: Vec<(DocId, usize)>,
+    /// Values stored once instead of in every document that uses them
+    ///
+    /// Only the outermost shared rules of each document count, so content
+    /// nested in several shared rules is counted once.
+    pub total_saved: usize,
+}
+
+/// A rule used by several documents.
+#[derive(Debug, Clone)]
+pub struct SharedRule<DocId> {
+    /// ID of the rule
+    pub rule_id: u32,
+    /// Number of values the rule expands to
+    pub expanded_len: usize,
+    /// Each document using the rule, with its number of occurrences in the
+    /// document's expansion, most occurrences first
+    pub documents: Vec<(DocId, usize)>,
+    /// Values the documents after the first would otherwise store again,
+    /// `expanded_len * (documents.len() - 1)`; overlaps with the savings of
+    /// shared rules nested in this one
+    pub saved: usize,
+}
+
+impl<T: Hash + Eq + Clone, DocId: Hash + Eq + Clone> SequiturDocuments<T, DocId> {
+    /// Reports which rules are shared between documents and how much the
+    /// sharing saves.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use sequitur_rs::SequiturDocuments;
+    ///
+    /// let mut docs = SequiturDocuments::new();
+    /// docs.extend_document("v1", "header-payload-one".bytes());
+    /// docs.extend_document("v2", "header-payload-two".bytes());
+    ///
+    /// let report = docs.dedup_report();
+    /// let largest = &report.shared_rules[0];
+    /// assert_eq!(largest.expanded_len, "header-payload-".len());
+    /// assert_eq!(report.total_saved, "header-payload-".len());
+    /// ```
+    pub fn dedup_report(&self) -> DedupReport<DocId> {
+        let lengths = self.grammar.rule_lengths();
+
+        // Occurrences of every rule in the full expansion of each document.
+        let mut users: HashMap<u32, Vec<(DocId, usize)>> = HashMap::default();
+        for (doc_id, info) in &self.documents {
+            let (counts, _) = self.grammar.expansion_counts(info.head, |_| true);
+            for (rule_id, count) in counts {
+                users
+                    .entry(rule_id)
+                    .or_default()
+                    .push((doc_id.clone(), count));
+            }
+        }
+        users.retain(|_, documents| documents.len() >= 2);
+
+        // Stop at shared rules to find the outermost ones and the values
+        // outside all of them.
+        let mut outermost: HashMap<u32, usize> = HashMap::default();
+        let mut unique = Vec::with_capacity(self.documents.len());
+        for (doc_id, info) in &self.documents {
+            let (counts, values) = self
+                .grammar
+                .expansion_counts(info.head, |rule_id| !users.contains_key(&rule_id));
+            for rule_id in counts.into_keys().filter(|id| users.contains_key(id)) {
+                *outermost.entry(rule_id).or_default() += 1;
+            }
+            unique.push((doc_id.clone(), values));
+        }
+        let total_saved = outermost
+            .iter()
+            .map(|(rule_id, &documents)| lengths[rule_id] * (documents - 1))
+            .sum();
+
+        let mut shared_rules: Vec<_> = users
+            .into_iter()
+            .map(|(rule_id, mut documents)| {
+                documents.sort_by_key(|&(_, count)| Reverse(count));
+                let expanded_len = lengths[&rule_id];
+                SharedRule {
+                    rule_id,
+                    expanded_len,
+                    saved: expanded_len * (documents.len() - 1),
+                    documents,
+                }
+            })
+            .collect();
+        shared_rules.sort_by(|a, b| b.saved.cmp(&a.saved).then(a.rule_id.cmp(&b.rule_id)));
+
+        DedupReport {
+            shared_rules,
+            unique,
+            total_saved,
+        }
+    }
+}
+
+impl<T: Hash + Eq + Clone> Grammar<T> {
+    /// Counts the occurrences of each rule in the expansion of the sequence at
+    /// `head`, and the values reached, only expanding rules for which
+    /// `expand` holds.
+    pub(crate) fn expansion_counts(
+        &self,
+        head: DefaultKey,
+        expand: impl Fn(u32) -> bool,
+    ) -> (HashMap<u32, usize>, usize) {
+        let mut counts: HashMap<u32, usize> = HashMap::default();
+        let mut values = 0;
+        let mut count_body = |head: DefaultKey, times: usize, counts: &mut HashMap<u32, usize>| {
+            for key in self.body_keys(head) {
+                match self.symbols[key].symbol {
+                    Symbol::Value(_) => values += times,
+                    Symbol::RuleRef { rule_id } => *counts.entry(rule_id).or_default() += times,
+                    _ => {}
+                }
+            }
+        };
+
+        count_body(head, 1, &mut counts);
+        // Parents first, so a rule's count is final before it is expanded.
+        for rule_id in self.rules_children_first(&[head]).into_iter().rev() {
+            let times = counts.get(&rule_id).copied().unwrap_or(0);
+            if times > 0 && expand(rule_id) {
+                count_body(self.rule_index[&rule_id], times, &mut counts);
+            }
+        }
+        (counts, values)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dedup_report() {
+        let mut docs = SequiturDocuments::new();
+        docs.extend_document(1, b"common-prefix/alpha/common-prefix".iter().copied());
+        docs.extend_document(2, b"common-prefix/beta".iter().copied());
+        docs.extend_document(3, b"UNRELATED".iter().copied());
+
+        let report = docs.dedup_report();
+        let prefix = report
+            .shared_rules
+            .iter()
+            .find(|rule| rule.expanded_len == b"common-prefix/".len())
+            .unwrap();
+        let mut users = prefix.documents.clone();
+        users.sort_unstable();
+        assert_eq!(users, [(1, 1), (2, 1)]);
+        assert_eq!(prefix.saved, prefix.expanded_len);
+
+        for rule in &report.shared_rules {
+            assert!(rule.documents.len() >= 2);
+            assert!(rule.documents.iter().all(|&(doc, _)| doc != 3));
+        }
+        let unique: HashMap<_, _> = report.unique.iter().copied().collect();
+        assert_eq!(unique[&3], 9);
+        assert_eq!(unique[&2], "beta".len());
+        assert!(report.total_saved >= b"common-prefix/".len());
+    }
+
+    #[test]
+    fn test_nested_shared_rules_counted_once() {
+        let block = "0123456789".repeat(4);
+        let mut docs = SequiturDocuments::new();
+        for doc in 0..3 {
+            docs.extend_document(doc, format!("{block}|{doc}").into_bytes());
+        }
+
+        let report = docs.dedup_report();
+        // The whole block is shared by three documents; the rules inside it
+        // are too, but don't add to the total.
+        assert!(report.shared_rules.len() > 1);
+        assert_eq!(report.total_saved, 2 * (block.len() + 1));
+        assert_eq!(report.shared_rules[0].saved, report.total_saved);
+        for (_, unique) in report.unique {
+            assert_eq!(unique, 1);
+        }
+    }
+
+    #[test]
+    fn test_occurrences_within_documents() {
+        let mut docs = SequiturDocuments::new();
+        docs.extend_document("a", "xyzxyzxyz".chars());
+        docs.extend_document("b", "xyz".chars());
+
+        let report = docs.dedup_report();
+        let xyz = report
+            .shared_rules
+            .iter()
+            .find(|rule| rule.expanded_len == 3)
+            .unwrap();
+        assert_eq!(xyz.documents, [("a", 3), ("b", 1)]);
+        assert_eq!(report.total_saved, 3);
+    }
+
+    #[test]
+    fn test_no_sharing() {
+        let mut docs = SequiturDocuments::new();
+        docs.extend_document(1, "abab".chars());
+        docs.extend_document(2, "cdcd".chars());
+        let report = docs.dedup_report();
+        assert!(report.shared_rules.is_empty());
+        assert_eq!(report.total_saved, 0);
+    }
+}
@@ -0,0 +1,239 @@
+use crate::documents::SequiturDocuments;
+use crate::occurrence::RuleOccurrence;
+use crate::sequitur::Sequitur;
+use crate::timeseries::{RuleInterval, SaxGrammar};
+use std::hash::Hash;
+
+/// A maximal range of positions sharing the same rule density.
+///
+/// Low-density ranges are covered by few or no repeated rules, which makes
+/// them candidates for anomalies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DensityInterval {
+    /// First position of the range
+    pub start: usize,
+    /// Position one past the end of the range
+    pub end: usize,
+    /// Number of rule occurrences covering each position of the range
+    pub density: usize,
+}
+
+impl DensityInterval {
+    /// Returns the number of positions in the range.
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    /// Returns true if the range is empty.
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+}
+
+impl<T: Hash + Eq + Clone> Sequitur<T> {
+    /// Returns, for each position of the expanded sequence, the number of rule
+    /// occurrences (at any nesting depth) covering it.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use sequitur_rs::Sequitur;
+    ///
+    /// let mut seq = Sequitur::new();
+    /// seq.extend("abcabcXabc".chars());
+    ///
+    /// let density = seq.rule_density();
+    /// assert_eq!(density.len(), 10);
+    /// assert_eq!(density[6], 0); // 'X' is not part of any rule
+    /// ```
+    pub fn rule_density(&self) -> Vec<usize> {
+        density_from_occurrences(self.len(), &self.rule_occurrences())
+    }
+
+    /// Returns the maximal ranges of the expanded sequence whose rule density is
+    /// the minimum over the whole sequence, ordered by start.
+    pub fn min_density_intervals(&self) -> Vec<DensityInterval> {
+        min_density_intervals(&self.rule_density())
+    }
+}
+
+impl<T: Hash + Eq + Clone, DocId: Hash + Eq + Clone> SequiturDocuments<T, DocId> {
+    /// Returns the rule density of each position of a document.
+    ///
+    /// Returns `None` if the document doesn't exist.
+    pub fn document_rule_density(&self, doc_id: &DocId) -> Option<Vec<usize>> {
+        let len = self.document_len(doc_id)?;
+        let occurrences = self.document_rule_occurrences(doc_id)?;
+        Some(density_from_occurrences(len, &occurrences))
+    }
+
+    /// Returns the maximal ranges of a document whose rule density is the
+    /// minimum over the document, ordered by start.
+    ///
+    /// Returns `None` if the document doesn't exist.
+    pub fn document_min_density_intervals(&self, doc_id: &DocId) -> Option<Vec<DensityInterval>> {
+        Some(min_density_intervals(&self.document_rule_density(doc_id)?))
+    }
+}
+
+impl SaxGrammar {
+    /// Returns, for each sample of the original series, the number of rule
+    /// intervals covering it.
+    pub fn rule_density(&self) -> Vec<usize> {
+        let mut delta = vec![0isize; self.series_len() + 1];
+        for &RuleInterval { start, end, .. } in &self.rule_intervals() {
+            delta[start] += 1;
+            delta[end] -= 1;
+        }
+        prefix_counts(&delta)
+    }
+
+    /// Returns the maximal ranges of the series whose rule density is the
+    /// minimum over the series, ordered by start.
+    pub fn min_density_intervals(&self) -> Vec<DensityInterval> {
+        min_density_intervals(&self.rule_density())
+    }
+}
+
+/// Sweeps occurrence boundaries to count the occurrences covering each position.
+fn density_from_occurrences(len: usize, occurrences: &[RuleOccurrence]) -> Vec<usize> {
+    let mut delta = vec![0isize; len + 1];
+    for occurrence in occurrences {
+        delta[occurrence.start] += 1;
+        delta[occurrence.end()] -= 1;
+    }
+    prefix_counts(&delta)
+}
+
+/// Turns a boundary delta array (one longer than the sequence) into counts.
+fn prefix_counts(delta: &[isize]) -> Vec<usize> {
+    let mut current = 0isize;
+    delta[..delta.len() - 1]
+        .iter()
+        .map(|&d| {
+            current += d;
+            current as usize
+        })
+        .collect()
+}
+
+/// Returns the maximal runs of positions at the minimum density.
+fn min_density_intervals(density: &[usize]) -> Vec<DensityInterval> {
+    let Some(&min) = density.iter().min() else {
+        return Vec::new();
+    };
+
+    let mut intervals = Vec::new();
+    let mut start = None;
+    for (i, &d) in density.iter().enumerate() {
+        match (d == min, start) {
+            (true, None) => start = Some(i),
+            (false, Some(s)) => {
+                intervals.push(DensityInterval {
+                    start: s,
+                    end: i,
+                    density: min,
+                });
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(s) = start {
+        intervals.push(DensityInterval {
+            start: s,
+            end: density.len(),
+            density: min,
+        });
+    }
+    intervals
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::timeseries::SaxConfig;
+
+    #[test]
+    fn test_density_flags_unique_segment() {
+        let mut input = "abcdefgh".repeat(4);
+        input.insert_str(16, "ZQ");
+        let mut seq = Sequitur::new();
+        seq.extend(input.chars());
+
+        let density = seq.rule_density();
+        assert_eq!(density.len(), input.len());
+        assert_eq!(density[16], 0);
+        assert_eq!(density[17], 0);
+
+        let intervals = seq.min_density_intervals();
+        assert_eq!(intervals.len(), 1);
+        assert_eq!(intervals[0].start, 16);
+        assert_eq!(intervals[0].end, 18);
+        assert_eq!(intervals[0].density, 0);
+    }
+
+    #[test]
+    fn test_density_matches_occurrences() {
+        let input: Vec<u32> = (0..500).map(|i| (i * i) % 13).collect();
+        let mut seq = Sequitur::new();
+        seq.extend(input.iter().copied());
+
+        let occurrences = seq.rule_occurrences();
+        let density = seq.rule_density();
+        for (i, &d) in density.iter().enumerate() {
+            let covering = occurrences
+                .iter()
+                .filter(|o| o.start <= i && i < o.end())
+                .count();
+            assert_eq!(d, covering);
+        }
+    }
+
+    #[test]
+    fn test_empty_sequence() {
+        let seq = Sequitur::<char>::new();
+        assert!(seq.rule_density().is_empty());
+        assert!(seq.min_density_intervals().is_empty());
+    }
+
+    #[test]
+    fn test_document_density() {
+        let mut docs = SequiturDocuments::new();
+        docs.extend_document("normal", "GET /a GET /a GET /a".chars());
+        docs.extend_document("odd", "GET /a PANIC GET /a".chars());
+
+        let density = docs.document_rule_density(&"odd").unwrap();
+        assert_eq!(density.len(), 19);
+
+        let intervals = docs.document_min_density_intervals(&"odd").unwrap();
+        assert!(intervals
+            .iter()
+            .any(|interval| interval.start <= 8 && 8 < interval.end));
+
+        assert!(docs.document_rule_density(&"missing").is_none());
+    }
+
+    #[test]
+    fn test_series_density() {
+        let mut series: Vec<f64> = (0..300).map(|i| ((i as f64) * 0.25).sin()).collect();
+        // A spike breaks the periodic pattern.
+        for x in &mut series[150..156] {
+            *x += 5.0;
+        }
+
+        let grammar = SaxGrammar::new(
+            &series,
+            SaxConfig {
+                window: 12,
+                ..SaxConfig::default()
+            },
+        );
+        let density = grammar.rule_density();
+        assert_eq!(density.len(), series.len());
+
+        let min = *density.iter().min().unwrap();
+        let normal_max = *density[..100].iter().max().unwrap();
+        assert!(min < normal_max);
+    }
+}
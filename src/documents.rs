@@ -1,6 +1,8 @@
+use crate::builder::GrammarConfig;
+use crate::error::SequiturError;
 use crate::grammar::Grammar;
 use crate::symbol::{Symbol, SymbolNode};
-use ahash::AHashMap as HashMap;
+use ahash::{AHashMap as HashMap, AHashSet as HashSet};
 use slotmap::DefaultKey;
 use std::hash::Hash;
 
@@ -46,6 +48,7 @@ pub(crate) struct DocumentInfo {
 /// let text1: String = docs.iter_document(&"article1".to_string()).unwrap().collect();
 /// let text2: String = docs.iter_document(&"article2".to_string()).unwrap().collect();
 /// ```
+#[derive(Clone)]
 pub struct SequiturDocuments<T, DocId> {
     /// Core grammar storage (shared implementation with Sequitur)
     pub(crate) grammar: Grammar<T>,
@@ -60,8 +63,13 @@ impl<T: Hash + Eq + Clone, DocId: Hash + Eq + Clone> SequiturDocuments<T, DocId>
     /// No documents or rules exist initially. The grammar is built incrementally
     /// as documents are added.
     pub fn new() -> Self {
+        Self::with_config(GrammarConfig::default())
+    }
+
+    /// Creates a new empty SequiturDocuments instance with the given constraints.
+    pub(crate) fn with_config(config: GrammarConfig) -> Self {
         Self {
-            grammar: Grammar::new(),
+            grammar: Grammar::with_config(config),
             documents: HashMap::default(),
         }
     }
@@ -138,6 +146,32 @@ impl<T: Hash + Eq + Clone, DocId: Hash + Eq + Clone> SequiturDocuments<T, DocId>
         }
     }
 
+    /// Adds a value to the specified document, or returns an error instead of
+    /// running out of rule IDs or overflowing the document's length.
+    ///
+    /// The collection is unchanged when an error is returned.
+    pub fn try_push_to_document(&mut self, doc_id: DocId, value: T) -> Result<(), SequiturError> {
+        let length = self.documents.get(&doc_id).map_or(0, |info| info.length);
+        self.grammar.check_capacity(length, 1)?;
+        self.push_to_document(doc_id, value);
+        Ok(())
+    }
+
+    /// Extends the document with multiple values, stopping at the first value
+    /// that [`try_push_to_document`](Self::try_push_to_document) rejects.
+    ///
+    /// Values before the rejected one remain in the document.
+    pub fn try_extend_document<I: IntoIterator<Item = T>>(
+        &mut self,
+        doc_id: DocId,
+        iter: I,
+    ) -> Result<(), SequiturError> {
+        for value in iter {
+            self.try_push_to_document(doc_id.clone(), value)?;
+        }
+        Ok(())
+    }
+
     /// Returns the number of values in a document.
     ///
     /// Returns `None` if the document doesn't exist.
@@ -152,6 +186,39 @@ impl<T: Hash + Eq + Clone, DocId: Hash + Eq + Clone> SequiturDocuments<T, DocId>
         self.documents.get(doc_id).map(|info| info.length == 0)
     }
 
+    /// Deletes a document, returning its length, or `None` if it doesn't exist.
+    ///
+    /// Rules only the document used are deleted, and rules left with a single
+    /// use are inlined. This re-checks the whole shared grammar, so it takes
+    /// time proportional to the grammar rather than to the document.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use sequitur_rs::SequiturDocuments;
+    ///
+    /// let mut docs = SequiturDocuments::new();
+    /// docs.extend_document(1, "abcabc".chars());
+    /// docs.extend_document(2, "xyzxyz".chars());
+    ///
+    /// assert_eq!(docs.remove_document(&2), Some(6));
+    /// assert_eq!(docs.remove_document(&2), None);
+    /// assert_eq!(docs.num_documents(), 1);
+    /// ```
+    pub fn remove_document(&mut self, doc_id: &DocId) -> Option<usize> {
+        let length = self.detach_document(doc_id)?;
+        self.restore_constraints();
+        Some(length)
+    }
+
+    /// Deletes a document and the rules only it used, leaving rules with too
+    /// few uses for [`restore_constraints`](Self::restore_constraints).
+    pub(crate) fn detach_document(&mut self, doc_id: &DocId) -> Option<usize> {
+        let info = self.documents.remove(doc_id)?;
+        self.grammar.remove_sequence(info.head);
+        Some(info.length)
+    }
+
     /// Returns an iterator over all document IDs.
     pub fn document_ids(&self) -> impl Iterator<Item = &DocId> {
         self.documents.keys()
@@ -169,27 +236,127 @@ impl<T: Hash + Eq + Clone, DocId: Hash + Eq + Clone> SequiturDocuments<T, DocId>
 
     /// Returns compression statistics for a specific document.
     ///
+    /// Besides the document's own symbols, the document is charged for the
+    /// symbols of the rules it uses: in full for rules no other document
+    /// reaches, and an even share for rules that several documents reach.
+    /// Other documents are only followed into rules that lead to the ones this
+    /// document uses, but telling which rules those are visits every rule
+    /// once; [`all_document_stats`](Self::all_document_stats) is cheaper than
+    /// calling this for every document.
+    ///
     /// Returns `None` if the document doesn't exist.
     pub fn document_stats(&self, doc_id: &DocId) -> Option<DocumentStats> {
         let doc_info = self.documents.get(doc_id)?;
+        let rules = self.grammar.rules_children_first(&[doc_info.head]);
+        Some(self.cost_of(doc_info, &self.document_users(&rules)))
+    }
 
-        // Count symbols in this document's sequence
-        let mut doc_symbols = 0;
-        let mut current = self.grammar.symbols[doc_info.head].next;
-        while let Some(key) = current {
-            match &self.grammar.symbols[key].symbol {
-                Symbol::DocTail => break,
-                _ => {
-                    doc_symbols += 1;
-                    current = self.grammar.symbols[key].next;
+    /// Returns the statistics of every document, as
+    /// [`document_stats`](Self::document_stats) computes them.
+    ///
+    /// The costs add up to the grammar: summed over all documents,
+    /// [`DocumentStats::total_symbols`] equals
+    /// [`OverallStats::total_grammar_symbols`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use sequitur_rs::SequiturDocuments;
+    ///
+    /// let mut docs = SequiturDocuments::new();
+    /// docs.extend_document("a", "shared text, then a's own part".chars());
+    /// docs.extend_document("b", "shared text, then b".chars());
+    ///
+    /// let total: f64 = docs
+    ///     .all_document_stats()
+    ///     .iter()
+    ///     .map(|(_, stats)| stats.total_symbols())
+    ///     .sum();
+    /// assert_eq!(total.round() as usize, docs.overall_stats().total_grammar_symbols);
+    /// ```
+    pub fn all_document_stats(&self) -> Vec<(DocId, DocumentStats)> {
+        let users = self.rule_users();
+        self.documents
+            .iter()
+            .map(|(doc_id, info)| (doc_id.clone(), self.cost_of(info, &users)))
+            .collect()
+    }
+
+    /// Counts the documents that reach each rule, directly or through other
+    /// rules.
+    fn rule_users(&self) -> HashMap<u32, usize> {
+        let mut users: HashMap<u32, usize> = HashMap::default();
+        for info in self.documents.values() {
+            for rule_id in self.grammar.rules_children_first(&[info.head]) {
+                *users.entry(rule_id).or_default() += 1;
+            }
+        }
+        users
+    }
+
+    /// Counts the documents that reach each of `rules`, directly or through
+    /// other rules.
+    fn document_users(&self, rules: &[u32]) -> HashMap<u32, usize> {
+        let mut users: HashMap<u32, usize> = rules.iter().map(|&rule_id| (rule_id, 0)).collect();
+        if users.is_empty() {
+            return users;
+        }
+        let heads: Vec<_> = self.documents.values().map(|info| info.head).collect();
+
+        // Whether each rule is one of `rules` or leads to one
+        let mut leads: HashMap<u32, bool> = HashMap::default();
+        for rule_id in self.grammar.rules_children_first(&heads) {
+            let head = self.grammar.rule_index[&rule_id];
+            let lead =
+                users.contains_key(&rule_id) || self.child_rules(head).any(|child| leads[&child]);
+            leads.insert(rule_id, lead);
+        }
+
+        for head in heads {
+            let mut visited = HashSet::default();
+            let mut stack = vec![head];
+            while let Some(head) = stack.pop() {
+                for child in self.child_rules(head) {
+                    if leads[&child] && visited.insert(child) {
+                        stack.push(self.grammar.rule_index[&child]);
+                        if let Some(count) = users.get_mut(&child) {
+                            *count += 1;
+                        }
+                    }
                 }
             }
         }
+        users
+    }
+
+    /// Lists the rules referenced from the body of `head`.
+    fn child_rules(&self, head: DefaultKey) -> impl Iterator<Item = u32> + '_ {
+        self.grammar
+            .body_keys(head)
+            .filter_map(|key| match self.grammar.symbols[key].symbol {
+                Symbol::RuleRef { rule_id } => Some(rule_id),
+                _ => None,
+            })
+    }
 
-        Some(DocumentStats {
+    fn cost_of(&self, doc_info: &DocumentInfo, users: &HashMap<u32, usize>) -> DocumentStats {
+        let mut stats = DocumentStats {
             input_length: doc_info.length,
-            document_symbols: doc_symbols,
-        })
+            document_symbols: self.grammar.body_keys(doc_info.head).count(),
+            exclusive_symbols: 0,
+            shared_symbols: 0.0,
+        };
+        for rule_id in self.grammar.rules_children_first(&[doc_info.head]) {
+            let symbols = self
+                .grammar
+                .body_keys(self.grammar.rule_index[&rule_id])
+                .count();
+            match users[&rule_id] {
+                1 => stats.exclusive_symbols += symbols,
+                n => stats.shared_symbols += symbols as f64 / n as f64,
+            }
+        }
+        stats
     }
 
     /// Returns overall compression statistics across all documents.
@@ -235,7 +402,7 @@ impl<T: Hash + Eq + Clone, DocId: Hash + Eq + Clone> SequiturDocuments<T, DocId>
     }
 
     /// Creates a new empty document.
-    fn create_document(&mut self, doc_id: DocId) {
+    pub(crate) fn create_document(&mut self, doc_id: DocId) {
         // Create DocTail first
         let tail_key = self
             .grammar
@@ -270,9 +437,30 @@ pub struct DocumentStats {
     pub input_length: usize,
     /// Number of symbols in this document's sequence (including rule references)
     pub document_symbols: usize,
+    /// Symbols in rules that only this document uses, directly or through
+    /// other rules
+    pub exclusive_symbols: usize,
+    /// This document's share of the symbols in rules it uses along with other
+    /// documents, each rule split evenly between the documents using it
+    pub shared_symbols: f64,
 }
 
 impl DocumentStats {
+    /// Returns the grammar symbols attributed to this document: its sequence,
+    /// its exclusive rules and its share of the shared ones.
+    pub fn total_symbols(&self) -> f64 {
+        (self.document_symbols + self.exclusive_symbols) as f64 + self.shared_symbols
+    }
+
+    /// Returns the attributed grammar symbols as a percentage of the input.
+    pub fn amortized_compression_ratio(&self) -> f64 {
+        if self.input_length == 0 {
+            0.0
+        } else {
+            self.total_symbols() / self.input_length as f64 * 100.0
+        }
+    }
+
     /// Returns the document-level compression ratio as a percentage.
     pub fn compression_ratio(&self) -> f64 {
         if self.input_length == 0 {
@@ -372,4 +560,58 @@ mod tests {
 
         assert_eq!(docs.document_len(&1), Some(3));
     }
+
+    #[test]
+    fn test_remove_document() {
+        let mut docs = SequiturDocuments::new();
+        docs.extend_document(1, "the cat sat on the mat".chars());
+        docs.extend_document(2, "the cat sat on the hat".chars());
+        docs.extend_document(3, "a dog".chars());
+        let shared = docs.rules().len();
+
+        assert_eq!(docs.remove_document(&2), Some(22));
+        assert_eq!(docs.document_len(&2), None);
+        assert!(docs.rules().len() < shared);
+        assert_eq!(
+            docs.iter_document(&1).unwrap().collect::<String>(),
+            "the cat sat on the mat"
+        );
+        assert!(docs.try_iter_document(&1).unwrap().is_some());
+
+        docs.extend_document(2, "the cat sat".chars());
+        assert_eq!(
+            docs.iter_document(&2).unwrap().collect::<String>(),
+            "the cat sat"
+        );
+        assert_eq!(docs.remove_document(&4), None);
+    }
+
+    #[test]
+    fn test_document_cost_attribution() {
+        let mut docs = SequiturDocuments::new();
+        docs.extend_document(1, "the cat sat on the mat".chars());
+        docs.extend_document(2, "the cat sat on the mat, twice: xyzxyz".chars());
+        docs.extend_document(3, "0101".chars());
+
+        let all = docs.all_document_stats();
+        let total: f64 = all.iter().map(|(_, stats)| stats.total_symbols()).sum();
+        let overall = docs.overall_stats().total_grammar_symbols as f64;
+        assert!((total - overall).abs() < 1e-9);
+
+        let stats: HashMap<_, _> = all.into_iter().collect();
+        // Only document 2 uses the rule for "xyz"; documents 1 and 2 share
+        // rules for the sentence.
+        assert!(stats[&2].exclusive_symbols >= 3);
+        assert!(stats[&1].shared_symbols > 0.0);
+        assert!((stats[&1].shared_symbols - stats[&2].shared_symbols).abs() < 1e-9);
+        assert_eq!(stats[&3].exclusive_symbols, 2);
+        assert_eq!(stats[&3].shared_symbols, 0.0);
+
+        for doc_id in 1..=3 {
+            let single = docs.document_stats(&doc_id).unwrap();
+            assert_eq!(single.exclusive_symbols, stats[&doc_id].exclusive_symbols);
+            assert_eq!(single.document_symbols, stats[&doc_id].document_symbols);
+            assert!((single.shared_symbols - stats[&doc_id].shared_symbols).abs() < 1e-9);
+        }
+    }
 }
@@ -1,8 +1,11 @@
 use crate::documents::SequiturDocuments;
+use crate::error::SequiturError;
 use crate::grammar::Grammar;
 use crate::symbol::Symbol;
+use ahash::AHashMap as HashMap;
 use slotmap::DefaultKey;
 use std::hash::Hash;
+use std::iter::FusedIterator;
 
 /// Iterator over a single document in SequiturDocuments.
 ///
@@ -11,6 +14,13 @@ pub struct DocumentIter<'a, T, DocId> {
     grammar: &'a Grammar<T>,
     current: Option<DefaultKey>,
     stack: Vec<DefaultKey>,
+    /// Next value from the back, and the RuleRefs it was reached through
+    back: Option<DefaultKey>,
+    back_stack: Vec<DefaultKey>,
+    /// Values not yet yielded from either end
+    remaining: usize,
+    /// Expanded rule lengths, filled in as [`nth`](Iterator::nth) needs them
+    lengths: HashMap<u32, usize>,
     _doc_id: std::marker::PhantomData<DocId>,
 }
 
@@ -34,10 +44,21 @@ impl<'a, T: Hash + Eq + Clone, DocId: Hash + Eq + Clone> DocumentIter<'a, T, Doc
         let mut stack = Vec::new();
         let current = Self::resolve_forward(&sequitur.grammar, start, &mut stack);
 
+        // Likewise from the last symbol before DocTail
+        let end = sequitur.grammar.symbols[doc_info.tail]
+            .prev
+            .expect("DocTail should have prev");
+        let mut back_stack = Vec::new();
+        let back = Self::resolve_backward(&sequitur.grammar, end, &mut back_stack);
+
         Some(Self {
             grammar: &sequitur.grammar,
             current,
             stack,
+            back,
+            back_stack,
+            remaining: doc_info.length,
+            lengths: HashMap::default(),
             _doc_id: std::marker::PhantomData,
         })
     }
@@ -90,13 +111,70 @@ impl<'a, T: Hash + Eq + Clone, DocId: Hash + Eq + Clone> DocumentIter<'a, T, Doc
             }
         }
     }
+
+    /// Resolves backward through RuleRefs to find the previous Value symbol.
+    ///
+    /// Mirrors [`resolve_forward`](Self::resolve_forward), entering rules at
+    /// their last symbol.
+    fn resolve_backward(
+        grammar: &'a Grammar<T>,
+        mut key: DefaultKey,
+        stack: &mut Vec<DefaultKey>,
+    ) -> Option<DefaultKey> {
+        loop {
+            match &grammar.symbols[key].symbol {
+                Symbol::Value(_) => return Some(key),
+
+                Symbol::RuleRef { rule_id } => {
+                    // Push current position to stack
+                    stack.push(key);
+
+                    // Jump to rule definition
+                    let rule_head = *grammar
+                        .rule_index
+                        .get(rule_id)
+                        .expect("Rule should exist in index");
+                    let Symbol::RuleHead { tail, .. } = grammar.symbols[rule_head].symbol else {
+                        unreachable!("rule index should point at RuleHeads");
+                    };
+
+                    // Move to last symbol in rule
+                    key = grammar.symbols[tail]
+                        .prev
+                        .expect("RuleTail should have prev");
+                }
+
+                Symbol::RuleHead { .. } | Symbol::DocHead { .. } => {
+                    // Start of rule or document, pop from stack
+                    if let Some(return_key) = stack.pop() {
+                        // Return to position before RuleRef
+                        key = grammar.symbols[return_key]
+                            .prev
+                            .expect("RuleRef should have prev");
+                    } else {
+                        // Stack empty, reached start
+                        return None;
+                    }
+                }
+
+                Symbol::RuleTail | Symbol::DocTail => {
+                    // Skip past tail
+                    key = grammar.symbols[key].prev.expect("Tail should have prev");
+                }
+            }
+        }
+    }
 }
 
 impl<'a, T: Hash + Eq + Clone, DocId: Hash + Eq + Clone> Iterator for DocumentIter<'a, T, DocId> {
     type Item = &'a T;
 
     fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
         let current_key = self.current?;
+        self.remaining -= 1;
 
         // Get the value
         let value = match &self.grammar.symbols[current_key].symbol {
@@ -110,8 +188,67 @@ impl<'a, T: Hash + Eq + Clone, DocId: Hash + Eq + Clone> Iterator for DocumentIt
 
         Some(value)
     }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+
+    fn count(self) -> usize {
+        self.remaining
+    }
+
+    fn last(mut self) -> Option<Self::Item> {
+        self.next_back()
+    }
+
+    /// Skips `n` values, stepping over whole rule expansions where possible.
+    fn nth(&mut self, n: usize) -> Option<Self::Item> {
+        if n >= self.remaining {
+            self.remaining = 0;
+            return None;
+        }
+        if n > 0 {
+            let next_key = self.grammar.symbols[self.current?].next?;
+            let key = self
+                .grammar
+                .skip_values(next_key, n - 1, &mut self.stack, &mut self.lengths);
+            self.current = Self::resolve_forward(self.grammar, key, &mut self.stack);
+            self.remaining -= n;
+        }
+        self.next()
+    }
+}
+
+impl<T: Hash + Eq + Clone, DocId: Hash + Eq + Clone> DoubleEndedIterator
+    for DocumentIter<'_, T, DocId>
+{
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let back_key = self.back?;
+        self.remaining -= 1;
+
+        let value = match &self.grammar.symbols[back_key].symbol {
+            Symbol::Value(v) => v,
+            _ => unreachable!("resolve_backward should only return Value symbols"),
+        };
+
+        // Move to previous symbol
+        let prev_key = self.grammar.symbols[back_key].prev?;
+        self.back = Self::resolve_backward(self.grammar, prev_key, &mut self.back_stack);
+
+        Some(value)
+    }
 }
 
+impl<T: Hash + Eq + Clone, DocId: Hash + Eq + Clone> ExactSizeIterator
+    for DocumentIter<'_, T, DocId>
+{
+}
+
+impl<T: Hash + Eq + Clone, DocId: Hash + Eq + Clone> FusedIterator for DocumentIter<'_, T, DocId> {}
+
 impl<T: Hash + Eq + Clone, DocId: Hash + Eq + Clone> SequiturDocuments<T, DocId> {
     /// Returns an iterator over the values in a specific document.
     ///
@@ -134,6 +271,29 @@ impl<T: Hash + Eq + Clone, DocId: Hash + Eq + Clone> SequiturDocuments<T, DocId>
         }
         DocumentIter::new(self, doc_id)
     }
+
+    /// Returns an iterator over the values in a specific document after
+    /// checking that the grammar is consistent, so iteration can't panic.
+    ///
+    /// Returns `Ok(None)` if the document doesn't exist. The check walks the
+    /// whole grammar, so prefer [`iter_document`](Self::iter_document) for
+    /// collections that were built rather than restored.
+    pub fn try_iter_document(
+        &self,
+        doc_id: &DocId,
+    ) -> Result<Option<DocumentIter<'_, T, DocId>>, SequiturError> {
+        if !self.documents.contains_key(doc_id) {
+            return Ok(None);
+        }
+        // Rules are shared, so their counts depend on every document.
+        let sequences: Vec<_> = self
+            .documents
+            .values()
+            .map(|info| (info.head, info.length))
+            .collect();
+        self.grammar.validate(&sequences, None)?;
+        Ok(self.iter_document(doc_id))
+    }
 }
 
 #[cfg(test)]
@@ -197,4 +357,34 @@ mod tests {
         assert_eq!(result1, vec!['a', 'b', 'a', 'b']);
         assert_eq!(result2, vec!['a', 'b', 'c']);
     }
+
+    #[test]
+    fn test_iter_rev() {
+        let mut docs = SequiturDocuments::new();
+        docs.extend_document(1, "abcabc".chars());
+        docs.extend_document(2, "xabcabcx".chars());
+
+        let reversed: String = docs.iter_document(&2).unwrap().rev().collect();
+        assert_eq!(reversed, "xcbacbax");
+
+        let mut iter = docs.iter_document(&1).unwrap();
+        assert_eq!(iter.next_back(), Some(&'c'));
+        assert_eq!(iter.next(), Some(&'a'));
+        assert_eq!(iter.collect::<String>(), "bcab");
+    }
+
+    #[test]
+    fn test_iter_exact_size_and_nth() {
+        let mut docs = SequiturDocuments::new();
+        docs.extend_document(1, "abcdabcdabcd".chars());
+        docs.extend_document(2, "xabcdabcdx".chars());
+
+        let mut iter = docs.iter_document(&2).unwrap();
+        assert_eq!(iter.len(), 10);
+        assert_eq!(iter.nth(5), Some(&'a'));
+        assert_eq!(iter.len(), 4);
+        assert_eq!(iter.nth(4), None);
+        assert_eq!(iter.len(), 0);
+        assert_eq!(iter.next(), None);
+    }
 }
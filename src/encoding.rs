@@ -0,0 +1,189 @@
+use crate::error::SequiturError;
+
+/// Values that serialize to a fixed number of little-endian bytes.
+///
+/// Serialized grammars store their values in a table of equally sized
+/// entries, so any value can be located without decoding the ones before it.
+///
+/// # Example
+///
+/// ```
+/// use sequitur_rs::FixedWidth;
+///
+/// let mut bytes = [0; 4];
+/// 'é'.write_le(&mut bytes);
+/// assert_eq!(char::read_le(&bytes), Some('é'));
+/// assert_eq!(char::read_le(&[0xff; 4]), None);
+/// ```
+pub trait FixedWidth: Sized {
+    /// Number of bytes in the encoding.
+    const WIDTH: usize;
+
+    /// Writes the value to `out`, which is exactly [`WIDTH`](Self::WIDTH)
+    /// bytes long.
+    fn write_le(&self, out: &mut [u8]);
+
+    /// Reads a value from exactly [`WIDTH`](Self::WIDTH) bytes, or returns
+    /// `None` if they don't encode one.
+    fn read_le(bytes: &[u8]) -> Option<Self>;
+}
+
+macro_rules! impl_fixed_width_int {
+    ($($ty:ty),*) => {
+        $(
+            impl FixedWidth for $ty {
+                const WIDTH: usize = std::mem::size_of::<$ty>();
+
+                fn write_le(&self, out: &mut [u8]) {
+                    out.copy_from_slice(&self.to_le_bytes());
+                }
+
+                fn read_le(bytes: &[u8]) -> Option<Self> {
+                    Some(<$ty>::from_le_bytes(bytes.try_into().ok()?))
+                }
+            }
+        )*
+    };
+}
+
+impl_fixed_width_int!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128);
+
+impl FixedWidth for char {
+    const WIDTH: usize = 4;
+
+    fn write_le(&self, out: &mut [u8]) {
+        (*self as u32).write_le(out);
+    }
+
+    fn read_le(bytes: &[u8]) -> Option<Self> {
+        char::from_u32(u32::read_le(bytes)?)
+    }
+}
+
+impl FixedWidth for bool {
+    const WIDTH: usize = 1;
+
+    fn write_le(&self, out: &mut [u8]) {
+        out[0] = *self as u8;
+    }
+
+    fn read_le(bytes: &[u8]) -> Option<Self> {
+        match bytes {
+            [0] => Some(false),
+            [1] => Some(true),
+            _ => None,
+        }
+    }
+}
+
+/// The unit type takes no bytes, for example as the document ID of a single
+/// sequence.
+impl FixedWidth for () {
+    const WIDTH: usize = 0;
+
+    fn write_le(&self, _out: &mut [u8]) {}
+
+    fn read_le(_bytes: &[u8]) -> Option<Self> {
+        Some(())
+    }
+}
+
+/// Reads fixed-width fields from the front of a byte slice.
+pub(crate) struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes }
+    }
+
+    /// Splits off the next `len` bytes.
+    pub(crate) fn take(&mut self, len: usize) -> Result<&'a [u8], SequiturError> {
+        if len > self.bytes.len() {
+            return Err(corrupt("unexpected end of data"));
+        }
+        let (front, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(front)
+    }
+
+    /// Splits off `count` entries of `width` bytes each.
+    pub(crate) fn take_table(
+        &mut self,
+        count: usize,
+        width: usize,
+    ) -> Result<&'a [u8], SequiturError> {
+        let len = count
+            .checked_mul(width)
+            .ok_or_else(|| corrupt("table size overflows"))?;
+        self.take(len)
+    }
+
+    pub(crate) fn u32(&mut self) -> Result<u32, SequiturError> {
+        Ok(u32::read_le(self.take(4)?).expect("four bytes encode a u32"))
+    }
+
+    /// Reads a `u64` that must fit in a `usize`.
+    pub(crate) fn usize(&mut self) -> Result<usize, SequiturError> {
+        let value = u64::read_le(self.take(8)?).expect("eight bytes encode a u64");
+        usize::try_from(value).map_err(|_| corrupt("size does not fit in usize"))
+    }
+
+    /// Returns the bytes not read yet.
+    pub(crate) fn rest(&self) -> &'a [u8] {
+        self.bytes
+    }
+}
+
+/// Reads entry `index` of a table of little-endian `u32`s.
+pub(crate) fn u32_at(table: &[u8], index: usize) -> u32 {
+    u32::read_le(&table[index * 4..index * 4 + 4]).expect("four bytes encode a u32")
+}
+
+/// Reads entry `index` of a table of little-endian `u64`s as a `usize`.
+///
+/// Only for tables already checked to hold values that fit.
+pub(crate) fn usize_at(table: &[u8], index: usize) -> usize {
+    u64::read_le(&table[index * 8..index * 8 + 8]).expect("eight bytes encode a u64") as usize
+}
+
+/// Reads entry `index` of a table of `T::WIDTH`-byte values.
+pub(crate) fn value_at<T: FixedWidth>(table: &[u8], index: usize) -> Option<T> {
+    T::read_le(&table[index * T::WIDTH..(index + 1) * T::WIDTH])
+}
+
+pub(crate) fn corrupt(reason: impl Into<String>) -> SequiturError {
+    SequiturError::CorruptGrammar(reason.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn roundtrip<T: FixedWidth + PartialEq + std::fmt::Debug>(value: T) {
+        let mut bytes = vec![0; T::WIDTH];
+        value.write_le(&mut bytes);
+        assert_eq!(T::read_le(&bytes), Some(value));
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        roundtrip(0xabu8);
+        roundtrip(-12345i32);
+        roundtrip(u64::MAX);
+        roundtrip('😀');
+        roundtrip(true);
+    }
+
+    #[test]
+    fn test_reader() {
+        let bytes = [1, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 9];
+        let mut reader = Reader::new(&bytes);
+        assert_eq!(reader.u32(), Ok(1));
+        assert_eq!(reader.usize(), Ok(2));
+        assert_eq!(reader.rest(), &[9]);
+        assert!(reader.take_table(1, 2).is_err());
+        assert!(reader.take_table(usize::MAX, 2).is_err());
+    }
+}
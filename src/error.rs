@@ -0,0 +1,47 @@
+use std::fmt;
+
+/// Errors reported by the fallible `try_*` methods.
+///
+/// The infallible methods panic in the same situations.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum SequiturError {
+    /// Every rule ID is in use, so no new rule can be created.
+    RuleIdsExhausted,
+    /// The sequence or document would be longer than `usize::MAX` values.
+    CapacityExceeded,
+    /// The grammar's internal structure is inconsistent, for example after
+    /// restoring corrupted data.
+    CorruptGrammar(String),
+}
+
+impl fmt::Display for SequiturError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::RuleIdsExhausted => write!(f, "all rule IDs are in use"),
+            Self::CapacityExceeded => write!(f, "sequence length would overflow usize"),
+            Self::CorruptGrammar(reason) => write!(f, "corrupt grammar: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for SequiturError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display() {
+        assert_eq!(
+            SequiturError::RuleIdsExhausted.to_string(),
+            "all rule IDs are in use"
+        );
+        assert_eq!(
+            SequiturError::CorruptGrammar("rule 3 is missing".into()).to_string(),
+            "corrupt grammar: rule 3 is missing"
+        );
+        let boxed: Box<dyn std::error::Error> = Box::new(SequiturError::CapacityExceeded);
+        assert_eq!(boxed.to_string(), "sequence length would overflow usize");
+    }
+}
@@ -0,0 +1,993 @@
+use crate::documents::SequiturDocuments;
+use crate::encoding::{corrupt, u32_at, FixedWidth, Reader};
+use crate::error::SequiturError;
+use crate::grammar::Grammar;
+use crate::sequitur::Sequitur;
+use crate::symbol::Symbol;
+use ahash::AHashMap as HashMap;
+use slotmap::DefaultKey;
+use std::hash::Hash;
+use std::iter::FusedIterator;
+use std::sync::Arc;
+
+/// Marks a symbol code as a rule reference; other codes index the value table.
+pub(crate) const RULE_FLAG: u32 = 1 << 31;
+
+/// Identifies serialized frozen grammars.
+const MAGIC: &[u8; 4] = b"SQFG";
+
+/// Version of the serialized layout.
+const VERSION: u32 = 1;
+
+/// An immutable snapshot of a grammar, shared cheaply across threads.
+///
+/// Freezing flattens the rules reachable from a sequence into contiguous
+/// arrays of symbol codes, dropping the linked lists and indexes that are only
+/// needed while compressing. Cloning only bumps a reference count, and the
+/// iterators own a clone, so they can outlive the grammar they were frozen
+/// from or be sent to other threads.
+///
+/// Each distinct value is stored once. The expanded length of every rule, and
+/// of every rule body up to each of its symbols, is stored alongside the
+/// bodies, so [`get`](Self::get) and [`Iterator::nth`] binary-search each body
+/// instead of expanding or scanning it. [`to_bytes`](Self::to_bytes) writes
+/// the arrays out, and [`thaw`](Self::thaw) turns the snapshot back into a
+/// [`Sequitur`] that can be extended.
+///
+/// # Example
+///
+/// ```
+/// use sequitur_rs::Sequitur;
+/// use std::thread;
+///
+/// let mut seq = Sequitur::new();
+/// seq.extend("abcabcabc".chars());
+/// let frozen = seq.freeze();
+///
+/// let handles: Vec<_> = (0..4)
+///     .map(|_| {
+///         let frozen = frozen.clone();
+///         thread::spawn(move || frozen.iter().collect::<String>())
+///     })
+///     .collect();
+/// for handle in handles {
+///     assert_eq!(handle.join().unwrap(), "abcabcabc");
+/// }
+/// ```
+pub struct FrozenGrammar<T> {
+    inner: Arc<FrozenRules<T>>,
+}
+
+/// Flattened rule bodies; the sequences come first, then the rules in an order
+/// where every rule only references earlier ones.
+pub(crate) struct FrozenRules<T> {
+    /// Distinct values, in the order they first appear in rule bodies
+    pub values: Vec<T>,
+    /// Rule bodies, back to back; in a snapshot rule 0 is the sequence itself
+    pub codes: Vec<u32>,
+    /// Rule `r` spans `codes[offsets[r]..offsets[r + 1]]`
+    pub offsets: Vec<usize>,
+    /// Expanded length of each rule
+    pub lengths: Vec<usize>,
+    /// Expanded length of each body up to and including each code, as
+    /// computed by [`code_ends`]
+    pub ends: Vec<usize>,
+}
+
+impl<T> FrozenRules<T> {
+    /// Builds the tables of a grammar that passed [`validate_rules`],
+    /// computing the lengths, or returns `None` if one overflows.
+    pub(crate) fn new(
+        values: Vec<T>,
+        codes: Vec<u32>,
+        offsets: Vec<usize>,
+        num_roots: usize,
+    ) -> Option<Self> {
+        let lengths = expanded_lengths(|i| codes[i], &offsets, num_roots)?;
+        let ends =
+            code_ends(|i| codes[i], &offsets, &lengths, num_roots).collect::<Option<Vec<_>>>()?;
+        Some(Self {
+            values,
+            codes,
+            offsets,
+            lengths,
+            ends,
+        })
+    }
+}
+
+/// Read access to the tables of flattened rules, whether owned or mapped.
+///
+/// Lookups binary-search the expanded length up to each code, so finding a
+/// value takes one search per rule it is nested in.
+pub(crate) trait RuleTables {
+    /// Returns the code at `pos`.
+    fn code(&self, pos: usize) -> u32;
+
+    /// Returns the positions of the codes of rule `rule`.
+    fn span(&self, rule: usize) -> (usize, usize);
+
+    /// Returns the expanded length of rule `rule`.
+    fn length(&self, rule: usize) -> usize;
+
+    /// Returns the expanded length of the body holding `pos` up to and
+    /// including the code at `pos`.
+    fn end(&self, pos: usize) -> usize;
+
+    /// Returns the number of values the code at `pos` expands to.
+    fn code_len(&self, pos: usize) -> usize {
+        let code = self.code(pos);
+        if code & RULE_FLAG == 0 {
+            1
+        } else {
+            self.length((code & !RULE_FLAG) as usize)
+        }
+    }
+
+    /// Returns the position of the code in `span` whose expansion holds the
+    /// value at `index` of the expansion of `span`, and the index of the value
+    /// within it, or `None` if `index` is past the end of `span`.
+    fn locate(&self, (start, end): (usize, usize), index: usize) -> Option<(usize, usize)> {
+        if start == end {
+            return None;
+        }
+        let before = self.end(start) - self.code_len(start);
+        let target = before.checked_add(index)?;
+        let (mut low, mut high) = (start, end);
+        while low < high {
+            let mid = low + (high - low) / 2;
+            if self.end(mid) <= target {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+        (low < end).then(|| (low, target - (self.end(low) - self.code_len(low))))
+    }
+
+    /// Returns the code of the value at `index` of the expansion of `span`,
+    /// descending through one rule per level.
+    fn find(&self, mut span: (usize, usize), mut index: usize) -> Option<u32> {
+        loop {
+            let (pos, offset) = self.locate(span, index)?;
+            let code = self.code(pos);
+            if code & RULE_FLAG == 0 {
+                return Some(code);
+            }
+            span = self.span((code & !RULE_FLAG) as usize);
+            index = offset;
+        }
+    }
+
+    /// Advances a stack of (next position, end) spans, as kept by the
+    /// iterators, past `n` values, skipping every rule that ends before them.
+    ///
+    /// At least `n` values must remain.
+    fn skip(&self, stack: &mut Vec<(usize, usize)>, mut n: usize) {
+        while n > 0 {
+            let (pos, end) = stack
+                .last_mut()
+                .expect("remaining values should be on the stack");
+            if *pos == *end {
+                stack.pop();
+                continue;
+            }
+            let Some((found, offset)) = self.locate((*pos, *end), n) else {
+                n -= self.end(*end - 1) - (self.end(*pos) - self.code_len(*pos));
+                stack.pop();
+                continue;
+            };
+            let code = self.code(found);
+            if code & RULE_FLAG == 0 {
+                *pos = found;
+                return;
+            }
+            *pos = found + 1;
+            stack.push(self.span((code & !RULE_FLAG) as usize));
+            n = offset;
+        }
+    }
+}
+
+impl<T> RuleTables for FrozenRules<T> {
+    fn code(&self, pos: usize) -> u32 {
+        self.codes[pos]
+    }
+
+    fn span(&self, rule: usize) -> (usize, usize) {
+        (self.offsets[rule], self.offsets[rule + 1])
+    }
+
+    fn length(&self, rule: usize) -> usize {
+        self.lengths[rule]
+    }
+
+    fn end(&self, pos: usize) -> usize {
+        self.ends[pos]
+    }
+}
+
+impl<T> Clone for FrozenGrammar<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: Arc::clone(&self.inner),
+        }
+    }
+}
+
+impl<T> FrozenGrammar<T> {
+    /// Returns the number of values in the sequence.
+    pub fn len(&self) -> usize {
+        self.inner.lengths[0]
+    }
+
+    /// Returns true if the sequence is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the number of rules, including the sequence itself.
+    pub fn num_rules(&self) -> usize {
+        self.inner.offsets.len() - 1
+    }
+
+    /// Returns an owning iterator over the sequence.
+    pub fn iter(&self) -> FrozenIter<T> {
+        FrozenIter::new(self.clone())
+    }
+
+    /// Returns the value at `index`, or `None` if it is out of bounds.
+    ///
+    /// Descends through one rule per level of the grammar, binary-searching
+    /// each body for the symbol that holds `index`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use sequitur_rs::Sequitur;
+    ///
+    /// let mut seq = Sequitur::new();
+    /// seq.extend("abcabdabcabd".chars());
+    /// let frozen = seq.freeze();
+    ///
+    /// assert_eq!(frozen.get(5), Some(&'d'));
+    /// assert_eq!(frozen.get(12), None);
+    /// ```
+    pub fn get(&self, index: usize) -> Option<&T> {
+        let rules = &*self.inner;
+        let code = rules.find(rules.span(0), index)?;
+        Some(&rules.values[code as usize])
+    }
+}
+
+impl<T: FixedWidth> FrozenGrammar<T> {
+    /// Serializes the snapshot.
+    ///
+    /// The layout is a header followed by the value, symbol code, rule offset
+    /// and rule length tables, all fixed-width and little-endian, so reading
+    /// it back only validates and copies the tables and recomputes the
+    /// lengths up to each code.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use sequitur_rs::{FrozenGrammar, Sequitur};
+    ///
+    /// let mut seq = Sequitur::new();
+    /// seq.extend("abcabcabc".chars());
+    ///
+    /// let bytes = seq.freeze().to_bytes();
+    /// let frozen = FrozenGrammar::<char>::from_bytes(&bytes).unwrap();
+    /// assert_eq!(frozen.iter().collect::<String>(), "abcabcabc");
+    /// ```
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let rules = &*self.inner;
+        let mut bytes = Vec::with_capacity(
+            36 + rules.values.len() * T::WIDTH
+                + rules.codes.len() * 4
+                + (rules.offsets.len() + rules.lengths.len()) * 8,
+        );
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        bytes.extend_from_slice(&(T::WIDTH as u32).to_le_bytes());
+        for count in [rules.values.len(), rules.codes.len(), rules.lengths.len()] {
+            bytes.extend_from_slice(&(count as u64).to_le_bytes());
+        }
+
+        for value in &rules.values {
+            let start = bytes.len();
+            bytes.resize(start + T::WIDTH, 0);
+            value.write_le(&mut bytes[start..]);
+        }
+        for code in &rules.codes {
+            bytes.extend_from_slice(&code.to_le_bytes());
+        }
+        for &n in rules.offsets.iter().chain(&rules.lengths) {
+            bytes.extend_from_slice(&(n as u64).to_le_bytes());
+        }
+        bytes
+    }
+
+    /// Reads a snapshot written by [`to_bytes`](Self::to_bytes).
+    ///
+    /// Returns [`SequiturError::CorruptGrammar`] if the bytes are truncated,
+    /// were written for values of a different width, or don't describe a
+    /// valid grammar.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, SequiturError> {
+        let mut reader = Reader::new(bytes);
+        if reader.take(4)? != MAGIC {
+            return Err(corrupt("not a serialized frozen grammar"));
+        }
+        let version = reader.u32()?;
+        if version != VERSION {
+            return Err(corrupt(format!("unsupported version {version}")));
+        }
+        let width = reader.u32()?;
+        if width as usize != T::WIDTH {
+            return Err(corrupt(format!(
+                "values are {width} bytes wide, expected {}",
+                T::WIDTH
+            )));
+        }
+        let num_values = reader.usize()?;
+        let num_codes = reader.usize()?;
+        let num_rules = reader.usize()?;
+        if num_rules == 0 {
+            return Err(corrupt("missing the sequence rule"));
+        }
+        if num_rules >= RULE_FLAG as usize || num_values >= RULE_FLAG as usize {
+            return Err(corrupt("too many rules or values"));
+        }
+
+        let value_table = reader.take_table(num_values, T::WIDTH)?;
+        let code_table = reader.take_table(num_codes, 4)?;
+        let offset_table = reader.take_table(num_rules + 1, 8)?;
+        let length_table = reader.take_table(num_rules, 8)?;
+        if !reader.rest().is_empty() {
+            return Err(corrupt("trailing bytes"));
+        }
+
+        let values = (0..num_values)
+            .map(|i| T::read_le(&value_table[i * T::WIDTH..(i + 1) * T::WIDTH]))
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| corrupt("invalid value"))?;
+        let codes: Vec<u32> = (0..num_codes).map(|i| u32_at(code_table, i)).collect();
+        let mut offset_reader = Reader::new(offset_table);
+        let offsets = (0..=num_rules)
+            .map(|_| offset_reader.usize())
+            .collect::<Result<Vec<_>, _>>()?;
+        validate_rules(|i| codes[i], codes.len(), &offsets, 1, values.len())?;
+
+        let rules = FrozenRules::new(values, codes, offsets, 1)
+            .ok_or_else(|| corrupt("sequence length overflows usize"))?;
+        let mut length_reader = Reader::new(length_table);
+        for (rule, &len) in rules.lengths.iter().enumerate() {
+            if length_reader.usize()? != len {
+                return Err(corrupt(format!("rule {rule} has the wrong length")));
+            }
+        }
+
+        Ok(Self {
+            inner: Arc::new(rules),
+        })
+    }
+}
+
+impl<T: Hash + Eq + Clone> FrozenGrammar<T> {
+    /// Rebuilds a mutable grammar with the same rules.
+    ///
+    /// The result uses the default constraints, whatever the grammar was
+    /// frozen from, and values can be pushed to it as usual.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use sequitur_rs::Sequitur;
+    ///
+    /// let mut seq = Sequitur::new();
+    /// seq.extend("abcabc".chars());
+    ///
+    /// let mut thawed = seq.freeze().thaw();
+    /// thawed.extend("abc".chars());
+    /// assert_eq!(thawed.iter().collect::<String>(), "abcabcabc");
+    /// ```
+    pub fn thaw(&self) -> Sequitur<T> {
+        Sequitur::from_flattened(&self.inner)
+    }
+}
+
+/// Checks that rule offsets are in bounds and every code points at a value or
+/// at a rule it may reference.
+///
+/// The first `num_roots` rules are sequences, which may reference any other
+/// rule; every other rule only references rules between the sequences and
+/// itself, so the grammar is acyclic.
+pub(crate) fn validate_rules(
+    code_at: impl Fn(usize) -> u32,
+    num_codes: usize,
+    offsets: &[usize],
+    num_roots: usize,
+    num_values: usize,
+) -> Result<(), SequiturError> {
+    if offsets[0] != 0 || offsets[offsets.len() - 1] != num_codes {
+        return Err(corrupt("rule offsets don't cover the symbol codes"));
+    }
+    for rule in 0..offsets.len() - 1 {
+        let (start, end) = (offsets[rule], offsets[rule + 1]);
+        if start > end {
+            return Err(corrupt(format!("rule {rule} ends before it starts")));
+        }
+        for code in (start..end).map(&code_at) {
+            let valid = if code & RULE_FLAG == 0 {
+                (code as usize) < num_values
+            } else {
+                let child = (code & !RULE_FLAG) as usize;
+                child >= num_roots && (rule < num_roots || child < rule)
+            };
+            if !valid {
+                return Err(corrupt(format!("rule {rule} holds invalid code {code:#x}")));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Computes the expanded length of every rule of a grammar that passed
+/// [`validate_rules`], or `None` if one overflows.
+pub(crate) fn expanded_lengths(
+    code_at: impl Fn(usize) -> u32,
+    offsets: &[usize],
+    num_roots: usize,
+) -> Option<Vec<usize>> {
+    let num_rules = offsets.len() - 1;
+    let mut lengths = vec![0; num_rules];
+    // Sequences may reference every other rule, so they come last.
+    for rule in (num_roots..num_rules).chain(0..num_roots) {
+        let mut len = 0usize;
+        for code in (offsets[rule]..offsets[rule + 1]).map(&code_at) {
+            let n = if code & RULE_FLAG == 0 {
+                1
+            } else {
+                lengths[(code & !RULE_FLAG) as usize]
+            };
+            len = len.checked_add(n)?;
+        }
+        lengths[rule] = len;
+    }
+    Some(lengths)
+}
+
+/// Yields, for each code of a grammar that passed [`validate_rules`], the
+/// expanded length of its body up to and including it, or `None` once that
+/// overflows.
+///
+/// Every rule body counts from zero, except that the first `num_roots` rules,
+/// the sequences, run on from one another, so the lengths also locate values
+/// across all sequences.
+pub(crate) fn code_ends<'a>(
+    code_at: impl Fn(usize) -> u32 + 'a,
+    offsets: &'a [usize],
+    lengths: &'a [usize],
+    num_roots: usize,
+) -> impl Iterator<Item = Option<usize>> + 'a {
+    let mut rule = 0;
+    let mut end = Some(0usize);
+    (0..offsets[offsets.len() - 1]).map(move |pos| {
+        while offsets[rule + 1] <= pos {
+            rule += 1;
+        }
+        if pos == offsets[rule] && rule >= num_roots {
+            end = Some(0);
+        }
+        let code = code_at(pos);
+        let len = if code & RULE_FLAG == 0 {
+            1
+        } else {
+            lengths[(code & !RULE_FLAG) as usize]
+        };
+        end = end.and_then(|end| end.checked_add(len));
+        end
+    })
+}
+
+impl<T: Clone> IntoIterator for FrozenGrammar<T> {
+    type Item = T;
+    type IntoIter = FrozenIter<T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        FrozenIter::new(self)
+    }
+}
+
+impl<T: Clone> IntoIterator for &FrozenGrammar<T> {
+    type Item = T;
+    type IntoIter = FrozenIter<T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Owning iterator over a [`FrozenGrammar`].
+///
+/// Holds a reference-counted handle to the grammar rather than a borrow, so it
+/// is `'static` when `T` is, and yields values by clone.
+pub struct FrozenIter<T> {
+    grammar: FrozenGrammar<T>,
+    /// (next position, end) of each rule body being expanded
+    stack: Vec<(usize, usize)>,
+    /// Values left to yield
+    remaining: usize,
+}
+
+impl<T> FrozenIter<T> {
+    fn new(grammar: FrozenGrammar<T>) -> Self {
+        let stack = vec![grammar.inner.span(0)];
+        let remaining = grammar.len();
+        Self {
+            grammar,
+            stack,
+            remaining,
+        }
+    }
+}
+
+impl<T: Clone> Iterator for FrozenIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (pos, end) = self.stack.last_mut()?;
+            if *pos == *end {
+                self.stack.pop();
+                continue;
+            }
+            let code = self.grammar.inner.codes[*pos];
+            *pos += 1;
+            if code & RULE_FLAG != 0 {
+                let span = self.grammar.inner.span((code & !RULE_FLAG) as usize);
+                self.stack.push(span);
+            } else {
+                self.remaining -= 1;
+                return Some(self.grammar.inner.values[code as usize].clone());
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+
+    fn count(self) -> usize {
+        self.remaining
+    }
+
+    /// Skips whole rules that end before the `n`th value without expanding
+    /// them, binary-searching each body on the way down.
+    fn nth(&mut self, n: usize) -> Option<Self::Item> {
+        if n >= self.remaining {
+            self.remaining = 0;
+            self.stack.clear();
+            return None;
+        }
+        self.remaining -= n;
+        self.grammar.inner.skip(&mut self.stack, n);
+        self.next()
+    }
+}
+
+impl<T: Clone> ExactSizeIterator for FrozenIter<T> {}
+
+impl<T: Clone> FusedIterator for FrozenIter<T> {}
+
+impl<T: Hash + Eq + Clone> Grammar<T> {
+    /// Flattens the sequence starting at `head` and every rule it reaches.
+    pub(crate) fn freeze(&self, head: DefaultKey, length: usize) -> FrozenGrammar<T> {
+        let frozen = self.flatten(&[head]);
+        debug_assert_eq!(frozen.lengths[0], length);
+        FrozenGrammar {
+            inner: Arc::new(frozen),
+        }
+    }
+
+    /// Flattens the given sequences, in order, followed by every rule they
+    /// reach, children first.
+    pub(crate) fn flatten(&self, sequences: &[DefaultKey]) -> FrozenRules<T> {
+        let order = self.rules_children_first(sequences);
+        let index: HashMap<u32, u32> = order
+            .iter()
+            .enumerate()
+            .map(|(i, &rule_id)| (rule_id, (i + sequences.len()) as u32))
+            .collect();
+        assert!(
+            order.len() + sequences.len() < RULE_FLAG as usize,
+            "too many rules to freeze"
+        );
+
+        // Codes of the distinct values, each stored once
+        let mut value_codes: HashMap<&T, u32> = HashMap::default();
+        let mut values = Vec::new();
+        let mut codes = Vec::new();
+        let mut offsets = vec![0];
+        let heads = sequences
+            .iter()
+            .copied()
+            .chain(order.iter().map(|id| self.rule_index[id]));
+        for head in heads {
+            for key in self.body_keys(head) {
+                let code = match &self.symbols[key].symbol {
+                    Symbol::Value(value) => *value_codes.entry(value).or_insert_with(|| {
+                        values.push(value.clone());
+                        values.len() as u32 - 1
+                    }),
+                    Symbol::RuleRef { rule_id } => index[rule_id] | RULE_FLAG,
+                    _ => unreachable!("rule bodies hold only values and references"),
+                };
+                codes.push(code);
+            }
+            offsets.push(codes.len());
+        }
+        assert!(
+            values.len() < RULE_FLAG as usize,
+            "too many values to freeze"
+        );
+        FrozenRules::new(values, codes, offsets, sequences.len())
+            .expect("rule lengths should fit in usize")
+    }
+
+    /// Copies flattened rules into the grammar as new rules, and the bodies of
+    /// the flattened sequences before the given sequence tails, without
+    /// enforcing any constraint or setting reference counts.
+    pub(crate) fn unflatten(&mut self, rules: &FrozenRules<T>, tails: &[DefaultKey]) {
+        let mut ids = vec![0; rules.lengths.len()];
+        let body = |rule: usize, ids: &[u32]| {
+            rules.codes[rules.offsets[rule]..rules.offsets[rule + 1]]
+                .iter()
+                .map(|&code| {
+                    if code & RULE_FLAG == 0 {
+                        Symbol::Value(rules.values[code as usize].clone())
+                    } else {
+                        Symbol::RuleRef {
+                            rule_id: ids[(code & !RULE_FLAG) as usize],
+                        }
+                    }
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // Rules only reference earlier rules, so every ID is known in time.
+        for rule in tails.len()..rules.lengths.len() {
+            ids[rule] = self.append_rule(body(rule, &ids)).0;
+        }
+        for (rule, &tail) in tails.iter().enumerate() {
+            for symbol in body(rule, &ids) {
+                self.insert_unlinked_before(tail, symbol);
+            }
+        }
+    }
+}
+
+impl<T: Hash + Eq + Clone> Sequitur<T> {
+    /// Builds a grammar with default constraints from flattened rules whose
+    /// rule 0 is the sequence, inlining rules used only once.
+    pub(crate) fn from_flattened(rules: &FrozenRules<T>) -> Self {
+        let mut seq = Self::new();
+        seq.grammar.unflatten(rules, &[seq.sequence_end]);
+        seq.length = rules.lengths[0];
+
+        let head = seq.grammar.rule_index[&0];
+        seq.grammar.recount_and_inline_single_use(Some(0), &[]);
+        seq.grammar.enforce_digram_uniqueness(&[head]);
+        seq
+    }
+
+    /// Returns an immutable snapshot of the grammar that can be shared across
+    /// threads.
+    pub fn freeze(&self) -> FrozenGrammar<T> {
+        self.grammar
+            .freeze(self.grammar.rule_index[&0], self.length)
+    }
+}
+
+impl<T: Hash + Eq + Clone> IntoIterator for Sequitur<T> {
+    type Item = T;
+    type IntoIter = FrozenIter<T>;
+
+    /// Consumes the grammar, returning an owning iterator over the sequence.
+    fn into_iter(self) -> Self::IntoIter {
+        self.freeze().into_iter()
+    }
+}
+
+impl<T: Hash + Eq + Clone, DocId: Hash + Eq + Clone> SequiturDocuments<T, DocId> {
+    /// Returns an immutable snapshot of a document, or `None` if it doesn't
+    /// exist.
+    ///
+    /// Only the rules the document uses are copied, and the snapshot is
+    /// unaffected by later changes to the collection.
+    pub fn freeze_document(&self, doc_id: &DocId) -> Option<FrozenGrammar<T>> {
+        let info = self.documents.get(doc_id)?;
+        Some(self.grammar.freeze(info.head, info.length))
+    }
+
+    /// Returns an owning iterator over a document, or `None` if it doesn't
+    /// exist.
+    ///
+    /// Unlike [`iter_document`](Self::iter_document), the iterator doesn't
+    /// borrow the collection, so documents can keep growing while it is
+    /// consumed, for example on another thread.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use sequitur_rs::SequiturDocuments;
+    ///
+    /// let mut docs = SequiturDocuments::new();
+    /// docs.extend_document(1, "abcabc".chars());
+    ///
+    /// let iter = docs.iter_document_owned(&1).unwrap();
+    /// docs.extend_document(1, "more".chars());
+    ///
+    /// assert_eq!(iter.collect::<String>(), "abcabc");
+    /// ```
+    pub fn iter_document_owned(&self, doc_id: &DocId) -> Option<FrozenIter<T>> {
+        self.freeze_document(doc_id).map(FrozenGrammar::into_iter)
+    }
+
+    /// Copies a document into an independent grammar, or returns `None` if it
+    /// doesn't exist.
+    ///
+    /// Only the rules the document reaches are copied, without expanding the
+    /// document. Rules the document uses only once are inlined and digrams
+    /// repeated inside it are factored out, so the result satisfies both
+    /// constraints with the default configuration and can be extended like
+    /// any other [`Sequitur`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use sequitur_rs::SequiturDocuments;
+    ///
+    /// let mut docs = SequiturDocuments::new();
+    /// docs.extend_document("shared", "abcdabcd".chars());
+    /// docs.extend_document("export", "xabcdy".chars());
+    ///
+    /// let seq = docs.extract_document(&"export").unwrap();
+    /// assert_eq!(seq.iter().collect::<String>(), "xabcdy");
+    /// // "abcd" appears once in the document, so its rule is inlined.
+    /// assert_eq!(seq.rules().len(), 1);
+    /// ```
+    pub fn extract_document(&self, doc_id: &DocId) -> Option<Sequitur<T>> {
+        let info = self.documents.get(doc_id)?;
+        Some(Sequitur::from_flattened(
+            &self.grammar.flatten(&[info.head]),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_freeze_roundtrip() {
+        let input: Vec<u32> = (0..3000).map(|i| (i * 7 + i / 13) % 11).collect();
+        let mut seq = Sequitur::new();
+        seq.extend(input.iter().copied());
+
+        let frozen = seq.freeze();
+        assert_eq!(frozen.len(), input.len());
+        assert_eq!(frozen.num_rules(), seq.rules().len());
+        assert!(frozen.iter().eq(input.iter().copied()));
+    }
+
+    #[test]
+    fn test_freeze_empty() {
+        let frozen = Sequitur::<char>::new().freeze();
+        assert!(frozen.is_empty());
+        assert_eq!(frozen.num_rules(), 1);
+        assert_eq!(frozen.iter().next(), None);
+    }
+
+    #[test]
+    fn test_sequitur_into_iter() {
+        let mut seq = Sequitur::new();
+        seq.extend("abracadabra abracadabra".chars());
+        let iter = seq.into_iter();
+        assert_eq!(iter.collect::<String>(), "abracadabra abracadabra");
+    }
+
+    #[test]
+    fn test_frozen_outlives_grammar_and_crosses_threads() {
+        let frozen = {
+            let mut seq = Sequitur::new();
+            seq.extend("the cat the hat ".repeat(20).chars());
+            seq.freeze()
+        };
+        let handles: Vec<_> = (0..3)
+            .map(|_| {
+                let iter = frozen.iter();
+                thread::spawn(move || iter.collect::<String>())
+            })
+            .collect();
+        for handle in handles {
+            assert_eq!(handle.join().unwrap(), "the cat the hat ".repeat(20));
+        }
+    }
+
+    #[test]
+    fn test_document_snapshot_copies_only_used_rules() {
+        let mut docs = SequiturDocuments::new();
+        docs.extend_document("a", "xyzxyzxyz".chars());
+        docs.extend_document("b", "123123123".chars());
+
+        let frozen = docs.freeze_document(&"a").unwrap();
+        assert_eq!(frozen.iter().collect::<String>(), "xyzxyzxyz");
+        assert!(frozen.num_rules() < docs.rules().len() + 1);
+        assert!(docs.freeze_document(&"c").is_none());
+    }
+
+    #[test]
+    fn test_owned_document_iter_while_writing() {
+        let mut docs = SequiturDocuments::new();
+        docs.extend_document(0, "abcabcabc".chars());
+        let iter = docs.iter_document_owned(&0).unwrap();
+
+        let reader = thread::spawn(move || iter.collect::<String>());
+        docs.extend_document(0, "abcd".chars());
+        docs.extend_document(1, "abcabc".chars());
+
+        assert_eq!(reader.join().unwrap(), "abcabcabc");
+        assert_eq!(
+            docs.iter_document(&0).unwrap().collect::<String>(),
+            "abcabcabcabcd"
+        );
+    }
+
+    #[test]
+    fn test_random_access() {
+        let input: Vec<u32> = (0..2000).map(|i| (i * 5 + i / 17) % 7).collect();
+        let mut seq = Sequitur::new();
+        seq.extend(input.iter().copied());
+        let frozen = seq.freeze();
+        // Each distinct value is stored once.
+        assert_eq!(frozen.inner.values.len(), 7);
+
+        for (i, value) in input.iter().enumerate() {
+            assert_eq!(frozen.get(i), Some(value));
+            assert_eq!(frozen.iter().nth(i), Some(*value));
+        }
+        assert_eq!(frozen.get(input.len()), None);
+
+        let mut iter = frozen.iter();
+        assert_eq!(iter.len(), input.len());
+        assert_eq!(iter.nth(1000), Some(input[1000]));
+        assert_eq!(iter.nth(500), Some(input[1501]));
+        assert_eq!(iter.len(), input.len() - 1502);
+        assert!(iter.eq(input[1502..].iter().copied()));
+        assert_eq!(frozen.iter().nth(input.len()), None);
+    }
+
+    #[test]
+    fn test_bytes_roundtrip() {
+        let mut seq = Sequitur::new();
+        seq.extend("how much wood would a woodchuck chuck".chars());
+        let frozen = seq.freeze();
+
+        let bytes = frozen.to_bytes();
+        let read = FrozenGrammar::<char>::from_bytes(&bytes).unwrap();
+        assert_eq!(read.num_rules(), frozen.num_rules());
+        assert!(read.iter().eq(frozen.iter()));
+        assert_eq!(read.to_bytes(), bytes);
+
+        let empty = Sequitur::<u8>::new().freeze().to_bytes();
+        assert!(FrozenGrammar::<u8>::from_bytes(&empty).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_from_bytes_rejects_corruption() {
+        let mut seq = Sequitur::new();
+        seq.extend("abcabcabcabc".chars());
+        let bytes = seq.freeze().to_bytes();
+        let invalid = |bytes: &[u8]| {
+            matches!(
+                FrozenGrammar::<char>::from_bytes(bytes),
+                Err(SequiturError::CorruptGrammar(_))
+            )
+        };
+
+        assert!(invalid(&bytes[..bytes.len() - 1]));
+        assert!(invalid(&[bytes.as_slice(), &[0]].concat()));
+        assert!(FrozenGrammar::<u16>::from_bytes(&bytes).is_err());
+
+        // The first code of rule 0 refers to itself.
+        let codes_start = 36 + 4 * seq.freeze().inner.values.len();
+        let mut cyclic = bytes.clone();
+        cyclic[codes_start..codes_start + 4].copy_from_slice(&RULE_FLAG.to_le_bytes());
+        assert!(invalid(&cyclic));
+
+        // The stored length of the last rule is off by one.
+        let mut wrong_length = bytes.clone();
+        let last = wrong_length.len() - 8;
+        wrong_length[last] ^= 1;
+        assert!(invalid(&wrong_length));
+
+        // A header alone, with no values or codes and more rules than fit.
+        let mut huge = bytes[..12].to_vec();
+        for count in [0, 0, u64::MAX] {
+            huge.extend_from_slice(&count.to_le_bytes());
+        }
+        assert!(invalid(&huge));
+    }
+
+    #[test]
+    fn test_thaw() {
+        let input = "the cat sat on the mat, the cat sat on the hat";
+        let mut seq = Sequitur::new();
+        seq.extend(input[..30].chars());
+
+        let mut thawed = seq.freeze().thaw();
+        assert_eq!(thawed.len(), 30);
+        assert_eq!(thawed.rules().len(), seq.rules().len());
+        assert!(thawed.try_iter().is_ok());
+
+        seq.extend(input[30..].chars());
+        thawed.extend(input[30..].chars());
+        assert_eq!(thawed.iter().collect::<String>(), input);
+        assert_eq!(thawed.stats().num_rules, seq.stats().num_rules);
+        assert_eq!(thawed.stats().grammar_symbols, seq.stats().grammar_symbols);
+    }
+
+    #[test]
+    fn test_thaw_document() {
+        let mut docs = SequiturDocuments::new();
+        docs.extend_document(1, "abcdabcd".chars());
+        docs.extend_document(2, "xabcdy".chars());
+
+        let thawed = docs.freeze_document(&2).unwrap().thaw();
+        assert_eq!(thawed.iter().collect::<String>(), "xabcdy");
+        assert!(thawed.try_iter().is_ok());
+    }
+
+    #[test]
+    fn test_extract_document() {
+        let mut docs = SequiturDocuments::new();
+        docs.extend_document(1, "the quick brown fox; the quick brown dog".chars());
+        docs.extend_document(
+            2,
+            "a quick brown fox, a lazy dog, a quick brown fox".chars(),
+        );
+        docs.extend_document(3, "unrelated".chars());
+
+        for doc_id in [1, 2, 3] {
+            let expected: String = docs.iter_document(&doc_id).unwrap().collect();
+            let seq = docs.extract_document(&doc_id).unwrap();
+            assert_eq!(seq.iter().collect::<String>(), expected);
+            assert_eq!(seq.len(), expected.chars().count());
+            assert!(seq.try_iter().is_ok());
+            for (&rule_id, &head) in seq.rules() {
+                if let Symbol::RuleHead { count, .. } = seq.grammar.symbols[head].symbol {
+                    assert!(
+                        rule_id == 0 || count >= 2,
+                        "rule {rule_id} used {count} times"
+                    );
+                }
+            }
+            assert!(seq.rules().len() <= docs.rules().len() + 1);
+        }
+        assert!(docs.extract_document(&4).is_none());
+    }
+
+    #[test]
+    fn test_extend_extracted_document() {
+        let mut docs = SequiturDocuments::new();
+        docs.extend_document("a", "abcabcabc".chars());
+        docs.extend_document("b", "abcxyz".chars());
+
+        let mut seq = docs.extract_document(&"b").unwrap();
+        seq.extend("xyzabc".chars());
+        assert_eq!(seq.iter().collect::<String>(), "abcxyzxyzabc");
+        assert!(seq.try_iter().is_ok());
+    }
+}
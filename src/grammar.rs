@@ -1,3 +1,4 @@
+use crate::builder::GrammarConfig;
 use crate::id_gen::IdGenerator;
 use crate::symbol::{Symbol, SymbolHash, SymbolNode};
 use ahash::AHashMap as HashMap;
@@ -12,6 +13,7 @@ use std::hash::Hash;
 /// - Digram index for detecting repeated pairs
 /// - Rule index for looking up rule definitions
 /// - ID generator for creating new rule IDs
+#[derive(Clone)]
 pub(crate) struct Grammar<T> {
     /// Storage for all symbols using generational indices
     pub symbols: SlotMap<DefaultKey, SymbolNode<T>>,
@@ -24,20 +26,142 @@ pub(crate) struct Grammar<T> {
 
     /// ID generator with reuse
     pub id_gen: IdGenerator,
+
+    /// Constraint parameters
+    pub config: GrammarConfig,
+
+    /// Further occurrences of indexed digrams, when digrams may repeat
+    pub pending_digrams: HashMap<(SymbolHash, SymbolHash), Vec<DefaultKey>>,
+
+    /// Known RuleRef locations by rule, when rules may be inlined at several sites
+    pub ref_locations: HashMap<u32, Vec<DefaultKey>>,
+
+    /// Cached expanded length of rules, when rule creation is gated on length
+    pub expansion_lengths: HashMap<u32, usize>,
 }
 
 impl<T> Grammar<T> {
     /// Creates a new empty grammar.
     pub fn new() -> Self {
+        Self::with_config(GrammarConfig::default())
+    }
+
+    /// Creates a new empty grammar with the given constraints.
+    pub fn with_config(config: GrammarConfig) -> Self {
         Self {
             symbols: SlotMap::new(),
             digram_index: HashMap::default(),
             rule_index: HashMap::default(),
             id_gen: IdGenerator::new(),
+            config,
+            pending_digrams: HashMap::default(),
+            ref_locations: HashMap::default(),
+            expansion_lengths: HashMap::default(),
+        }
+    }
+
+    /// Returns an iterator over the keys of the symbols in a rule or document body.
+    ///
+    /// `head` must be a RuleHead or DocHead; the sentinels themselves are not yielded.
+    pub fn body_keys(&self, head: DefaultKey) -> BodyKeys<'_, T> {
+        BodyKeys {
+            grammar: self,
+            current: self.symbols[head].next,
+        }
+    }
+
+    // ========================================================================
+    // Analysis
+    // ========================================================================
+
+    /// Computes the expanded length of every rule.
+    pub fn rule_lengths(&self) -> HashMap<u32, usize> {
+        let mut lengths = HashMap::default();
+        for &rule_id in self.rule_index.keys() {
+            self.rule_length(rule_id, &mut lengths);
+        }
+        lengths
+    }
+
+    pub(crate) fn rule_length(&self, rule_id: u32, lengths: &mut HashMap<u32, usize>) -> usize {
+        if let Some(&len) = lengths.get(&rule_id) {
+            return len;
+        }
+
+        let head = self.rule_index[&rule_id];
+        let mut len = 0;
+        for key in self.body_keys(head) {
+            len += match self.symbols[key].symbol {
+                Symbol::RuleRef { rule_id } => self.rule_length(rule_id, lengths),
+                _ => 1,
+            };
+        }
+
+        lengths.insert(rule_id, len);
+        len
+    }
+
+    /// Calls `f(rule_id, start, len)` for every rule occurrence, at any nesting
+    /// depth, in the expansion of the sequence starting at `head`.
+    ///
+    /// `start` is relative to the start of the sequence, and occurrences are
+    /// reported in order of their start.
+    pub fn for_each_rule_occurrence<F>(
+        &self,
+        head: DefaultKey,
+        lengths: &HashMap<u32, usize>,
+        f: &mut F,
+    ) where
+        F: FnMut(u32, usize, usize),
+    {
+        self.visit_occurrences(head, 0, lengths, f);
+    }
+
+    fn visit_occurrences<F>(
+        &self,
+        head: DefaultKey,
+        mut offset: usize,
+        lengths: &HashMap<u32, usize>,
+        f: &mut F,
+    ) where
+        F: FnMut(u32, usize, usize),
+    {
+        for key in self.body_keys(head) {
+            if let Symbol::RuleRef { rule_id } = self.symbols[key].symbol {
+                let len = lengths[&rule_id];
+                f(rule_id, offset, len);
+                self.visit_occurrences(self.rule_index[&rule_id], offset, lengths, f);
+                offset += len;
+            } else {
+                offset += 1;
+            }
         }
     }
 }
 
+/// Iterator over the symbol keys between a head sentinel and its tail.
+pub(crate) struct BodyKeys<'a, T> {
+    grammar: &'a Grammar<T>,
+    current: Option<DefaultKey>,
+}
+
+impl<T> Iterator for BodyKeys<'_, T> {
+    type Item = DefaultKey;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let key = self.current?;
+        if matches!(
+            self.grammar.symbols[key].symbol,
+            Symbol::RuleTail | Symbol::DocTail
+        ) {
+            self.current = None;
+            return None;
+        }
+        self.current = self.grammar.symbols[key].next;
+        Some(key)
+    }
+}
+
 impl<T: Hash + Eq + Clone> Grammar<T> {
     // ========================================================================
     // Digram Operations
@@ -137,8 +261,32 @@ impl<T: Hash + Eq + Clone> Grammar<T> {
         let second_hash = SymbolHash::from_symbol(&self.symbols[second].symbol);
 
         // Only remove if it points to this exact location
-        if let Entry::Occupied(e) = self.digram_index.entry((first_hash, second_hash)) {
-            if *e.get() == first {
+        let Entry::Occupied(e) = self.digram_index.entry((first_hash, second_hash)) else {
+            return;
+        };
+        if *e.get() != first {
+            return;
+        }
+
+        // Of overlapping occurrences in a run like `aaa` only one is indexed,
+        // so the other one takes its place rather than being forgotten. The
+        // relaxed constraints track every occurrence in `pending_digrams`.
+        let equal =
+            |a: DefaultKey, b: DefaultKey| self.symbols[a].symbol.equals(&self.symbols[b].symbol);
+        let overlapping = self.symbols[second]
+            .next
+            .filter(|_| self.config.is_classic())
+            .filter(|&third| equal(first, second) && equal(second, third))
+            .map(|_| second)
+            .or_else(|| {
+                self.symbols[first]
+                    .prev
+                    .filter(|_| self.config.is_classic())
+                    .filter(|&prev| equal(prev, first) && equal(first, second))
+            });
+        match overlapping {
+            Some(key) => *e.into_mut() = key,
+            None => {
                 e.remove();
             }
         }
@@ -318,13 +466,23 @@ impl<T: Hash + Eq + Clone> Grammar<T> {
             .expect("Rule first should have next");
 
         self.expand_rule_if_necessary(rule_first);
-        self.expand_rule_if_necessary(rule_second);
+
+        // Expanding the first symbol checks the digrams it forms, which may
+        // already have moved the second one into another rule.
+        if self.symbols.contains_key(rule_second) {
+            self.expand_rule_if_necessary(rule_second);
+        }
 
         new_rule_key
     }
 
     /// Expands a rule inline if it's only used once (rule utility constraint).
     pub fn expand_rule_if_necessary(&mut self, potential_rule: DefaultKey) {
+        if !self.config.is_classic() {
+            self.expand_relaxed(potential_rule);
+            return;
+        }
+
         // Only RuleRef symbols can be expanded
         let Symbol::RuleRef { rule_id } = self.symbols[potential_rule].symbol else {
             return;
@@ -374,7 +532,8 @@ impl<T: Hash + Eq + Clone> Grammar<T> {
 
         // Remove rule from indices
         self.rule_index.remove(&rule_id);
-        self.id_gen.free(rule_id);
+        let freed = self.id_gen.free(rule_id);
+        debug_assert!(freed, "rule {rule_id} should have an allocated ID");
 
         // Unlink rule head and tail
         self.symbols[rule_head].next = None;
@@ -407,8 +566,9 @@ impl<T: Hash + Eq + Clone> Grammar<T> {
             }
         }
 
-        // Check digram at rule_last if valid
-        if let Some(after) = after_rule {
+        // Check digram at rule_last if valid; the check before can have
+        // replaced it with a rule.
+        if let Some(after) = self.symbols.get(rule_last).and_then(|node| node.next) {
             if !self.is_sequence_end(&self.symbols[after].symbol) {
                 self.link_made(rule_last);
             }
@@ -425,6 +585,11 @@ impl<T: Hash + Eq + Clone> Grammar<T> {
             "link_made called on symbol without next"
         );
 
+        if !self.config.is_classic() {
+            self.link_made_relaxed(first_key);
+            return;
+        }
+
         let second_key = self.symbols[first_key].next.unwrap();
 
         // Try to find existing digram or add to index
@@ -473,6 +638,10 @@ impl<T: Hash + Eq + Clone> Grammar<T> {
 
     /// Checks newly formed links after two rule insertions.
     pub fn check_new_links_pair(&mut self, rule1: DefaultKey, rule2: DefaultKey) {
+        // Each check can inline rules and remove the other location, so every
+        // step re-checks that its key is still valid.
+        let live = |grammar: &Self, key| grammar.symbols.contains_key(key);
+
         // Check at rule1
         if let Some(next) = self.symbols[rule1].next {
             if !self.is_sequence_end(&self.symbols[next].symbol)
@@ -483,25 +652,31 @@ impl<T: Hash + Eq + Clone> Grammar<T> {
         }
 
         // Check at rule2
-        if let Some(next) = self.symbols[rule2].next {
-            if !self.is_sequence_end(&self.symbols[next].symbol)
-                && !self.is_sequence_start(&self.symbols[rule2].symbol)
-            {
-                self.link_made(rule2);
+        if live(self, rule2) {
+            if let Some(next) = self.symbols[rule2].next {
+                if !self.is_sequence_end(&self.symbols[next].symbol)
+                    && !self.is_sequence_start(&self.symbols[rule2].symbol)
+                {
+                    self.link_made(rule2);
+                }
             }
         }
 
         // Check before rule2
-        if let Some(prev) = self.symbols[rule2].prev {
-            if prev != rule1 && !self.is_sequence_start(&self.symbols[prev].symbol) {
-                self.link_made(prev);
+        if live(self, rule2) {
+            if let Some(prev) = self.symbols[rule2].prev {
+                if prev != rule1 && !self.is_sequence_start(&self.symbols[prev].symbol) {
+                    self.link_made(prev);
+                }
             }
         }
 
         // Check before rule1
-        if let Some(prev) = self.symbols[rule1].prev {
-            if prev != rule2 && !self.is_sequence_start(&self.symbols[prev].symbol) {
-                self.link_made(prev);
+        if live(self, rule1) {
+            if let Some(prev) = self.symbols[rule1].prev {
+                if prev != rule2 && !self.is_sequence_start(&self.symbols[prev].symbol) {
+                    self.link_made(prev);
+                }
             }
         }
     }
@@ -512,19 +687,19 @@ impl<T: Hash + Eq + Clone> Grammar<T> {
 
     /// Checks if a symbol marks the start of a sequence (RuleHead or DocHead).
     #[inline]
-    fn is_sequence_start(&self, symbol: &Symbol<T>) -> bool {
+    pub(crate) fn is_sequence_start(&self, symbol: &Symbol<T>) -> bool {
         matches!(symbol, Symbol::RuleHead { .. } | Symbol::DocHead { .. })
     }
 
     /// Checks if a symbol marks the end of a sequence (RuleTail or DocTail).
     #[inline]
-    fn is_sequence_end(&self, symbol: &Symbol<T>) -> bool {
+    pub(crate) fn is_sequence_end(&self, symbol: &Symbol<T>) -> bool {
         matches!(symbol, Symbol::RuleTail | Symbol::DocTail)
     }
 
     /// Increments the count of a rule if the symbol is a RuleRef.
     #[inline]
-    fn increment_if_rule(&mut self, key: DefaultKey) {
+    pub(crate) fn increment_if_rule(&mut self, key: DefaultKey) {
         if let Symbol::RuleRef { rule_id } = self.symbols[key].symbol {
             if let Some(&head_key) = self.rule_index.get(&rule_id) {
                 self.increment_rule_count(head_key);
@@ -534,7 +709,7 @@ impl<T: Hash + Eq + Clone> Grammar<T> {
 
     /// Decrements the count of a rule if the symbol is a RuleRef.
     #[inline]
-    fn decrement_if_rule(&mut self, key: DefaultKey) {
+    pub(crate) fn decrement_if_rule(&mut self, key: DefaultKey) {
         if let Symbol::RuleRef { rule_id } = self.symbols[key].symbol {
             if let Some(&head_key) = self.rule_index.get(&rule_id) {
                 self.decrement_rule_count(head_key);
@@ -544,7 +719,7 @@ impl<T: Hash + Eq + Clone> Grammar<T> {
 
     /// Increments a rule's reference count.
     #[inline]
-    fn increment_rule_count(&mut self, head_key: DefaultKey) {
+    pub(crate) fn increment_rule_count(&mut self, head_key: DefaultKey) {
         if let Symbol::RuleHead {
             rule_id,
             count,
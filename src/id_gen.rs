@@ -1,7 +1,7 @@
 /// ID generator that reuses freed IDs to prevent exhaustion on long sequences.
 ///
 /// Mimics the behavior of the C++ implementation's ID class.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub(crate) struct IdGenerator {
     next: u32,
     freed: Vec<u32>,
@@ -17,10 +17,16 @@ impl IdGenerator {
     }
 
     /// Gets a new ID, reusing a freed one if available.
+    ///
+    /// # Panics
+    ///
+    /// Panics if every ID is in use; check [`available`](Self::available)
+    /// first to fail gracefully.
     pub(crate) fn get(&mut self) -> u32 {
         if let Some(id) = self.freed.pop() {
             id
         } else {
+            assert!(self.next < u32::MAX, "rule IDs exhausted");
             let id = self.next;
             self.next += 1;
             id
@@ -28,9 +34,29 @@ impl IdGenerator {
     }
 
     /// Marks an ID as freed, making it available for reuse.
-    pub(crate) fn free(&mut self, id: u32) {
-        assert!(id < self.next, "Cannot free ID that was never allocated");
+    ///
+    /// Returns false, leaving the generator unchanged, if the ID was never
+    /// allocated.
+    #[must_use = "an ID that was never allocated points at corrupt bookkeeping"]
+    pub(crate) fn free(&mut self, id: u32) -> bool {
+        if id >= self.next {
+            return false;
+        }
         self.freed.push(id);
+        true
+    }
+
+    /// Returns how many more IDs can be handed out.
+    pub(crate) fn available(&self) -> u64 {
+        u64::from(u32::MAX - self.next) + self.freed.len() as u64
+    }
+
+    /// Creates a generator whose next fresh ID is `next`, with no freed IDs.
+    pub(crate) fn starting_at(next: u32) -> Self {
+        Self {
+            next,
+            freed: Vec::new(),
+        }
     }
 }
 
@@ -57,20 +83,40 @@ mod tests {
         assert_eq!(id1, 1);
         assert_eq!(id2, 2);
 
-        gen.free(id1);
+        assert!(gen.free(id1));
         assert_eq!(gen.get(), 1); // Should reuse freed ID
 
-        gen.free(id0);
-        gen.free(id2);
+        assert!(gen.free(id0));
+        assert!(gen.free(id2));
         assert_eq!(gen.get(), 2); // LIFO order
         assert_eq!(gen.get(), 0);
     }
 
     #[test]
-    #[should_panic(expected = "Cannot free ID that was never allocated")]
     fn test_free_invalid_id() {
         let mut gen = IdGenerator::new();
         gen.get();
-        gen.free(999); // Should panic
+        assert!(!gen.free(999));
+        assert_eq!(gen.get(), 1); // The invalid ID wasn't added for reuse
+    }
+
+    #[test]
+    fn test_exhaustion() {
+        let mut gen = IdGenerator::starting_at(u32::MAX - 2);
+        assert_eq!(gen.available(), 2);
+        gen.get();
+        let last = gen.get();
+        assert_eq!(gen.available(), 0);
+
+        assert!(gen.free(last));
+        assert_eq!(gen.available(), 1);
+        assert_eq!(gen.get(), last);
+    }
+
+    #[test]
+    #[should_panic(expected = "rule IDs exhausted")]
+    fn test_get_past_exhaustion() {
+        let mut gen = IdGenerator::starting_at(u32::MAX);
+        gen.get();
     }
 }
@@ -0,0 +1,212 @@
+use crate::interner::Interner;
+use crate::iter::SequiturIter;
+use crate::sequitur::{CompressionStats, Sequitur};
+use std::borrow::Borrow;
+use std::hash::Hash;
+
+/// Sequitur over interned values.
+///
+/// Each distinct value is stored once and mapped to a dense `u32` ID; the grammar
+/// is built over the IDs. This avoids cloning and rehashing expensive values such
+/// as `String`s or large structs on every digram check, while iteration still
+/// yields `&T`.
+///
+/// # Example
+///
+/// ```
+/// use sequitur_rs::InternedSequitur;
+///
+/// let mut seq = InternedSequitur::<String>::new();
+/// for word in "one two one two one two".split(' ') {
+///     seq.push_ref(word);
+/// }
+///
+/// assert_eq!(seq.num_distinct_values(), 2);
+/// let words: Vec<&str> = seq.iter().map(String::as_str).collect();
+/// assert_eq!(words.join(" "), "one two one two one two");
+/// ```
+pub struct InternedSequitur<T> {
+    /// Grammar over value IDs
+    sequitur: Sequitur<u32>,
+
+    /// Value storage, indexed by ID
+    values: Interner<T>,
+}
+
+impl<T: Hash + Eq> InternedSequitur<T> {
+    /// Creates a new empty instance.
+    pub fn new() -> Self {
+        Self {
+            sequitur: Sequitur::new(),
+            values: Interner::new(),
+        }
+    }
+
+    /// Adds a value to the sequence.
+    ///
+    /// The value is dropped if an equal value has already been interned.
+    pub fn push(&mut self, value: T) {
+        let id = self.values.intern_owned(value);
+        self.sequitur.push(id);
+    }
+
+    /// Adds a borrowed value to the sequence, only converting it to an owned `T`
+    /// the first time it is seen.
+    pub fn push_ref<Q>(&mut self, value: &Q)
+    where
+        T: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = T> + ?Sized,
+    {
+        let id = self.values.intern(value);
+        self.sequitur.push(id);
+    }
+
+    /// Extends the sequence with multiple values.
+    pub fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for value in iter {
+            self.push(value);
+        }
+    }
+
+    /// Returns the ID assigned to a value, if it has been seen.
+    pub fn id_of<Q>(&self, value: &Q) -> Option<u32>
+    where
+        T: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.values.get(value)
+    }
+}
+
+impl<T> InternedSequitur<T> {
+    /// Returns the value for an ID.
+    pub fn value(&self, id: u32) -> Option<&T> {
+        self.values.resolve(id)
+    }
+
+    /// Returns the number of values added to the sequence.
+    pub fn len(&self) -> usize {
+        self.sequitur.len()
+    }
+
+    /// Returns true if no values have been added.
+    pub fn is_empty(&self) -> bool {
+        self.sequitur.is_empty()
+    }
+
+    /// Returns the number of distinct values stored.
+    pub fn num_distinct_values(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Returns the underlying grammar over value IDs.
+    pub fn sequitur(&self) -> &Sequitur<u32> {
+        &self.sequitur
+    }
+
+    /// Returns compression statistics.
+    pub fn stats(&self) -> CompressionStats {
+        self.sequitur.stats()
+    }
+
+    /// Returns an iterator over the reconstructed sequence.
+    pub fn iter(&self) -> InternedIter<'_, T> {
+        InternedIter {
+            ids: self.sequitur.iter(),
+            values: &self.values,
+        }
+    }
+}
+
+impl<T: Hash + Eq> Default for InternedSequitur<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Iterator over the values of an [`InternedSequitur`].
+pub struct InternedIter<'a, T> {
+    ids: SequiturIter<'a, u32>,
+    values: &'a Interner<T>,
+}
+
+impl<'a, T> Iterator for InternedIter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let &id = self.ids.next()?;
+        Some(
+            self.values
+                .resolve(id)
+                .expect("value IDs in the grammar should be interned"),
+        )
+    }
+}
+
+impl<'a, T> IntoIterator for &'a InternedSequitur<T> {
+    type Item = &'a T;
+    type IntoIter = InternedIter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip_strings() {
+        let words: Vec<String> = "a rose is a rose is a rose"
+            .split(' ')
+            .map(String::from)
+            .collect();
+
+        let mut seq = InternedSequitur::new();
+        seq.extend(words.clone());
+
+        let result: Vec<String> = seq.iter().cloned().collect();
+        assert_eq!(result, words);
+        assert_eq!(seq.len(), words.len());
+        assert_eq!(seq.num_distinct_values(), 3);
+    }
+
+    #[test]
+    fn test_push_ref() {
+        let mut seq = InternedSequitur::<String>::new();
+        seq.push_ref("x");
+        seq.push_ref("y");
+        seq.push_ref("x");
+
+        assert_eq!(seq.id_of("x"), Some(0));
+        assert_eq!(seq.id_of("y"), Some(1));
+        assert_eq!(seq.id_of("z"), None);
+        assert_eq!(seq.value(1).map(String::as_str), Some("y"));
+    }
+
+    #[test]
+    fn test_grammar_matches_plain_sequitur() {
+        let input: Vec<u64> = (0..200).map(|i| (i % 7) * 1_000_003).collect();
+
+        let mut interned = InternedSequitur::new();
+        interned.extend(input.iter().copied());
+
+        let mut plain = Sequitur::new();
+        plain.extend(input.iter().copied());
+
+        assert_eq!(interned.stats().num_rules, plain.stats().num_rules);
+        assert_eq!(
+            interned.stats().grammar_symbols,
+            plain.stats().grammar_symbols
+        );
+        assert!(interned.iter().eq(plain.iter()));
+    }
+
+    #[test]
+    fn test_empty() {
+        let seq = InternedSequitur::<String>::new();
+        assert!(seq.is_empty());
+        assert_eq!(seq.iter().count(), 0);
+    }
+}
@@ -0,0 +1,156 @@
+use ahash::AHashMap as HashMap;
+use ahash::RandomState;
+use std::borrow::Borrow;
+use std::hash::Hash;
+
+/// Sentinel marking the end of a hash chain.
+const NO_ID: u32 = u32::MAX;
+
+/// Maps values to dense `u32` IDs, storing each distinct value exactly once.
+///
+/// Values live in a single `Vec<T>` indexed by ID. The lookup table only holds
+/// 64-bit hashes pointing at the most recently interned ID with that hash; IDs
+/// whose hashes collide are chained through `chain`.
+#[derive(Debug, Clone)]
+pub(crate) struct Interner<T> {
+    /// Interned values, indexed by ID
+    values: Vec<T>,
+
+    /// Next ID with the same hash (or `NO_ID`), indexed by ID
+    chain: Vec<u32>,
+
+    /// Maps value hashes to the head of their chain
+    heads: HashMap<u64, u32>,
+
+    /// Hasher used for the lookup table
+    hasher: RandomState,
+}
+
+impl<T> Interner<T> {
+    /// Creates a new empty interner.
+    pub(crate) fn new() -> Self {
+        Self {
+            values: Vec::new(),
+            chain: Vec::new(),
+            heads: HashMap::default(),
+            hasher: RandomState::new(),
+        }
+    }
+
+    /// Returns the value for an ID, if it has been assigned.
+    #[inline]
+    pub(crate) fn resolve(&self, id: u32) -> Option<&T> {
+        self.values.get(id as usize)
+    }
+
+    /// Returns the number of distinct values interned.
+    pub(crate) fn len(&self) -> usize {
+        self.values.len()
+    }
+}
+
+impl<T: Hash + Eq> Interner<T> {
+    /// Returns the ID of a value if it has already been interned.
+    pub(crate) fn get<Q>(&self, value: &Q) -> Option<u32>
+    where
+        T: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = self.hasher.hash_one(value);
+        let mut id = *self.heads.get(&hash)?;
+        while id != NO_ID {
+            if self.values[id as usize].borrow() == value {
+                return Some(id);
+            }
+            id = self.chain[id as usize];
+        }
+        None
+    }
+
+    /// Returns the ID of a value, interning it if it is new.
+    pub(crate) fn intern_owned(&mut self, value: T) -> u32 {
+        match self.get(&value) {
+            Some(id) => id,
+            None => self.insert_new(value),
+        }
+    }
+
+    /// Returns the ID of a value, interning it if it is new.
+    ///
+    /// The value is only converted to an owned `T` when it has not been seen before.
+    pub(crate) fn intern<Q>(&mut self, value: &Q) -> u32
+    where
+        T: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = T> + ?Sized,
+    {
+        match self.get(value) {
+            Some(id) => id,
+            None => self.insert_new(value.to_owned()),
+        }
+    }
+
+    /// Stores a value known not to be interned yet.
+    fn insert_new(&mut self, value: T) -> u32 {
+        let id = u32::try_from(self.values.len())
+            .ok()
+            .filter(|&id| id != NO_ID)
+            .expect("Interner cannot hold more than u32::MAX - 1 values");
+        let hash = self.hasher.hash_one(&value);
+        let previous = self.heads.insert(hash, id).unwrap_or(NO_ID);
+        self.values.push(value);
+        self.chain.push(previous);
+        id
+    }
+}
+
+impl<T> Default for Interner<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dense_ids() {
+        let mut interner: Interner<String> = Interner::new();
+        assert_eq!(interner.intern("a"), 0);
+        assert_eq!(interner.intern("b"), 1);
+        assert_eq!(interner.intern("a"), 0);
+        assert_eq!(interner.len(), 2);
+    }
+
+    #[test]
+    fn test_resolve() {
+        let mut interner = Interner::new();
+        let id = interner.intern_owned(42u64);
+        assert_eq!(interner.resolve(id), Some(&42));
+        assert_eq!(interner.resolve(id + 1), None);
+    }
+
+    #[test]
+    fn test_borrowed_lookup() {
+        let mut interner: Interner<String> = Interner::new();
+        let id = interner.intern("hello");
+        assert_eq!(interner.get("hello"), Some(id));
+        assert_eq!(interner.intern("hello"), id);
+        assert_eq!(interner.get("world"), None);
+    }
+
+    #[test]
+    fn test_lookup_walks_chain() {
+        let mut interner = Interner::new();
+        let a = interner.intern(&1u32);
+        let b = interner.intern(&2u32);
+
+        // Simulate a collision: make `2` the head of `1`'s chain.
+        let hash_a = interner.hasher.hash_one(1u32);
+        interner.heads.insert(hash_a, b);
+        interner.chain[b as usize] = a;
+
+        assert_eq!(interner.get(&1), Some(a));
+        assert_eq!(interner.get(&2), Some(b));
+    }
+}
@@ -1,8 +1,11 @@
+use crate::error::SequiturError;
 use crate::grammar::Grammar;
 use crate::sequitur::Sequitur;
 use crate::symbol::Symbol;
+use ahash::AHashMap as HashMap;
 use slotmap::DefaultKey;
 use std::hash::Hash;
+use std::iter::FusedIterator;
 
 /// Iterator that reconstructs the original sequence by expanding rules.
 ///
@@ -11,23 +14,51 @@ pub struct SequiturIter<'a, T> {
     grammar: &'a Grammar<T>,
     current: Option<DefaultKey>,
     stack: Vec<DefaultKey>,
+    /// Next value from the back, and the RuleRefs it was reached through
+    back: Option<DefaultKey>,
+    back_stack: Vec<DefaultKey>,
+    /// Values not yet yielded from either end
+    remaining: usize,
+    /// Expanded rule lengths, filled in as [`nth`](Iterator::nth) needs them
+    lengths: HashMap<u32, usize>,
 }
 
 impl<'a, T: Hash + Eq + Clone> SequiturIter<'a, T> {
     pub(crate) fn new(sequitur: &'a Sequitur<T>) -> Self {
-        // Start at Rule 0's first symbol
-        let rule_0_head = *sequitur.rules().get(&0).expect("Rule 0 should exist");
-        let start = sequitur.grammar.symbols[rule_0_head]
-            .next
-            .expect("Rule 0 should have content");
+        // Start at Rule 0's first symbol, and at its last symbol for iteration
+        // from the back. Without Rule 0 the grammar is corrupt; yield nothing
+        // rather than panic, and leave reporting it to `try_iter`.
+        let symbols = &sequitur.grammar.symbols;
+        let ends = sequitur.rules().get(&0).and_then(|&head| {
+            let start = symbols.get(head)?.next?;
+            let end = symbols.get(sequitur.sequence_end)?.prev?;
+            Some((start, end))
+        });
+        let Some((start, end)) = ends else {
+            return Self {
+                grammar: &sequitur.grammar,
+                current: None,
+                stack: Vec::new(),
+                back: None,
+                back_stack: Vec::new(),
+                remaining: 0,
+                lengths: HashMap::default(),
+            };
+        };
 
         let mut stack = Vec::new();
         let current = Self::resolve_forward(&sequitur.grammar, start, &mut stack);
+        let mut back_stack = Vec::new();
+        let back = Self::resolve_backward(&sequitur.grammar, end, &mut back_stack);
 
         Self {
             grammar: &sequitur.grammar,
             current,
             stack,
+            back,
+            back_stack,
+            remaining: sequitur.length,
+            lengths: HashMap::default(),
         }
     }
 
@@ -79,13 +110,55 @@ impl<'a, T: Hash + Eq + Clone> SequiturIter<'a, T> {
             }
         }
     }
+
+    /// Resolves backward through rules to find the previous Value symbol.
+    ///
+    /// Mirrors [`resolve_forward`](Self::resolve_forward), descending into
+    /// rules from their tails.
+    fn resolve_backward(
+        grammar: &Grammar<T>,
+        key: DefaultKey,
+        stack: &mut Vec<DefaultKey>,
+    ) -> Option<DefaultKey> {
+        match &grammar.symbols[key].symbol {
+            Symbol::Value(_) => Some(key),
+
+            Symbol::RuleRef { rule_id } => {
+                // Push current position and descend into rule from its end
+                stack.push(key);
+                let rule_head = *grammar.rule_index.get(rule_id)?;
+                let Symbol::RuleHead { tail, .. } = grammar.symbols[rule_head].symbol else {
+                    unreachable!("rule index should point at RuleHeads");
+                };
+                let rule_last = grammar.symbols[tail].prev?;
+                Self::resolve_backward(grammar, rule_last, stack)
+            }
+
+            Symbol::RuleTail | Symbol::DocTail => {
+                // Skip past tail
+                let prev = grammar.symbols[key].prev?;
+                Self::resolve_backward(grammar, prev, stack)
+            }
+
+            Symbol::RuleHead { .. } | Symbol::DocHead { .. } => {
+                // Start of rule, pop stack and continue before the reference
+                let parent = stack.pop()?;
+                let prev = grammar.symbols[parent].prev?;
+                Self::resolve_backward(grammar, prev, stack)
+            }
+        }
+    }
 }
 
 impl<'a, T: Hash + Eq + Clone> Iterator for SequiturIter<'a, T> {
     type Item = &'a T;
 
     fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
         let current_key = self.current?;
+        self.remaining -= 1;
 
         // Extract the value
         let value = match &self.grammar.symbols[current_key].symbol {
@@ -99,6 +172,111 @@ impl<'a, T: Hash + Eq + Clone> Iterator for SequiturIter<'a, T> {
 
         Some(value)
     }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+
+    fn count(self) -> usize {
+        self.remaining
+    }
+
+    fn last(mut self) -> Option<Self::Item> {
+        self.next_back()
+    }
+
+    /// Skips `n` values, stepping over whole rule expansions where possible.
+    fn nth(&mut self, n: usize) -> Option<Self::Item> {
+        if n >= self.remaining {
+            self.remaining = 0;
+            return None;
+        }
+        if n > 0 {
+            let next_key = self.grammar.symbols[self.current?].next?;
+            let key = self
+                .grammar
+                .skip_values(next_key, n - 1, &mut self.stack, &mut self.lengths);
+            self.current = Self::resolve_forward(self.grammar, key, &mut self.stack);
+            self.remaining -= n;
+        }
+        self.next()
+    }
+}
+
+impl<T: Hash + Eq + Clone> DoubleEndedIterator for SequiturIter<'_, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let back_key = self.back?;
+        self.remaining -= 1;
+
+        let value = match &self.grammar.symbols[back_key].symbol {
+            Symbol::Value(v) => v,
+            _ => unreachable!("resolve_backward should only return Value symbols"),
+        };
+
+        // Move to previous symbol
+        let prev_key = self.grammar.symbols[back_key].prev?;
+        self.back = Self::resolve_backward(self.grammar, prev_key, &mut self.back_stack);
+
+        Some(value)
+    }
+}
+
+impl<T: Hash + Eq + Clone> ExactSizeIterator for SequiturIter<'_, T> {}
+
+impl<T: Hash + Eq + Clone> FusedIterator for SequiturIter<'_, T> {}
+
+impl<T: Hash + Eq + Clone> Grammar<T> {
+    /// Walks forward from `key` past `n` values and returns the key that
+    /// follows them, without resolving it to a value.
+    ///
+    /// RuleRefs whose expansion fits within the remaining count are stepped
+    /// over using `lengths`, which caches expanded rule lengths. Rules that are
+    /// entered are pushed onto `stack`, as the iterators' `resolve_forward`
+    /// does. At least `n` values must follow `key`.
+    pub(crate) fn skip_values(
+        &self,
+        mut key: DefaultKey,
+        mut n: usize,
+        stack: &mut Vec<DefaultKey>,
+        lengths: &mut HashMap<u32, usize>,
+    ) -> DefaultKey {
+        while n > 0 {
+            match self.symbols[key].symbol {
+                Symbol::Value(_) => {
+                    n -= 1;
+                    key = self.symbols[key].next.expect("Value should have next");
+                }
+
+                Symbol::RuleRef { rule_id } => {
+                    let len = self.rule_length(rule_id, lengths);
+                    if len <= n {
+                        // Step over the whole expansion
+                        n -= len;
+                        key = self.symbols[key].next.expect("RuleRef should have next");
+                    } else {
+                        // The target lies inside this rule
+                        stack.push(key);
+                        key = self.symbols[self.rule_index[&rule_id]]
+                            .next
+                            .expect("RuleHead should have next");
+                    }
+                }
+
+                Symbol::RuleHead { .. } | Symbol::DocHead { .. } => {
+                    key = self.symbols[key].next.expect("Head should have next");
+                }
+
+                Symbol::RuleTail | Symbol::DocTail => {
+                    let parent = stack.pop().expect("fewer values remain than skipped");
+                    key = self.symbols[parent].next.expect("RuleRef should have next");
+                }
+            }
+        }
+        key
+    }
 }
 
 impl<T: Hash + Eq + Clone> Sequitur<T> {
@@ -106,6 +284,19 @@ impl<T: Hash + Eq + Clone> Sequitur<T> {
     pub fn iter(&self) -> SequiturIter<'_, T> {
         SequiturIter::new(self)
     }
+
+    /// Returns an iterator over the reconstructed sequence after checking that
+    /// the grammar is consistent, so iteration can't panic or stop early.
+    ///
+    /// The check walks the whole grammar, so prefer [`iter`](Self::iter) for
+    /// grammars that were built rather than restored.
+    pub fn try_iter(&self) -> Result<SequiturIter<'_, T>, SequiturError> {
+        let Some(&head) = self.grammar.rule_index.get(&0) else {
+            return Err(SequiturError::CorruptGrammar("rule 0 is missing".into()));
+        };
+        self.grammar.validate(&[(head, self.length)], Some(0))?;
+        Ok(self.iter())
+    }
 }
 
 impl<'a, T: Hash + Eq + Clone> IntoIterator for &'a Sequitur<T> {
@@ -159,4 +350,54 @@ mod tests {
         let collected: Vec<&i32> = (&seq).into_iter().collect();
         assert_eq!(collected, vec![&1, &2, &3]);
     }
+
+    #[test]
+    fn test_iter_rev() {
+        let mut seq = Sequitur::new();
+        seq.extend("abcabcabdabcabd".chars());
+        let reversed: String = seq.iter().rev().collect();
+        assert_eq!(reversed, "dbacbadbacbacba");
+        assert_eq!(Sequitur::<char>::new().iter().next_back(), None);
+    }
+
+    #[test]
+    fn test_iter_both_ends_meet() {
+        let mut seq = Sequitur::new();
+        seq.extend("abcabcabc".chars());
+        let mut iter = seq.iter();
+        assert_eq!(iter.next(), Some(&'a'));
+        assert_eq!(iter.next_back(), Some(&'c'));
+        assert_eq!(iter.next_back(), Some(&'b'));
+        let middle: String = iter.by_ref().collect();
+        assert_eq!(middle, "bcabca");
+        assert_eq!(iter.next_back(), None);
+    }
+
+    #[test]
+    fn test_iter_exact_size() {
+        let mut seq = Sequitur::new();
+        seq.extend("abcabcabc".chars());
+        let mut iter = seq.iter();
+        assert_eq!(iter.len(), 9);
+        iter.next();
+        iter.next_back();
+        assert_eq!(iter.size_hint(), (7, Some(7)));
+        assert_eq!(iter.by_ref().count(), 7);
+        assert_eq!(iter.next(), None);
+        assert_eq!(iter.next_back(), None);
+    }
+
+    #[test]
+    fn test_iter_nth_skips_rules() {
+        let input: Vec<u32> = (0..2000).map(|i| i % 17).collect();
+        let mut seq = Sequitur::new();
+        seq.extend(input.iter().copied());
+
+        let mut iter = seq.iter();
+        assert_eq!(iter.next(), Some(&input[0]));
+        assert_eq!(iter.nth(1234), Some(&input[1235]));
+        assert_eq!(iter.len(), 2000 - 1236);
+        assert!(iter.skip(500).eq(input[1736..].iter()));
+        assert_eq!(seq.iter().nth(2000), None);
+    }
 }
@@ -44,23 +44,123 @@
 //! // Reconstruction still works correctly
 //! let result: String = seq.iter().collect();
 //! assert_eq!(result.len(), 100);
+//!
+//! // Or read it back as runs without expanding them
+//! assert_eq!(seq.iter_runs().collect::<Vec<_>>(), [(&'x', 100)]);
 //! ```
 //!
+//! [`Sequitur`] and [`SequiturRle`], and likewise [`SequiturDocuments`] and
+//! [`SequiturDocumentsRle`], convert into each other with `From` without
+//! expanding the sequence.
+//!
+//! ## Configuration
+//!
+//! [`Sequitur::builder`] relaxes the constraints: `min_rule_uses(k)` only keeps
+//! rules used at least `k` times, and the experimental `min_rule_length(n)` only
+//! creates rules for repeats expanding to at least `n` values. Either setting
+//! lets digrams repeat and makes construction somewhat slower.
+//!
+//! ## Concurrency
+//!
+//! All grammar types are `Send` and `Sync` when their values are.
+//! [`ConcurrentSequiturDocuments`] lets many threads append to different
+//! documents at once by sharding documents over independently locked grammars,
+//! and [`SequiturDocuments::merge`] combines separately built collections.
+//! [`Sequitur::freeze`] and [`SequiturDocuments::freeze_document`] take an
+//! immutable [`FrozenGrammar`] snapshot that is cheap to clone and whose
+//! iterators own their data, so decompression can move to other threads.
+//! Snapshots store rules contiguously with their expanded lengths for fast
+//! iteration and random access, serialize with [`FrozenGrammar::to_bytes`],
+//! and [`FrozenGrammar::thaw`] back into a mutable grammar.
+//! [`Sequitur::write_mapped`] and [`SequiturDocuments::write_mapped`] write a
+//! layout of fixed-width tables that [`MappedGrammar`] reads in place, for
+//! example from a memory-mapped file.
+//!
+//! ## Storage
+//!
+//! [`BlobStore`] keeps byte strings under string keys in a
+//! [`SequiturDocuments`], persisted through an append-only log and periodic
+//! checkpoints of the shared grammar, and recovers from crashes on open.
+//!
+//! ## Offline Compression
+//!
+//! [`Sequitur::compress`] selects the construction [`Algorithm`]: online
+//! Sequitur, or offline Re-Pair, which needs the whole input up front but usually
+//! yields a smaller grammar. Both produce an ordinary [`Sequitur`].
+//! [`Sequitur::optimize`] shrinks an online grammar after the fact by inlining
+//! rules that do not pay for themselves and re-pairing frequent digrams.
+//! [`Sequitur::build_parallel`] compresses chunks of a large input on separate
+//! threads and merges the chunk grammars.
+//!
+//! ## Text
+//!
+//! [`TextSequitur`] tokenizes natural-language input into words, whitespace and
+//! punctuation, and lists the discovered phrase hierarchy as strings.
+//!
+//! ```
+//! use sequitur_rs::TextSequitur;
+//!
+//! let mut text = TextSequitur::new();
+//! text.push_str("the cat and the hat and the cat and the hat");
+//!
+//! for phrase in text.phrases() {
+//!     println!("R{} ({} uses): {:?}", phrase.rule_id, phrase.uses, phrase.text);
+//! }
+//! ```
+//!
+//! ## Time Series
+//!
+//! [`SaxGrammar`] discretizes a numeric series with SAX over sliding windows and
+//! builds a grammar over the words; [`SaxGrammar::rule_intervals`] maps rule
+//! occurrences back to ranges of the original series.
+//!
+//! `rule_density` counts the rule occurrences covering each position of a
+//! sequence, document or series; positions covered by few rules are anomaly
+//! candidates.
+//!
+//! ## Error Handling
+//!
+//! The `try_*` methods, such as [`Sequitur::try_push`] and
+//! [`Sequitur::try_iter`], return a [`SequiturError`] instead of panicking when
+//! rule IDs run out, a length would overflow, or a grammar is inconsistent.
+//!
 //! ## Performance
 //!
 //! - O(1) amortized time per symbol added
 //! - Grammar size grows sub-linearly with input size for repetitive data
 //! - Memory-efficient using generational indices (SlotMap)
 
+mod builder;
+mod compact;
+mod concurrent;
+mod dedup;
+mod density;
 mod documents;
 mod documents_iter;
+mod encoding;
+mod error;
+mod frozen;
 mod grammar;
 mod id_gen;
+mod interned;
+mod interner;
 mod iter;
+mod mapped;
+mod merge;
+mod occurrence;
+mod optimize;
+mod parallel;
+mod relaxed;
+mod repair;
 mod sequitur;
+mod store;
 mod symbol;
+mod text;
+mod timeseries;
+mod validate;
 
 // RLE (Run-Length Encoding) Sequitur modules
+mod rle_convert;
 mod rle_documents;
 mod rle_documents_iter;
 mod rle_grammar;
@@ -71,13 +171,29 @@ mod rle_symbol;
 #[cfg(test)]
 mod tests;
 
+pub use builder::SequiturBuilder;
+pub use concurrent::ConcurrentSequiturDocuments;
+pub use dedup::{DedupReport, SharedRule};
+pub use density::DensityInterval;
 pub use documents::{DocumentStats, OverallStats, SequiturDocuments};
 pub use documents_iter::DocumentIter;
+pub use encoding::FixedWidth;
+pub use error::SequiturError;
+pub use frozen::{FrozenGrammar, FrozenIter};
+pub use interned::{InternedIter, InternedSequitur};
 pub use iter::SequiturIter;
+pub use mapped::{MappedGrammar, MappedIter};
+pub use merge::DocConflict;
+pub use occurrence::RuleOccurrence;
+pub use optimize::OptimizationReport;
+pub use repair::Algorithm;
 pub use sequitur::{CompressionStats, Sequitur};
+pub use store::BlobStore;
+pub use text::{Phrase, PhrasePart, TextSequitur};
+pub use timeseries::{NumerosityReduction, RuleInterval, SaxConfig, SaxGrammar, SaxWord};
 
 // RLE exports
 pub use rle_documents::{RleDocumentStats, RleOverallStats, SequiturDocumentsRle};
-pub use rle_documents_iter::RleDocumentIter;
-pub use rle_iter::RleSequiturIter;
+pub use rle_documents_iter::{RleDocumentIter, RleDocumentRunIter};
+pub use rle_iter::{RleRunIter, RleSequiturIter};
 pub use rle_sequitur::{RleCompressionStats, SequiturRle};
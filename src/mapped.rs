@@ -0,0 +1,598 @@
+use crate::documents::{OverallStats, SequiturDocuments};
+use crate::encoding::{corrupt, u32_at, usize_at, value_at, FixedWidth, Reader};
+use crate::error::SequiturError;
+use crate::frozen::{
+    code_ends, expanded_lengths, validate_rules, FrozenRules, RuleTables, RULE_FLAG,
+};
+use crate::sequitur::Sequitur;
+use slotmap::DefaultKey;
+use std::hash::Hash;
+use std::io::{self, BufWriter, Write};
+use std::iter::FusedIterator;
+use std::marker::PhantomData;
+
+/// Identifies grammars written for [`MappedGrammar`].
+const MAGIC: &[u8; 4] = b"SQMG";
+
+/// Version of the mapped layout.
+const VERSION: u32 = 2;
+
+/// A read-only view of a serialized grammar, used in place without
+/// deserializing it.
+///
+/// The layout starts with a fixed header followed by fixed-width,
+/// little-endian tables: the values, the document IDs in ascending byte order,
+/// the symbol codes of every rule body back to back, the offset of each body,
+/// the expanded length of each rule and the expanded length of each body up to
+/// each code. Document `i` is rule `i`. Any byte slice holding that layout can
+/// be viewed, such as a memory-mapped file, and every table is validated once
+/// by [`open`](Self::open), so reading never copies the grammar. Lookups
+/// binary-search one body per level of the grammar.
+///
+/// Files are written by [`Sequitur::write_mapped`], which stores a single
+/// sequence under the document ID `()`, and
+/// [`SequiturDocuments::write_mapped`].
+///
+/// # Example
+///
+/// ```
+/// use sequitur_rs::{MappedGrammar, SequiturDocuments};
+///
+/// let mut docs = SequiturDocuments::<u8, u32>::new();
+/// docs.extend_document(7, b"abcabcabc".iter().copied());
+/// docs.extend_document(9, b"xabcx".iter().copied());
+///
+/// let mut bytes = Vec::new();
+/// docs.write_mapped(&mut bytes).unwrap();
+///
+/// // `bytes` could equally be a memory-mapped file.
+/// let mapped = MappedGrammar::<u8, u32>::open(&bytes).unwrap();
+/// assert!(mapped.iter_document(&9).unwrap().eq(b"xabcx".iter().copied()));
+/// assert_eq!(mapped.get(10), Some(b'a'));
+/// assert_eq!(mapped.stats().num_documents, 2);
+/// ```
+pub struct MappedGrammar<'a, T, DocId = ()> {
+    rules: MappedRules<'a>,
+    /// Encoded document IDs, in ascending byte order
+    doc_ids: &'a [u8],
+    num_docs: usize,
+    /// Total number of values over all documents
+    length: usize,
+    _marker: PhantomData<fn() -> (T, DocId)>,
+}
+
+/// The rule tables of a mapped grammar.
+#[derive(Clone, Copy)]
+struct MappedRules<'a> {
+    values: &'a [u8],
+    codes: &'a [u8],
+    offsets: &'a [u8],
+    lengths: &'a [u8],
+    ends: &'a [u8],
+}
+
+impl MappedRules<'_> {
+    fn value<T: FixedWidth>(&self, code: u32) -> T {
+        value_at(self.values, code as usize).expect("values are validated on open")
+    }
+}
+
+impl RuleTables for MappedRules<'_> {
+    fn code(&self, pos: usize) -> u32 {
+        u32_at(self.codes, pos)
+    }
+
+    fn span(&self, rule: usize) -> (usize, usize) {
+        (
+            usize_at(self.offsets, rule),
+            usize_at(self.offsets, rule + 1),
+        )
+    }
+
+    fn length(&self, rule: usize) -> usize {
+        usize_at(self.lengths, rule)
+    }
+
+    fn end(&self, pos: usize) -> usize {
+        usize_at(self.ends, pos)
+    }
+}
+
+impl<T, DocId> Clone for MappedGrammar<'_, T, DocId> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T, DocId> Copy for MappedGrammar<'_, T, DocId> {}
+
+impl<'a, T: FixedWidth, DocId: FixedWidth> MappedGrammar<'a, T, DocId> {
+    /// Validates `bytes` and returns a view of the grammar they hold.
+    ///
+    /// Returns [`SequiturError::CorruptGrammar`] if the bytes are truncated,
+    /// were written for values or document IDs of a different width, or don't
+    /// describe a valid grammar. Validation reads every table once; afterwards
+    /// no access can fail.
+    pub fn open(bytes: &'a [u8]) -> Result<Self, SequiturError> {
+        let mut reader = Reader::new(bytes);
+        if reader.take(4)? != MAGIC {
+            return Err(corrupt("not a mapped grammar"));
+        }
+        let version = reader.u32()?;
+        if version != VERSION {
+            return Err(corrupt(format!("unsupported version {version}")));
+        }
+        let value_width = reader.u32()?;
+        let doc_id_width = reader.u32()?;
+        if value_width as usize != T::WIDTH || doc_id_width as usize != DocId::WIDTH {
+            return Err(corrupt(format!(
+                "values and document IDs are {value_width} and {doc_id_width} bytes wide, \
+                 expected {} and {}",
+                T::WIDTH,
+                DocId::WIDTH
+            )));
+        }
+        let num_values = reader.usize()?;
+        let num_codes = reader.usize()?;
+        let num_rules = reader.usize()?;
+        let num_docs = reader.usize()?;
+        if num_docs > num_rules {
+            return Err(corrupt("more documents than rules"));
+        }
+        if num_rules >= RULE_FLAG as usize || num_values >= RULE_FLAG as usize {
+            return Err(corrupt("too many rules or values"));
+        }
+
+        let values = reader.take_table(num_values, T::WIDTH)?;
+        let doc_ids = reader.take_table(num_docs, DocId::WIDTH)?;
+        let codes = reader.take_table(num_codes, 4)?;
+        let offsets = reader.take_table(num_rules + 1, 8)?;
+        let lengths = reader.take_table(num_rules, 8)?;
+        let ends = reader.take_table(num_codes, 8)?;
+        if !reader.rest().is_empty() {
+            return Err(corrupt("trailing bytes"));
+        }
+
+        if (0..num_values).any(|i| value_at::<T>(values, i).is_none()) {
+            return Err(corrupt("invalid value"));
+        }
+        let doc_id = |i: usize| &doc_ids[i * DocId::WIDTH..(i + 1) * DocId::WIDTH];
+        for i in 0..num_docs {
+            if value_at::<DocId>(doc_ids, i).is_none() {
+                return Err(corrupt("invalid document ID"));
+            }
+            if i > 0 && doc_id(i - 1) >= doc_id(i) {
+                return Err(corrupt("document IDs are not sorted and unique"));
+            }
+        }
+
+        let mut offset_reader = Reader::new(offsets);
+        let rule_offsets = (0..=num_rules)
+            .map(|_| offset_reader.usize())
+            .collect::<Result<Vec<_>, _>>()?;
+        let code_at = |i| u32_at(codes, i);
+        validate_rules(code_at, num_codes, &rule_offsets, num_docs, num_values)?;
+        let rule_lengths = expanded_lengths(code_at, &rule_offsets, num_docs)
+            .ok_or_else(|| corrupt("rule length overflows usize"))?;
+        let mut length_reader = Reader::new(lengths);
+        for (rule, &len) in rule_lengths.iter().enumerate() {
+            if length_reader.usize()? != len {
+                return Err(corrupt(format!("rule {rule} has the wrong length")));
+            }
+        }
+        let length = rule_lengths[..num_docs]
+            .iter()
+            .try_fold(0usize, |total, &len| total.checked_add(len))
+            .ok_or_else(|| corrupt("total length overflows usize"))?;
+        let code_ends = code_ends(code_at, &rule_offsets, &rule_lengths, num_docs);
+        for (pos, end) in code_ends.enumerate() {
+            if end != Some(usize_at(ends, pos)) {
+                return Err(corrupt(format!("code {pos} has the wrong end")));
+            }
+        }
+
+        Ok(Self {
+            rules: MappedRules {
+                values,
+                codes,
+                offsets,
+                lengths,
+                ends,
+            },
+            doc_ids,
+            num_docs,
+            length,
+            _marker: PhantomData,
+        })
+    }
+
+    /// Returns the total number of values over all documents.
+    pub fn len(&self) -> usize {
+        self.length
+    }
+
+    /// Returns true if no document holds any values.
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    /// Returns the number of documents.
+    pub fn num_documents(&self) -> usize {
+        self.num_docs
+    }
+
+    /// Returns an iterator over every document, back to back in ascending
+    /// order of their encoded IDs.
+    ///
+    /// For a grammar written by [`Sequitur::write_mapped`] this is the
+    /// sequence.
+    pub fn iter(&self) -> MappedIter<'a, T> {
+        MappedIter::new(self.rules, (0, self.roots_end()), self.length)
+    }
+
+    /// Returns the value at `index` of [`iter`](Self::iter), or `None` if it
+    /// is out of bounds.
+    pub fn get(&self, index: usize) -> Option<T> {
+        if index >= self.length {
+            return None;
+        }
+        let code = self.rules.find((0, self.roots_end()), index)?;
+        Some(self.rules.value(code))
+    }
+
+    /// Returns an iterator over a document, or `None` if it doesn't exist.
+    ///
+    /// Documents are found by binary search over the sorted ID table.
+    pub fn iter_document(&self, doc_id: &DocId) -> Option<MappedIter<'a, T>> {
+        let doc = self.find_document(doc_id)?;
+        Some(MappedIter::new(
+            self.rules,
+            self.rules.span(doc),
+            self.rules.length(doc),
+        ))
+    }
+
+    /// Returns the IDs of all documents, in ascending order of their
+    /// encodings.
+    pub fn document_ids(&self) -> impl Iterator<Item = DocId> + 'a {
+        let doc_ids = self.doc_ids;
+        (0..self.num_docs)
+            .map(move |i| value_at(doc_ids, i).expect("document IDs are validated on open"))
+    }
+
+    /// Returns statistics about the stored grammar.
+    ///
+    /// Documents are not counted as rules.
+    pub fn stats(&self) -> OverallStats {
+        OverallStats {
+            total_input_length: self.length,
+            total_grammar_symbols: self.rules.codes.len() / 4,
+            num_rules: self.rules.lengths.len() / 8 - self.num_docs,
+            num_documents: self.num_docs,
+        }
+    }
+
+    /// End of the document bodies, which are stored first.
+    fn roots_end(&self) -> usize {
+        usize_at(self.rules.offsets, self.num_docs)
+    }
+
+    fn find_document(&self, doc_id: &DocId) -> Option<usize> {
+        let mut key = vec![0; DocId::WIDTH];
+        doc_id.write_le(&mut key);
+        let id_at = |i: usize| &self.doc_ids[i * DocId::WIDTH..(i + 1) * DocId::WIDTH];
+
+        let (mut low, mut high) = (0, self.num_docs);
+        while low < high {
+            let mid = low + (high - low) / 2;
+            match id_at(mid).cmp(&key[..]) {
+                std::cmp::Ordering::Less => low = mid + 1,
+                std::cmp::Ordering::Greater => high = mid,
+                std::cmp::Ordering::Equal => return Some(mid),
+            }
+        }
+        None
+    }
+}
+
+/// Iterator over values of a [`MappedGrammar`], decoding them on the fly.
+pub struct MappedIter<'a, T> {
+    rules: MappedRules<'a>,
+    /// (next position, end) of each rule body being expanded
+    stack: Vec<(usize, usize)>,
+    /// Values left to yield
+    remaining: usize,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<'a, T> MappedIter<'a, T> {
+    fn new(rules: MappedRules<'a>, span: (usize, usize), length: usize) -> Self {
+        Self {
+            rules,
+            stack: vec![span],
+            remaining: length,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T: FixedWidth> Iterator for MappedIter<'_, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (pos, end) = self.stack.last_mut()?;
+            if *pos == *end {
+                self.stack.pop();
+                continue;
+            }
+            let code = self.rules.code(*pos);
+            *pos += 1;
+            if code & RULE_FLAG != 0 {
+                let span = self.rules.span((code & !RULE_FLAG) as usize);
+                self.stack.push(span);
+            } else {
+                self.remaining -= 1;
+                return Some(self.rules.value(code));
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+
+    fn count(self) -> usize {
+        self.remaining
+    }
+
+    /// Skips whole rules that end before the `n`th value without expanding
+    /// them, binary-searching each body on the way down.
+    fn nth(&mut self, n: usize) -> Option<Self::Item> {
+        if n >= self.remaining {
+            self.remaining = 0;
+            self.stack.clear();
+            return None;
+        }
+        self.remaining -= n;
+        self.rules.skip(&mut self.stack, n);
+        self.next()
+    }
+}
+
+impl<T: FixedWidth> ExactSizeIterator for MappedIter<'_, T> {}
+
+impl<T: FixedWidth> FusedIterator for MappedIter<'_, T> {}
+
+/// Writes flattened rules whose first rules are the documents with the given
+/// encoded IDs, which must be sorted and unique.
+fn write_rules<T: FixedWidth, W: Write>(
+    rules: &FrozenRules<T>,
+    doc_ids: &[Vec<u8>],
+    doc_id_width: usize,
+    writer: W,
+) -> io::Result<()> {
+    let mut writer = BufWriter::new(writer);
+    writer.write_all(MAGIC)?;
+    writer.write_all(&VERSION.to_le_bytes())?;
+    writer.write_all(&(T::WIDTH as u32).to_le_bytes())?;
+    writer.write_all(&(doc_id_width as u32).to_le_bytes())?;
+    for count in [
+        rules.values.len(),
+        rules.codes.len(),
+        rules.lengths.len(),
+        doc_ids.len(),
+    ] {
+        writer.write_all(&(count as u64).to_le_bytes())?;
+    }
+
+    let mut value = vec![0; T::WIDTH];
+    for v in &rules.values {
+        v.write_le(&mut value);
+        writer.write_all(&value)?;
+    }
+    for doc_id in doc_ids {
+        writer.write_all(doc_id)?;
+    }
+    for code in &rules.codes {
+        writer.write_all(&code.to_le_bytes())?;
+    }
+    for &n in rules
+        .offsets
+        .iter()
+        .chain(&rules.lengths)
+        .chain(&rules.ends)
+    {
+        writer.write_all(&(n as u64).to_le_bytes())?;
+    }
+    writer.flush()
+}
+
+impl<T: Hash + Eq + Clone + FixedWidth> Sequitur<T> {
+    /// Writes the grammar in the layout read by [`MappedGrammar`], as a
+    /// single document with ID `()`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use sequitur_rs::{MappedGrammar, Sequitur};
+    ///
+    /// let mut seq = Sequitur::new();
+    /// seq.extend("abcabdabcabd".chars());
+    ///
+    /// let mut bytes = Vec::new();
+    /// seq.write_mapped(&mut bytes).unwrap();
+    ///
+    /// let mapped = MappedGrammar::<char>::open(&bytes).unwrap();
+    /// assert_eq!(mapped.iter().collect::<String>(), "abcabdabcabd");
+    /// assert_eq!(mapped.get(5), Some('d'));
+    /// ```
+    pub fn write_mapped<W: Write>(&self, writer: W) -> io::Result<()> {
+        let rules = self.grammar.flatten(&[self.grammar.rule_index[&0]]);
+        write_rules(&rules, &[Vec::new()], 0, writer)
+    }
+}
+
+impl<T, DocId> SequiturDocuments<T, DocId>
+where
+    T: Hash + Eq + Clone + FixedWidth,
+    DocId: Hash + Eq + Clone + FixedWidth,
+{
+    /// Writes every document and the shared rules in the layout read by
+    /// [`MappedGrammar`].
+    pub fn write_mapped<W: Write>(&self, writer: W) -> io::Result<()> {
+        let mut docs: Vec<(Vec<u8>, DefaultKey)> = self
+            .documents
+            .iter()
+            .map(|(doc_id, info)| {
+                let mut encoded = vec![0; DocId::WIDTH];
+                doc_id.write_le(&mut encoded);
+                (encoded, info.head)
+            })
+            .collect();
+        docs.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+
+        let heads: Vec<_> = docs.iter().map(|(_, head)| *head).collect();
+        let doc_ids: Vec<_> = docs.into_iter().map(|(doc_id, _)| doc_id).collect();
+        let rules = self.grammar.flatten(&heads);
+        write_rules(&rules, &doc_ids, DocId::WIDTH, writer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn mapped_bytes(docs: &SequiturDocuments<u8, u64>) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        docs.write_mapped(&mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn test_mapped_file_roundtrip() {
+        let input: Vec<u16> = (0..5000).map(|i| (i * 7 + i / 13) % 11).collect();
+        let mut seq = Sequitur::new();
+        seq.extend(input.iter().copied());
+
+        let path = std::env::temp_dir().join(format!("sequitur-mapped-{}.bin", std::process::id()));
+        seq.write_mapped(fs::File::create(&path).unwrap()).unwrap();
+        let bytes = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let mapped = MappedGrammar::<u16>::open(&bytes).unwrap();
+        assert_eq!(mapped.len(), input.len());
+        assert!(mapped.iter().eq(input.iter().copied()));
+        assert!(mapped.iter_document(&()).unwrap().eq(input.iter().copied()));
+        for i in (0..input.len()).step_by(37) {
+            assert_eq!(mapped.get(i), Some(input[i]));
+        }
+        assert_eq!(mapped.get(input.len()), None);
+        assert_eq!(mapped.iter().nth(4321), Some(input[4321]));
+
+        let stats = mapped.stats();
+        assert_eq!(stats.total_input_length, input.len());
+        assert_eq!(stats.total_grammar_symbols, seq.stats().grammar_symbols);
+        assert_eq!(stats.num_rules + 1, seq.stats().num_rules);
+    }
+
+    #[test]
+    fn test_mapped_documents() {
+        let mut docs = SequiturDocuments::new();
+        docs.extend_document(300, b"the cat sat on the mat".iter().copied());
+        docs.extend_document(2, b"the cat sat on the hat".iter().copied());
+        docs.extend_document(1 << 40, b"t".iter().copied());
+
+        let bytes = mapped_bytes(&docs);
+        let mapped = MappedGrammar::<u8, u64>::open(&bytes).unwrap();
+        assert_eq!(mapped.num_documents(), 3);
+        for doc_id in [300, 2, 1 << 40] {
+            let expected: Vec<u8> = docs.iter_document(&doc_id).unwrap().copied().collect();
+            let iter = mapped.iter_document(&doc_id).unwrap();
+            assert_eq!(iter.len(), expected.len());
+            assert_eq!(iter.collect::<Vec<_>>(), expected);
+        }
+        assert!(mapped.iter_document(&3).is_none());
+        // Lookups across the whole grammar run on from one document to the next.
+        for (i, value) in mapped.iter().enumerate() {
+            assert_eq!(mapped.get(i), Some(value));
+            assert_eq!(mapped.iter().nth(i), Some(value));
+        }
+        assert_eq!(mapped.get(mapped.len()), None);
+
+        let mut ids: Vec<_> = mapped.document_ids().collect();
+        ids.sort_unstable();
+        assert_eq!(ids, [2, 300, 1 << 40]);
+
+        let stats = mapped.stats();
+        let overall = docs.overall_stats();
+        assert_eq!(stats.total_input_length, overall.total_input_length);
+        assert_eq!(stats.num_rules, overall.num_rules);
+        assert_eq!(stats.total_grammar_symbols, overall.total_grammar_symbols);
+    }
+
+    #[test]
+    fn test_open_rejects_corruption() {
+        let mut docs = SequiturDocuments::new();
+        docs.extend_document(1, b"abcabcabcabc".iter().copied());
+        docs.extend_document(2, b"xyzabcxyz".iter().copied());
+        let bytes = mapped_bytes(&docs);
+        let invalid = |bytes: &[u8]| {
+            matches!(
+                MappedGrammar::<u8, u64>::open(bytes),
+                Err(SequiturError::CorruptGrammar(_))
+            )
+        };
+
+        assert!(MappedGrammar::<u8, u64>::open(&bytes).is_ok());
+        assert!(invalid(&bytes[..bytes.len() - 1]));
+        assert!(invalid(&[bytes.as_slice(), &[0]].concat()));
+        assert!(MappedGrammar::<u16, u64>::open(&bytes).is_err());
+        assert!(MappedGrammar::<u8, u32>::open(&bytes).is_err());
+        assert!(invalid(b"SQFG"));
+
+        // Swapping the document IDs breaks their order.
+        let num_values = u64::from_le_bytes(bytes[16..24].try_into().unwrap()) as usize;
+        let ids = 48 + num_values;
+        let mut unsorted = bytes.clone();
+        unsorted[ids..ids + 8].copy_from_slice(&2u64.to_le_bytes());
+        unsorted[ids + 8..ids + 16].copy_from_slice(&1u64.to_le_bytes());
+        assert!(invalid(&unsorted));
+
+        // A document referring to itself.
+        let codes = ids + 16;
+        let mut cyclic = bytes.clone();
+        cyclic[codes..codes + 4].copy_from_slice(&RULE_FLAG.to_le_bytes());
+        assert!(invalid(&cyclic));
+
+        // The stored length of the last rule is off by one.
+        let num_codes = u64::from_le_bytes(bytes[24..32].try_into().unwrap()) as usize;
+        let mut wrong_length = bytes.clone();
+        let last = wrong_length.len() - num_codes * 8 - 8;
+        wrong_length[last] ^= 1;
+        assert!(invalid(&wrong_length));
+
+        // So is the stored length up to the last code.
+        let mut wrong_end = bytes.clone();
+        let last = wrong_end.len() - 8;
+        wrong_end[last] ^= 1;
+        assert!(invalid(&wrong_end));
+    }
+
+    #[test]
+    fn test_mapped_empty() {
+        let docs = SequiturDocuments::<u8, u64>::new();
+        let bytes = mapped_bytes(&docs);
+        let mapped = MappedGrammar::<u8, u64>::open(&bytes).unwrap();
+        assert!(mapped.is_empty());
+        assert_eq!(mapped.iter().next(), None);
+        assert_eq!(mapped.stats().num_documents, 0);
+
+        let mut bytes = Vec::new();
+        Sequitur::<char>::new().write_mapped(&mut bytes).unwrap();
+        let mapped = MappedGrammar::<char>::open(&bytes).unwrap();
+        assert_eq!(mapped.num_documents(), 1);
+        assert_eq!(mapped.iter_document(&()).unwrap().count(), 0);
+    }
+}
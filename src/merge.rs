@@ -0,0 +1,792 @@
+use crate::documents::SequiturDocuments;
+use crate::grammar::Grammar;
+use crate::symbol::{Symbol, SymbolNode};
+use ahash::{AHashMap as HashMap, AHashSet as HashSet};
+use slotmap::DefaultKey;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::iter;
+
+/// Modulus of the expansion fingerprints (a Mersenne prime).
+const MODULUS: u64 = (1 << 61) - 1;
+
+/// Base of the expansion fingerprints.
+const BASE: u64 = 0x1f35_a2c9_4d7b_e601 % MODULUS;
+
+/// Polynomial hash of a rule expansion, paired with its length.
+type Fingerprint = (u64, usize);
+
+/// How to resolve a document ID present in both collections of a
+/// [`merge`](SequiturDocuments::merge).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DocConflict<DocId> {
+    /// Keep the existing document and drop the incoming one
+    KeepExisting,
+    /// Replace the existing document with the incoming one
+    Replace,
+    /// Append the incoming document to the end of the existing one
+    Append,
+    /// Add the incoming document under another ID
+    Rename(DocId),
+}
+
+impl<T: Hash + Eq + Clone, DocId: Hash + Eq + Clone> SequiturDocuments<T, DocId> {
+    /// Moves every document of `other` into this collection.
+    ///
+    /// Rules of `other` whose expansion equals that of an existing rule are
+    /// mapped onto it instead of being copied, so documents are not
+    /// recompressed. When a document ID exists in both collections,
+    /// `on_conflict` decides what happens; a [`DocConflict::Rename`] target that
+    /// is taken as well is passed to `on_conflict` again.
+    ///
+    /// Both constraints are re-established over the merged grammar, which keeps
+    /// this collection's configuration.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use sequitur_rs::{DocConflict, SequiturDocuments};
+    ///
+    /// let mut host_a = SequiturDocuments::new();
+    /// host_a.extend_document("log", "GET /index GET /index".chars());
+    ///
+    /// let mut host_b = SequiturDocuments::new();
+    /// host_b.extend_document("log", "GET /about".chars());
+    ///
+    /// host_a.merge(host_b, |_| DocConflict::Rename("log-b"));
+    ///
+    /// let log_b: String = host_a.iter_document(&"log-b").unwrap().collect();
+    /// assert_eq!(log_b, "GET /about");
+    /// assert_eq!(host_a.num_documents(), 2);
+    /// ```
+    pub fn merge<F>(&mut self, other: Self, mut on_conflict: F)
+    where
+        F: FnMut(&DocId) -> DocConflict<DocId>,
+    {
+        // Conflicts are resolved before any rule is imported, as a replaced
+        // document takes the rules only it used with it.
+        let mut targets = Vec::with_capacity(other.documents.len());
+        let mut replaced = Vec::new();
+        for (doc_id, info) in &other.documents {
+            let Some((target, append)) =
+                self.resolve_conflict(doc_id.clone(), &mut on_conflict, &mut replaced)
+            else {
+                continue;
+            };
+            if !append {
+                self.create_document(target.clone());
+            }
+            let tail = self.documents[&target].tail;
+            targets.push((target, tail, info));
+        }
+
+        let mut merger = Merger::default();
+        let heads: Vec<_> = self.documents.values().map(|info| info.head).collect();
+        merger.register_rules(&self.grammar, &heads);
+
+        let other_heads: Vec<_> = other.documents.values().map(|info| info.head).collect();
+        let ids = merger.import_rules(&mut self.grammar, &other.grammar, &other_heads);
+
+        let mut starts = Vec::new();
+        let mut filled = HashSet::default();
+        for (target, tail, info) in targets {
+            // A later incoming document replaced this one.
+            if !self.grammar.symbols.contains_key(tail) {
+                continue;
+            }
+            if filled.insert(tail) {
+                starts.push(
+                    self.grammar.symbols[tail]
+                        .prev
+                        .expect("tail should have prev"),
+                );
+            }
+            for symbol in Merger::translate(&other.grammar, info.head, &ids) {
+                self.grammar.insert_unlinked_before(tail, symbol);
+            }
+            self.documents.get_mut(&target).unwrap().length += info.length;
+        }
+
+        self.grammar.restore_imported(&merger.added, &starts);
+        self.inline_underused(&replaced);
+    }
+
+    /// Merges collections whose document IDs don't overlap into a new one.
+    ///
+    /// Unlike calling [`merge`](Self::merge) once per collection, every rule is
+    /// imported through the same [`Merger`] and the constraints are restored
+    /// once at the end, as [`Sequitur::build_parallel`](crate::Sequitur::build_parallel)
+    /// does for its chunks.
+    pub(crate) fn merge_disjoint<I>(collections: I) -> Self
+    where
+        I: IntoIterator<Item = Self>,
+    {
+        let mut merged = Self::new();
+        let mut merger = Merger::default();
+
+        for other in collections {
+            let heads: Vec<_> = other.documents.values().map(|info| info.head).collect();
+            let ids = merger.import_rules(&mut merged.grammar, &other.grammar, &heads);
+            for (doc_id, info) in &other.documents {
+                debug_assert!(!merged.documents.contains_key(doc_id));
+                merged.create_document(doc_id.clone());
+                let target = merged.documents.get_mut(doc_id).unwrap();
+                target.length = info.length;
+                let tail = target.tail;
+                for symbol in Merger::translate(&other.grammar, info.head, &ids) {
+                    merged.grammar.insert_unlinked_before(tail, symbol);
+                }
+            }
+        }
+
+        merged.restore_constraints();
+        merged
+    }
+
+    /// Re-establishes both constraints over the whole grammar after document
+    /// bodies were changed without enforcing them, dropping rules no document
+    /// uses any more.
+    pub(crate) fn restore_constraints(&mut self) {
+        let heads: Vec<_> = self.documents.values().map(|info| info.head).collect();
+        self.grammar.remove_unreferenced_rules(&heads);
+        self.grammar.recount_and_inline_single_use(None, &heads);
+        if !self.grammar.config.is_classic() {
+            self.grammar.inline_underused_rules(&heads);
+        }
+        self.grammar.enforce_digram_uniqueness(&heads);
+    }
+
+    /// Inlines those of `rules` left with fewer uses than the configuration
+    /// requires.
+    ///
+    /// Their remaining uses could be anywhere, so finding them walks the whole
+    /// grammar, but only if there are any. The sequences they are inlined into
+    /// are replayed afterwards, as an inlined body can continue a run, like
+    /// `aa` next to `aa`, that only shows up as a repeat when pushed again.
+    fn inline_underused(&mut self, rules: &[u32]) {
+        let min_uses = self.grammar.config.min_rule_uses;
+        let underused = |grammar: &Grammar<T>, rule_id: &u32| {
+            grammar.rule_index.get(rule_id).is_some_and(|&head| {
+                matches!(grammar.symbols[head].symbol, Symbol::RuleHead { count, .. } if count < min_uses)
+            })
+        };
+        let mut pending: Vec<u32> = rules
+            .iter()
+            .copied()
+            .filter(|rule_id| underused(&self.grammar, rule_id))
+            .collect();
+
+        if pending.is_empty() {
+            return;
+        }
+
+        let sequences: Vec<_> = self
+            .grammar
+            .rule_index
+            .values()
+            .chain(self.documents.values().map(|info| &info.head))
+            .copied()
+            .collect();
+        let affected: Vec<_> = sequences
+            .into_iter()
+            .filter(|&head| {
+                self.grammar.body_keys(head).any(|key| {
+                    pending
+                        .iter()
+                        .any(|&rule_id| self.grammar.is_ref_to(key, rule_id))
+                })
+            })
+            .collect();
+
+        while !pending.is_empty() {
+            let heads: Vec<_> = self.documents.values().map(|info| info.head).collect();
+            let refs = self.grammar.rule_refs(&heads);
+            let before = pending.len();
+            for &rule_id in &pending {
+                // Expanding one rule can replace the references to another.
+                let location = refs.get(&rule_id).and_then(|locations| {
+                    locations
+                        .iter()
+                        .copied()
+                        .find(|&key| self.grammar.is_ref_to(key, rule_id))
+                });
+                if let Some(key) = location {
+                    self.grammar.expand_rule_if_necessary(key);
+                }
+            }
+            pending.retain(|rule_id| underused(&self.grammar, rule_id));
+            if pending.len() == before {
+                break;
+            }
+        }
+
+        // The relaxed constraints track every occurrence already.
+        if self.grammar.config.is_classic() {
+            for head in affected {
+                // Rules inlined meanwhile are part of another sequence now.
+                if self.grammar.symbols.contains_key(head) {
+                    self.grammar.replay_sequence(head);
+                }
+            }
+        }
+    }
+
+    /// Finds where an incoming document goes, returning its ID and whether it is
+    /// appended to an existing document, or `None` if it is dropped.
+    ///
+    /// A replaced document is deleted here, and the rules it reached are added
+    /// to `replaced`.
+    fn resolve_conflict<F>(
+        &mut self,
+        mut doc_id: DocId,
+        on_conflict: &mut F,
+        replaced: &mut Vec<u32>,
+    ) -> Option<(DocId, bool)>
+    where
+        F: FnMut(&DocId) -> DocConflict<DocId>,
+    {
+        while self.documents.contains_key(&doc_id) {
+            match on_conflict(&doc_id) {
+                DocConflict::KeepExisting => return None,
+                DocConflict::Append => return Some((doc_id, true)),
+                DocConflict::Replace => {
+                    let head = self.documents[&doc_id].head;
+                    replaced.extend(self.grammar.rules_children_first(&[head]));
+                    self.detach_document(&doc_id);
+                }
+                DocConflict::Rename(new_id) => doc_id = new_id,
+            }
+        }
+        Some((doc_id, false))
+    }
+}
+
+/// Rule unification state while merging grammars.
+///
+/// Rules are identified by a fingerprint of their expansion, and candidates
+/// with equal fingerprints are compared value by value.
+#[derive(Default)]
+pub(crate) struct Merger {
+    /// Merged rules by expansion fingerprint
+    rules: HashMap<Fingerprint, Vec<u32>>,
+    /// Fingerprint of every merged rule
+    fingerprints: HashMap<u32, Fingerprint>,
+    /// Rules added to the target grammar, children first
+    added: Vec<u32>,
+}
+
+impl Merger {
+    /// Makes the rules of the target grammar reachable from `sequences`
+    /// available for unification.
+    pub(crate) fn register_rules<T: Hash + Eq + Clone>(
+        &mut self,
+        grammar: &Grammar<T>,
+        sequences: &[DefaultKey],
+    ) {
+        for rule_id in grammar.rules_children_first(sequences) {
+            let head = grammar.rule_index[&rule_id];
+            let fingerprint = self.fingerprint(
+                grammar
+                    .body_keys(head)
+                    .map(|key| &grammar.symbols[key].symbol),
+            );
+            self.rules.entry(fingerprint).or_default().push(rule_id);
+            self.fingerprints.insert(rule_id, fingerprint);
+        }
+    }
+
+    /// Copies the rules of `source` reachable from `sequences` into `target`,
+    /// unifying them with rules already there, and returns the target ID of each
+    /// source rule.
+    pub(crate) fn import_rules<T: Hash + Eq + Clone>(
+        &mut self,
+        target: &mut Grammar<T>,
+        source: &Grammar<T>,
+        sequences: &[DefaultKey],
+    ) -> HashMap<u32, u32> {
+        let mut ids = HashMap::default();
+        // Children first, so every reference can be renamed.
+        for rule_id in source.rules_children_first(sequences) {
+            let body = Self::translate(source, source.rule_index[&rule_id], &ids);
+            ids.insert(rule_id, self.add_rule(target, body));
+        }
+        ids
+    }
+
+    /// Copies a sequence body, renaming rule references with `ids`.
+    pub(crate) fn translate<T: Clone>(
+        grammar: &Grammar<T>,
+        head: DefaultKey,
+        ids: &HashMap<u32, u32>,
+    ) -> Vec<Symbol<T>> {
+        grammar
+            .body_keys(head)
+            .map(|key| match &grammar.symbols[key].symbol {
+                Symbol::RuleRef { rule_id } => Symbol::RuleRef {
+                    rule_id: ids[rule_id],
+                },
+                symbol => symbol.clone_symbol(),
+            })
+            .collect()
+    }
+
+    /// Adds a rule with the given body to the grammar, unless a rule with the
+    /// same expansion already exists, and returns the ID of the rule to use.
+    fn add_rule<T: Hash + Eq + Clone>(
+        &mut self,
+        grammar: &mut Grammar<T>,
+        body: Vec<Symbol<T>>,
+    ) -> u32 {
+        let fingerprint = self.fingerprint(body.iter());
+        let (rule_id, head) = grammar.append_rule(body);
+
+        let candidates = self.rules.entry(fingerprint).or_default();
+        let existing = candidates.iter().copied().find(|existing| {
+            let other = grammar.rule_index[existing];
+            grammar.expansion(head).eq(grammar.expansion(other))
+        });
+        if let Some(existing) = existing {
+            grammar.remove_rule(rule_id);
+            return existing;
+        }
+
+        candidates.push(rule_id);
+        self.fingerprints.insert(rule_id, fingerprint);
+        self.added.push(rule_id);
+        rule_id
+    }
+
+    /// Combines the fingerprints of the symbols of a body.
+    ///
+    /// The fingerprint of `xy` is `h(x) * BASE^len(y) + h(y)`, so it only depends
+    /// on the expansion, not on how it is split into rules.
+    fn fingerprint<'a, T: Hash + 'a>(
+        &self,
+        body: impl Iterator<Item = &'a Symbol<T>>,
+    ) -> Fingerprint {
+        body.fold((0, 0), |(hash, len), symbol| {
+            let (symbol_hash, symbol_len) = match symbol {
+                Symbol::RuleRef { rule_id } => self.fingerprints[rule_id],
+                Symbol::Value(value) => {
+                    let mut hasher = DefaultHasher::new();
+                    value.hash(&mut hasher);
+                    (hasher.finish() % MODULUS, 1)
+                }
+                _ => unreachable!("bodies only hold values and rule references"),
+            };
+            let shifted = mul_mod(hash, pow_mod(BASE, symbol_len));
+            ((shifted + symbol_hash) % MODULUS, len + symbol_len)
+        })
+    }
+}
+
+impl<T: Hash + Eq + Clone> Grammar<T> {
+    /// Creates a rule with the given body and no uses, without enforcing any
+    /// constraint, and returns its ID and RuleHead.
+    pub(crate) fn append_rule(&mut self, body: Vec<Symbol<T>>) -> (u32, DefaultKey) {
+        let rule_id = self.id_gen.get();
+        let tail = self.symbols.insert(SymbolNode::new(Symbol::RuleTail));
+        let head = self.symbols.insert(SymbolNode::new(Symbol::RuleHead {
+            rule_id,
+            count: 0,
+            tail,
+        }));
+        self.symbols[head].next = Some(tail);
+        self.symbols[tail].prev = Some(head);
+        for symbol in body {
+            self.insert_unlinked_before(tail, symbol);
+        }
+
+        self.rule_index.insert(rule_id, head);
+        (rule_id, head)
+    }
+
+    /// Inserts a symbol before `next` without enforcing any constraint.
+    pub(crate) fn insert_unlinked_before(
+        &mut self,
+        next: DefaultKey,
+        symbol: Symbol<T>,
+    ) -> DefaultKey {
+        let key = self.symbols.insert(SymbolNode::new(symbol));
+        let prev = self.symbols[next].prev;
+
+        self.symbols[key].prev = prev;
+        self.symbols[key].next = Some(next);
+        self.symbols[next].prev = Some(key);
+        if let Some(prev) = prev {
+            self.symbols[prev].next = Some(key);
+        }
+        key
+    }
+
+    /// Re-establishes both constraints after rules and the ends of sequences
+    /// were imported without enforcing them, when the rest of the grammar
+    /// satisfies them.
+    ///
+    /// `rules` are the imported rules, children first, and `starts` the symbol
+    /// of each sequence after which its imported end begins. Only imported
+    /// symbols are replayed, so this takes time proportional to them rather
+    /// than to the grammar.
+    pub(crate) fn restore_imported(&mut self, rules: &[u32], starts: &[DefaultKey]) {
+        let imported: HashSet<u32> = rules.iter().copied().collect();
+        let mut keys = Vec::new();
+        for rule_id in rules {
+            keys.extend(self.body_keys(self.rule_index[rule_id]));
+            // IDs can be reused, so anything recorded under them is stale.
+            self.ref_locations.remove(rule_id);
+            self.expansion_lengths.remove(rule_id);
+        }
+        for &start in starts {
+            keys.extend(
+                iter::successors(self.symbols[start].next, |&key| self.symbols[key].next)
+                    .take_while(|&key| !self.is_sequence_end(&self.symbols[key].symbol)),
+            );
+        }
+        let mut refs: HashMap<u32, Vec<DefaultKey>> = HashMap::default();
+        for key in keys {
+            if let Symbol::RuleRef { rule_id } = self.symbols[key].symbol {
+                refs.entry(rule_id).or_default().push(key);
+            }
+        }
+        let live_uses = |grammar: &Self, refs: &mut HashMap<u32, Vec<DefaultKey>>, rule_id: u32| {
+            let locations = refs.entry(rule_id).or_default();
+            locations.retain(|&key| grammar.is_ref_to(key, rule_id));
+            locations.len()
+        };
+
+        // A rule imported as a child of one that turned out to duplicate an
+        // existing rule, or only for dropped documents, is unused. Parents go
+        // first, so their children lose those uses.
+        for &rule_id in rules.iter().rev() {
+            if live_uses(self, &mut refs, rule_id) == 0 {
+                self.remove_rule(rule_id);
+            }
+        }
+        let min_uses = self.config.min_rule_uses as usize;
+        for &rule_id in rules {
+            if self.rule_index.contains_key(&rule_id)
+                && live_uses(self, &mut refs, rule_id) < min_uses
+            {
+                self.inline_everywhere(rule_id, &mut refs);
+            }
+        }
+
+        // Imported symbols are the only uses of imported rules and extra uses
+        // of the others.
+        let used: Vec<u32> = refs.keys().copied().collect();
+        for rule_id in used {
+            let uses = live_uses(self, &mut refs, rule_id) as u32;
+            let Some(&head) = self.rule_index.get(&rule_id) else {
+                continue;
+            };
+            if let Symbol::RuleHead { count, .. } = &mut self.symbols[head].symbol {
+                *count = if imported.contains(&rule_id) {
+                    uses
+                } else {
+                    *count + uses
+                };
+            }
+        }
+        if !self.config.is_classic() {
+            for (&rule_id, locations) in &refs {
+                for &key in locations {
+                    self.note_ref(rule_id, key);
+                }
+            }
+        }
+
+        // Rules come before the sequences using them, as in
+        // `enforce_digram_uniqueness`, and every imported end is detached
+        // first so no replay reaches into one that is still pending.
+        let suffixes: Vec<_> = starts
+            .iter()
+            .map(|&start| self.detach_suffix(start))
+            .collect();
+        let heads: Vec<_> = rules
+            .iter()
+            .filter_map(|rule_id| self.rule_index.get(rule_id).copied())
+            .collect();
+        for head in heads {
+            // Rules inlined while replaying their parents are gone.
+            if self.symbols.contains_key(head) {
+                let suffix = self.detach_suffix(head);
+                self.replay_suffix(suffix);
+            }
+        }
+        for suffix in suffixes {
+            self.replay_suffix(suffix);
+        }
+    }
+
+    /// Whether `key` is a live RuleRef to `rule_id`.
+    pub(crate) fn is_ref_to(&self, key: DefaultKey, rule_id: u32) -> bool {
+        matches!(
+            self.symbols.get(key).map(|node| &node.symbol),
+            Some(&Symbol::RuleRef { rule_id: id }) if id == rule_id
+        )
+    }
+
+    /// Deletes a rule, without adjusting the counts of the rules it references.
+    fn remove_rule(&mut self, rule_id: u32) {
+        let head = self
+            .rule_index
+            .remove(&rule_id)
+            .expect("removed rule should exist");
+        let Symbol::RuleHead { tail, .. } = self.symbols[head].symbol else {
+            unreachable!("rule index should only point at RuleHeads");
+        };
+        let keys: Vec<_> = self.body_keys(head).collect();
+        for key in keys.into_iter().chain([head, tail]) {
+            self.symbols.remove(key);
+        }
+        self.ref_locations.remove(&rule_id);
+        self.expansion_lengths.remove(&rule_id);
+        let freed = self.id_gen.free(rule_id);
+        debug_assert!(freed, "rule {rule_id} should have an allocated ID");
+    }
+
+    /// Deletes a sequence, keeping the counts of the rules it uses and the
+    /// digram index up to date, along with every rule it leaves unused.
+    ///
+    /// Rules left with too few uses are kept, until
+    /// [`restore_constraints`](SequiturDocuments::restore_constraints) inlines
+    /// them, so this only takes time proportional to what is deleted.
+    pub(crate) fn remove_sequence(&mut self, head: DefaultKey) {
+        let mut unused = vec![head];
+        while let Some(head) = unused.pop() {
+            let (Symbol::RuleHead { tail, .. } | Symbol::DocHead { tail }) =
+                self.symbols[head].symbol
+            else {
+                unreachable!("sequences should start with a RuleHead or DocHead");
+            };
+            // Symbols go from the front, so the only digram left to unindex is
+            // the one starting at the next symbol.
+            while let Some(key) = self.symbols[head].next.filter(|&key| key != tail) {
+                self.remove_digram_from_index(key);
+                let next = self.symbols[key]
+                    .next
+                    .expect("body symbols should have next");
+                self.symbols[head].next = Some(next);
+                self.symbols[next].prev = Some(head);
+
+                self.decrement_if_rule(key);
+                if let Symbol::RuleRef { rule_id } = self.symbols[key].symbol {
+                    let rule_head = self.rule_index[&rule_id];
+                    if let Symbol::RuleHead { count: 0, .. } = self.symbols[rule_head].symbol {
+                        unused.push(rule_head);
+                    }
+                }
+                self.symbols.remove(key);
+            }
+
+            if let Symbol::RuleHead { rule_id, .. } = self.symbols[head].symbol {
+                self.rule_index.remove(&rule_id);
+                self.ref_locations.remove(&rule_id);
+                self.expansion_lengths.remove(&rule_id);
+                let freed = self.id_gen.free(rule_id);
+                debug_assert!(freed, "rule {rule_id} should have an allocated ID");
+            }
+            self.symbols.remove(head);
+            self.symbols.remove(tail);
+        }
+    }
+
+    /// Deletes every rule not reachable from `sequences`, other than the
+    /// sequences themselves.
+    pub(crate) fn remove_unreferenced_rules(&mut self, sequences: &[DefaultKey]) {
+        let reachable: HashSet<u32> = self.rules_children_first(sequences).into_iter().collect();
+        let sequences: HashSet<DefaultKey> = sequences.iter().copied().collect();
+        let unreferenced: Vec<u32> = self
+            .rule_index
+            .iter()
+            .filter(|(rule_id, head)| !reachable.contains(rule_id) && !sequences.contains(head))
+            .map(|(&rule_id, _)| rule_id)
+            .collect();
+        for rule_id in unreferenced {
+            self.remove_rule(rule_id);
+        }
+    }
+
+    /// Inlines every rule used fewer than the configured minimum number of
+    /// times and resets the bookkeeping of the relaxed constraints, recording
+    /// the remaining RuleRefs.
+    fn inline_underused_rules(&mut self, sequences: &[DefaultKey]) {
+        let min_uses = self.config.min_rule_uses as usize;
+        let mut refs = self.rule_refs(sequences);
+        for rule_id in self.rules_children_first(sequences) {
+            if refs.get(&rule_id).map_or(0, Vec::len) < min_uses {
+                self.inline_everywhere(rule_id, &mut refs);
+            }
+        }
+        self.recount_and_inline_single_use(None, sequences);
+
+        self.pending_digrams.clear();
+        self.ref_locations = self.rule_refs(sequences);
+        self.expansion_lengths.clear();
+    }
+
+    /// Returns the values of the expansion of the rule at `head`.
+    pub(crate) fn expansion(&self, head: DefaultKey) -> impl Iterator<Item = &T> + '_ {
+        let mut stack = vec![self.body_keys(head)];
+        iter::from_fn(move || loop {
+            let Some(key) = stack.last_mut()?.next() else {
+                stack.pop();
+                continue;
+            };
+            match &self.symbols[key].symbol {
+                Symbol::Value(value) => return Some(value),
+                Symbol::RuleRef { rule_id } => stack.push(self.body_keys(self.rule_index[rule_id])),
+                _ => {}
+            }
+        })
+    }
+}
+
+/// Multiplies modulo [`MODULUS`].
+fn mul_mod(a: u64, b: u64) -> u64 {
+    ((a as u128 * b as u128) % MODULUS as u128) as u64
+}
+
+/// Raises to a power modulo [`MODULUS`].
+fn pow_mod(mut base: u64, mut exp: usize) -> u64 {
+    let mut result = 1;
+    while exp > 0 {
+        if exp & 1 == 1 {
+            result = mul_mod(result, base);
+        }
+        base = mul_mod(base, base);
+        exp >>= 1;
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Sequitur;
+
+    fn document(docs: &SequiturDocuments<char, &'static str>, doc_id: &'static str) -> String {
+        docs.iter_document(&doc_id).unwrap().collect()
+    }
+
+    fn assert_constraints<T: Hash + Eq + Clone, DocId: Hash + Eq + Clone>(
+        docs: &SequiturDocuments<T, DocId>,
+    ) {
+        let heads: Vec<_> = docs.documents.values().map(|info| info.head).collect();
+        let refs = docs.grammar.rule_refs(&heads);
+        for (&rule_id, &head) in docs.rules() {
+            let Symbol::RuleHead { count, .. } = docs.grammar.symbols[head].symbol else {
+                unreachable!();
+            };
+            assert!(count >= 2, "rule {rule_id} used {count} times");
+            assert_eq!(count as usize, refs[&rule_id].len());
+        }
+    }
+
+    #[test]
+    fn test_merge_disjoint_documents() {
+        let mut a = SequiturDocuments::new();
+        a.extend_document("a1", "hello world hello world".chars());
+        let mut b = SequiturDocuments::new();
+        b.extend_document("b1", "hello there hello there".chars());
+        b.extend_document("b2", "world peace".chars());
+
+        a.merge(b, |_| panic!("no conflicts expected"));
+
+        assert_eq!(a.num_documents(), 3);
+        assert_eq!(document(&a, "a1"), "hello world hello world");
+        assert_eq!(document(&a, "b1"), "hello there hello there");
+        assert_eq!(document(&a, "b2"), "world peace");
+        assert_eq!(a.document_len(&"b1"), Some(23));
+        assert_constraints(&a);
+    }
+
+    #[test]
+    fn test_merge_deduplicates_rules() {
+        let text = "abcdefgh".repeat(8);
+        let mut a = SequiturDocuments::new();
+        a.extend_document("a", text.chars());
+        let rules_before = a.rules().len();
+
+        let mut b = SequiturDocuments::new();
+        b.extend_document("b", text.chars());
+        a.merge(b, |_| DocConflict::KeepExisting);
+
+        // The second copy is one more use of the same top-level rule.
+        assert!(a.rules().len() <= rules_before + 1);
+        assert_eq!(document(&a, "b"), text);
+        assert_constraints(&a);
+    }
+
+    #[test]
+    fn test_merge_conflicts() {
+        let build = |text: &str| {
+            let mut docs = SequiturDocuments::new();
+            docs.extend_document("keep", text.chars());
+            docs.extend_document("replace", text.chars());
+            docs.extend_document("append", text.chars());
+            docs.extend_document("rename", text.chars());
+            docs
+        };
+        let mut a = build("abcabc");
+        let b = build("xyzxyz");
+
+        a.merge(b, |&doc_id| match doc_id {
+            "keep" => DocConflict::KeepExisting,
+            "replace" => DocConflict::Replace,
+            "append" => DocConflict::Append,
+            // Renaming onto a taken ID asks again.
+            "rename" => DocConflict::Rename("keep"),
+            _ => unreachable!(),
+        });
+
+        assert_eq!(a.num_documents(), 4);
+        assert_eq!(document(&a, "keep"), "abcabc");
+        assert_eq!(document(&a, "replace"), "xyzxyz");
+        assert_eq!(document(&a, "append"), "abcabcxyzxyz");
+        assert_eq!(document(&a, "rename"), "abcabc");
+        assert_eq!(a.document_len(&"append"), Some(12));
+        assert_constraints(&a);
+
+        a.extend_document("append", "abc".chars());
+        assert_eq!(document(&a, "append"), "abcabcxyzxyzabc");
+    }
+
+    #[test]
+    fn test_merge_relaxed_config() {
+        let mut a = Sequitur::builder().min_rule_uses(3).build_documents();
+        a.extend_document(1, "abcabcabc".chars());
+        let mut b = Sequitur::builder().build_documents();
+        b.extend_document(1, "abcabc".chars());
+
+        // "abc" is left with two uses, which is below the minimum.
+        a.merge(b, |_| DocConflict::Replace);
+        let doc: String = a.iter_document(&1).unwrap().collect();
+        assert_eq!(doc, "abcabc");
+        for &head in a.rules().values() {
+            let Symbol::RuleHead { count, .. } = a.grammar.symbols[head].symbol else {
+                unreachable!();
+            };
+            assert!(count >= 3);
+        }
+    }
+
+    #[test]
+    fn test_expansion_fingerprint_ignores_structure() {
+        // ((ab)c) and (a(bc)) expand to the same values.
+        let mut grammar = Grammar::<char>::new();
+        let mut merger = Merger::default();
+        let ab = merger.add_rule(&mut grammar, vec![Symbol::Value('a'), Symbol::Value('b')]);
+        let bc = merger.add_rule(&mut grammar, vec![Symbol::Value('b'), Symbol::Value('c')]);
+        let left = merger.add_rule(
+            &mut grammar,
+            vec![Symbol::RuleRef { rule_id: ab }, Symbol::Value('c')],
+        );
+        let right = merger.add_rule(
+            &mut grammar,
+            vec![Symbol::Value('a'), Symbol::RuleRef { rule_id: bc }],
+        );
+        assert_eq!(left, right);
+        assert_eq!(grammar.rule_index.len(), 3);
+    }
+}
@@ -0,0 +1,200 @@
+use crate::documents::SequiturDocuments;
+use crate::rle_sequitur::SequiturRle;
+use crate::sequitur::Sequitur;
+use std::hash::Hash;
+
+/// One occurrence of a rule in the expanded sequence.
+///
+/// Positions are indices into the reconstructed sequence, so the occurrence
+/// covers `start..start + len`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RuleOccurrence {
+    /// ID of the rule
+    pub rule_id: u32,
+    /// Position of the first value covered by the occurrence
+    pub start: usize,
+    /// Number of values the rule expands to
+    pub len: usize,
+}
+
+impl RuleOccurrence {
+    /// Returns the position one past the last value covered by the occurrence.
+    pub fn end(&self) -> usize {
+        self.start + self.len
+    }
+}
+
+impl<T: Hash + Eq + Clone> Sequitur<T> {
+    /// Returns every occurrence of every rule (except Rule 0) in the expanded
+    /// sequence, including occurrences nested inside other rules.
+    ///
+    /// Occurrences are ordered by start position.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use sequitur_rs::Sequitur;
+    ///
+    /// let mut seq = Sequitur::new();
+    /// seq.extend("abcxabc".chars());
+    ///
+    /// let occurrences = seq.rule_occurrences();
+    /// assert!(occurrences.iter().any(|o| o.start == 4 && o.len == 3));
+    /// ```
+    pub fn rule_occurrences(&self) -> Vec<RuleOccurrence> {
+        let lengths = self.grammar.rule_lengths();
+        let head = self.grammar.rule_index[&0];
+
+        let mut occurrences = Vec::new();
+        self.grammar
+            .for_each_rule_occurrence(head, &lengths, &mut |rule_id, start, len| {
+                occurrences.push(RuleOccurrence {
+                    rule_id,
+                    start,
+                    len,
+                });
+            });
+        occurrences
+    }
+}
+
+impl<T: Hash + Eq + Clone, DocId: Hash + Eq + Clone> SequiturDocuments<T, DocId> {
+    /// Returns every rule occurrence in the expansion of a document, with
+    /// positions relative to the start of the document.
+    ///
+    /// Occurrences are ordered by start position. Returns `None` if the document
+    /// doesn't exist.
+    pub fn document_rule_occurrences(&self, doc_id: &DocId) -> Option<Vec<RuleOccurrence>> {
+        let head = self.documents.get(doc_id)?.head;
+        let lengths = self.grammar.rule_lengths();
+
+        let mut occurrences = Vec::new();
+        self.grammar
+            .for_each_rule_occurrence(head, &lengths, &mut |rule_id, start, len| {
+                occurrences.push(RuleOccurrence {
+                    rule_id,
+                    start,
+                    len,
+                });
+            });
+        Some(occurrences)
+    }
+}
+
+impl<T: Hash + Eq + Clone> SequiturRle<T> {
+    /// Returns every occurrence of every rule (except Rule 0) in the expanded
+    /// sequence, including occurrences nested inside other rules.
+    ///
+    /// A rule reference with run `k` contributes `k` consecutive occurrences.
+    /// Occurrences are ordered by start position.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use sequitur_rs::SequiturRle;
+    ///
+    /// let mut seq = SequiturRle::new();
+    /// seq.extend("abcxabc".chars());
+    ///
+    /// let occurrences = seq.rule_occurrences();
+    /// assert!(occurrences.iter().any(|o| o.start == 4 && o.len == 3));
+    /// ```
+    pub fn rule_occurrences(&self) -> Vec<RuleOccurrence> {
+        let lengths = self.grammar.rule_lengths();
+        let head = self.grammar.rule_index[&0];
+
+        let mut occurrences = Vec::new();
+        self.grammar
+            .for_each_rule_occurrence(head, &lengths, &mut |rule_id, start, len| {
+                occurrences.push(RuleOccurrence {
+                    rule_id,
+                    start,
+                    len,
+                });
+            });
+        occurrences
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_occurrences_match_expansion() {
+        let input: Vec<u8> = b"abracadabra abracadabra cadabra".to_vec();
+        let mut seq = Sequitur::new();
+        seq.extend(input.iter().copied());
+
+        let occurrences = seq.rule_occurrences();
+        assert!(!occurrences.is_empty());
+
+        for a in &occurrences {
+            for b in occurrences.iter().filter(|b| b.rule_id == a.rule_id) {
+                assert_eq!(input[a.start..a.end()], input[b.start..b.end()]);
+            }
+            assert!(a.end() <= input.len());
+        }
+        assert!(occurrences.windows(2).all(|w| w[0].start <= w[1].start));
+    }
+
+    #[test]
+    fn test_document_occurrences() {
+        let mut docs = SequiturDocuments::new();
+        docs.extend_document("a", "xyzxyz".chars());
+        docs.extend_document("b", "qxyz".chars());
+
+        let occurrences = docs.document_rule_occurrences(&"b").unwrap();
+        assert!(occurrences.iter().any(|o| o.start == 1 && o.len == 3));
+
+        let text: Vec<char> = docs.iter_document(&"a").unwrap().copied().collect();
+        for o in docs.document_rule_occurrences(&"a").unwrap() {
+            assert!(o.end() <= text.len());
+        }
+
+        assert!(docs.document_rule_occurrences(&"missing").is_none());
+    }
+
+    #[test]
+    fn test_rle_occurrences_match_expansion() {
+        let input: Vec<u8> = b"aaabcaaabcxyaaabc".to_vec();
+        let mut seq = SequiturRle::new();
+        seq.extend(input.iter().copied());
+
+        let occurrences = seq.rule_occurrences();
+        assert!(!occurrences.is_empty());
+
+        // Every occurrence of the same rule covers identical content.
+        for a in &occurrences {
+            for b in occurrences.iter().filter(|b| b.rule_id == a.rule_id) {
+                assert_eq!(a.len, b.len);
+                assert_eq!(input[a.start..a.end()], input[b.start..b.end()]);
+            }
+            assert!(a.end() <= input.len());
+        }
+
+        // Ordered by start position.
+        assert!(occurrences.windows(2).all(|w| w[0].start <= w[1].start));
+    }
+
+    #[test]
+    fn test_rle_occurrences_expand_runs() {
+        let mut seq = SequiturRle::new();
+        seq.extend("abababab".chars());
+
+        let occurrences = seq.rule_occurrences();
+        let outer: Vec<_> = occurrences.iter().filter(|o| o.len == 2).collect();
+        assert_eq!(outer.len(), 4);
+        assert_eq!(
+            outer.iter().map(|o| o.start).collect::<Vec<_>>(),
+            vec![0, 2, 4, 6]
+        );
+    }
+
+    #[test]
+    fn test_no_occurrences_without_rules() {
+        let mut seq = SequiturRle::new();
+        seq.extend("abc".chars());
+        assert!(seq.rule_occurrences().is_empty());
+    }
+}
@@ -0,0 +1,360 @@
+use crate::documents::SequiturDocuments;
+use crate::grammar::Grammar;
+use crate::repair::RePair;
+use crate::sequitur::Sequitur;
+use crate::symbol::{Symbol, SymbolNode};
+use ahash::{AHashMap as HashMap, AHashSet as HashSet};
+use slotmap::DefaultKey;
+use std::hash::Hash;
+
+/// Minimum occurrences for a new pair rule to shrink the grammar: replacing `c`
+/// occurrences saves `c` symbols and the rule costs 3 (two symbols and itself).
+const MIN_PAIR_USES: usize = 4;
+
+/// Summary of an [`optimize`](Sequitur::optimize) pass.
+///
+/// Costs are measured as the number of symbols in all rule and sequence bodies
+/// plus one per rule.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OptimizationReport {
+    /// Grammar cost before the pass
+    pub cost_before: usize,
+    /// Grammar cost after the pass
+    pub cost_after: usize,
+    /// Number of rules inlined into their uses
+    pub rules_inlined: usize,
+    /// Number of rules created by re-pairing
+    pub rules_created: usize,
+}
+
+impl OptimizationReport {
+    /// Returns the reduction in grammar cost.
+    pub fn saved(&self) -> usize {
+        self.cost_before - self.cost_after
+    }
+}
+
+impl<T: Hash + Eq + Clone> Sequitur<T> {
+    /// Shrinks the grammar after it has been built online.
+    ///
+    /// Rules whose removal does not increase the grammar cost are inlined
+    /// (which merges chains of single-symbol or single-use rules), then pairs
+    /// repeated at least four times in Rule 0 are replaced by new rules. The
+    /// reconstructed sequence is unchanged.
+    ///
+    /// The result no longer guarantees digram uniqueness, since inlining small
+    /// rules reintroduces repeated digrams on purpose. Values can still be pushed
+    /// afterwards.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use sequitur_rs::Sequitur;
+    ///
+    /// let mut seq = Sequitur::new();
+    /// seq.extend("abcdbcabcdbc".chars());
+    ///
+    /// let report = seq.optimize();
+    /// assert!(report.cost_after <= report.cost_before);
+    /// assert_eq!(seq.iter().collect::<String>(), "abcdbcabcdbc");
+    /// ```
+    pub fn optimize(&mut self) -> OptimizationReport {
+        let sequence = self.grammar.rule_index[&0];
+        self.grammar.optimize(Some(0), &[], &[sequence])
+    }
+}
+
+impl<T: Hash + Eq + Clone, DocId: Hash + Eq + Clone> SequiturDocuments<T, DocId> {
+    /// Shrinks the shared grammar after it has been built online.
+    ///
+    /// Works like [`Sequitur::optimize`], with every document sequence taking
+    /// the place of Rule 0. Every document reconstructs unchanged.
+    pub fn optimize(&mut self) -> OptimizationReport {
+        let doc_heads: Vec<_> = self.documents.values().map(|info| info.head).collect();
+        self.grammar.optimize(None, &doc_heads, &doc_heads)
+    }
+}
+
+impl<T: Hash + Eq + Clone> Grammar<T> {
+    /// Runs the optimization pass.
+    ///
+    /// `root_rule` is the rule holding the main sequence, if any, `doc_heads`
+    /// are the DocHeads of all documents, and `sequences` are the heads of the
+    /// top-level sequences to re-pair.
+    fn optimize(
+        &mut self,
+        root_rule: Option<u32>,
+        doc_heads: &[DefaultKey],
+        sequences: &[DefaultKey],
+    ) -> OptimizationReport {
+        let cost_before = self.cost(root_rule, doc_heads);
+
+        let mut rules_inlined = self.inline_cheap_rules(sequences, doc_heads);
+
+        // Pair rules are only as long as the pair, so they are skipped when a
+        // minimum rule length is configured.
+        let mut rules_created = 0;
+        if self.config.min_rule_length <= 2 {
+            let min_uses = MIN_PAIR_USES.max(self.config.min_rule_uses as usize);
+            let mut repair = RePair::new(self, min_uses);
+            for &head in sequences {
+                repair.add_sequence(head);
+            }
+            rules_created = repair.run();
+        }
+
+        rules_inlined += self.recount_and_inline_single_use(root_rule, doc_heads);
+        if !self.config.is_classic() {
+            // Re-pairing may leave nested rules with fewer than the minimum uses.
+            rules_inlined += self.inline_cheap_rules(sequences, doc_heads);
+            rules_inlined += self.recount_and_inline_single_use(root_rule, doc_heads);
+            self.rebuild_relaxed_index(doc_heads);
+        } else {
+            self.rebuild_digram_index(doc_heads);
+        }
+
+        OptimizationReport {
+            cost_before,
+            cost_after: self.cost(root_rule, doc_heads),
+            rules_inlined,
+            rules_created,
+        }
+    }
+
+    /// Returns the number of symbols in all bodies plus one per rule.
+    fn cost(&self, root_rule: Option<u32>, doc_heads: &[DefaultKey]) -> usize {
+        let rules = self.rule_index.len() - usize::from(root_rule.is_some());
+        let symbols: usize = self
+            .rule_index
+            .values()
+            .chain(doc_heads)
+            .map(|&head| self.body_keys(head).count())
+            .sum();
+        symbols + rules
+    }
+
+    /// Inlines every rule whose removal does not increase the cost, or that is
+    /// used fewer than the configured minimum, children before parents, and
+    /// returns how many were inlined.
+    ///
+    /// A rule with body length `L` used `c` times costs `L + 1`, and inlining it
+    /// adds `c * (L - 1)` symbols.
+    fn inline_cheap_rules(&mut self, sequences: &[DefaultKey], doc_heads: &[DefaultKey]) -> usize {
+        let mut refs = self.rule_refs(doc_heads);
+        let min_uses = self.config.min_rule_uses as usize;
+        let mut inlined = 0;
+
+        for rule_id in self.rules_children_first(sequences) {
+            let head = self.rule_index[&rule_id];
+            let len = self.body_keys(head).count();
+            let uses = refs.get(&rule_id).map_or(0, Vec::len);
+            if uses > 0 && (uses < min_uses || uses * (len - 1) <= len + 1) {
+                self.inline_everywhere(rule_id, &mut refs);
+                inlined += 1;
+            }
+        }
+        inlined
+    }
+
+    /// Lists the rules reachable from `sequences` in post-order.
+    pub(crate) fn rules_children_first(&self, sequences: &[DefaultKey]) -> Vec<u32> {
+        let mut order = Vec::with_capacity(self.rule_index.len());
+        let mut visited = HashSet::default();
+        // (head, finished): a rule is emitted once all its children are.
+        let mut stack: Vec<(DefaultKey, Option<u32>)> =
+            sequences.iter().map(|&head| (head, None)).collect();
+
+        while let Some((head, rule_id)) = stack.pop() {
+            if let Some(rule_id) = rule_id {
+                order.push(rule_id);
+                continue;
+            }
+            let rule_id = match self.symbols[head].symbol {
+                Symbol::RuleHead { rule_id, .. } if !sequences.contains(&head) => Some(rule_id),
+                _ => None,
+            };
+            if let Some(rule_id) = rule_id {
+                // A rule is expanded once, when it is first popped, so it is
+                // emitted after every rule it references.
+                if !visited.insert(rule_id) {
+                    continue;
+                }
+                stack.push((head, Some(rule_id)));
+            }
+            for key in self.body_keys(head) {
+                if let Symbol::RuleRef { rule_id } = self.symbols[key].symbol {
+                    if !visited.contains(&rule_id) {
+                        stack.push((self.rule_index[&rule_id], None));
+                    }
+                }
+            }
+        }
+        order
+    }
+
+    /// Replaces every reference to a rule with a copy of its body and deletes
+    /// the rule, keeping `refs` up to date.
+    pub(crate) fn inline_everywhere(
+        &mut self,
+        rule_id: u32,
+        refs: &mut HashMap<u32, Vec<DefaultKey>>,
+    ) {
+        let Some(locations) = refs.remove(&rule_id) else {
+            return;
+        };
+        let head = self.rule_index[&rule_id];
+        let body: Vec<Symbol<T>> = self
+            .body_keys(head)
+            .map(|key| self.symbols[key].symbol.clone_symbol())
+            .collect();
+
+        for &location in &locations[1..] {
+            let before = self.symbols[location].prev;
+            let after = self.symbols[location].next;
+            let mut prev = before;
+            for symbol in &body {
+                let key = self.symbols.insert(SymbolNode::new(symbol.clone_symbol()));
+                if let Symbol::RuleRef { rule_id } = symbol {
+                    refs.entry(*rule_id).or_default().push(key);
+                }
+                self.symbols[key].prev = prev;
+                if let Some(prev) = prev {
+                    self.symbols[prev].next = Some(key);
+                }
+                prev = Some(key);
+            }
+            if let Some(prev) = prev {
+                self.symbols[prev].next = after;
+            }
+            if let Some(after) = after {
+                self.symbols[after].prev = prev;
+            }
+            self.symbols.remove(location);
+        }
+
+        self.inline_rule(rule_id, locations[0]);
+    }
+
+    /// Rebuilds the digram index from scratch, indexing the first occurrence of
+    /// every digram.
+    fn rebuild_digram_index(&mut self, doc_heads: &[DefaultKey]) {
+        self.digram_index.clear();
+        let heads: Vec<_> = self.rule_index.values().chain(doc_heads).copied().collect();
+        for head in heads {
+            let keys: Vec<_> = self.body_keys(head).collect();
+            for pair in keys.windows(2) {
+                self.find_and_add_digram(pair[0], pair[1]);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_optimize_roundtrip() {
+        let input = "abracadabra abracadabra cadabra abra";
+        let mut seq = Sequitur::new();
+        seq.extend(input.chars());
+
+        let report = seq.optimize();
+        assert_eq!(seq.iter().collect::<String>(), input);
+        assert_eq!(seq.len(), input.len());
+        assert_eq!(report.saved(), report.cost_before - report.cost_after);
+        assert_eq!(
+            report.cost_after,
+            seq.stats().grammar_symbols + seq.stats().num_rules - 1
+        );
+    }
+
+    #[test]
+    fn test_optimize_inlines_small_rules() {
+        // A two-symbol rule used twice costs more than it saves.
+        let mut seq = Sequitur::new();
+        seq.extend("abXab".chars());
+        let before = seq.stats();
+
+        let report = seq.optimize();
+        assert!(report.rules_inlined > 0);
+        assert!(report.saved() > 0);
+        assert!(seq.stats().num_rules < before.num_rules);
+        assert_eq!(seq.iter().collect::<String>(), "abXab");
+    }
+
+    #[test]
+    fn test_optimize_repairs_frequent_pairs() {
+        // After inlining, "ab" occurs often enough in Rule 0 to pay for a rule.
+        let input = "abXabYabZabWabV";
+        let mut seq = Sequitur::new();
+        seq.extend(input.chars());
+
+        let report = seq.optimize();
+        assert_eq!(seq.iter().collect::<String>(), input);
+        assert!(seq.stats().num_rules >= 2);
+        assert!(report.cost_after <= report.cost_before);
+    }
+
+    #[test]
+    fn test_push_after_optimize() {
+        let mut seq = Sequitur::new();
+        seq.extend("abcabcabdabd".chars());
+        seq.optimize();
+        seq.extend("abcabdxyxy".chars());
+
+        assert_eq!(seq.iter().collect::<String>(), "abcabcabdabdabcabdxyxy");
+    }
+
+    #[test]
+    fn test_optimize_is_idempotent_on_cost() {
+        let input: Vec<u8> = (0..3000u32).map(|i| ((i * i) % 17) as u8).collect();
+        let mut seq = Sequitur::new();
+        seq.extend(input.iter().copied());
+
+        let first = seq.optimize();
+        let second = seq.optimize();
+        assert!(second.cost_after <= first.cost_after);
+        assert!(seq.iter().copied().eq(input.iter().copied()));
+    }
+
+    #[test]
+    fn test_rules_children_first() {
+        let input: Vec<u8> = (0..2000u32).map(|i| ((i * i) % 23) as u8).collect();
+        let mut seq = Sequitur::new();
+        seq.extend(input);
+
+        let head = seq.grammar.rule_index[&0];
+        let order = seq.grammar.rules_children_first(&[head]);
+        assert_eq!(order.len(), seq.rules().len() - 1);
+
+        let position: HashMap<u32, usize> =
+            order.iter().enumerate().map(|(i, &id)| (id, i)).collect();
+        for &rule_id in &order {
+            for key in seq.grammar.body_keys(seq.grammar.rule_index[&rule_id]) {
+                if let Symbol::RuleRef { rule_id: child } = seq.grammar.symbols[key].symbol {
+                    assert!(position[&child] < position[&rule_id]);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_optimize_documents() {
+        let mut docs = SequiturDocuments::new();
+        docs.extend_document(1, "hello world, hello there".chars());
+        docs.extend_document(2, "world of hello".chars());
+
+        let report = docs.optimize();
+        assert!(report.cost_after <= report.cost_before);
+
+        let doc1: String = docs.iter_document(&1).unwrap().collect();
+        let doc2: String = docs.iter_document(&2).unwrap().collect();
+        assert_eq!(doc1, "hello world, hello there");
+        assert_eq!(doc2, "world of hello");
+
+        docs.extend_document(2, " world".chars());
+        let doc2: String = docs.iter_document(&2).unwrap().collect();
+        assert_eq!(doc2, "world of hello world");
+    }
+}
@@ -0,0 +1,150 @@
+use crate::merge::Merger;
+use crate::sequitur::Sequitur;
+use std::hash::Hash;
+use std::thread;
+
+impl<T: Hash + Eq + Clone + Send + Sync> Sequitur<T> {
+    /// Builds a grammar for `input` on `threads` threads.
+    ///
+    /// The input is split into contiguous chunks that are compressed
+    /// independently, then the chunk grammars are merged: rules with identical
+    /// expansions are unified, and digram uniqueness and rule utility are
+    /// re-established across the whole grammar, including chunk boundaries.
+    ///
+    /// The result is an ordinary [`Sequitur`] that iterates to `input` and can be
+    /// extended with [`push`](Self::push). It is usually slightly larger than one
+    /// built sequentially.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `threads` is zero.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use sequitur_rs::Sequitur;
+    ///
+    /// let input: Vec<u8> = b"abcabdabcabd".repeat(100);
+    /// let seq = Sequitur::build_parallel(&input, 4);
+    ///
+    /// assert!(seq.iter().eq(input.iter()));
+    /// ```
+    pub fn build_parallel(input: &[T], threads: usize) -> Self {
+        assert!(threads > 0, "at least one thread is required");
+        let chunk_len = input.len().div_ceil(threads).max(1);
+
+        let chunks: Vec<Self> = thread::scope(|scope| {
+            let handles: Vec<_> = input
+                .chunks(chunk_len)
+                .map(|chunk| {
+                    scope.spawn(move || {
+                        let mut seq = Self::new();
+                        seq.extend(chunk.iter().cloned());
+                        seq
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().expect("chunk compression panicked"))
+                .collect()
+        });
+
+        Self::merge_chunks(chunks)
+    }
+
+    /// Merges grammars of consecutive chunks into a grammar of their
+    /// concatenation.
+    fn merge_chunks(chunks: Vec<Self>) -> Self {
+        let mut merged = Self::new();
+        let mut merger = Merger::default();
+
+        for chunk in chunks {
+            let head = chunk.grammar.rule_index[&0];
+            let ids = merger.import_rules(&mut merged.grammar, &chunk.grammar, &[head]);
+            for symbol in Merger::translate(&chunk.grammar, head, &ids) {
+                merged.append_unlinked(symbol);
+            }
+            merged.length += chunk.length;
+        }
+
+        let head = merged.grammar.rule_index[&0];
+        // A chunk rule can be imported as a child of a rule that then turns out
+        // to duplicate an existing one, leaving it unreferenced.
+        merged.grammar.remove_unreferenced_rules(&[head]);
+        merged.grammar.recount_and_inline_single_use(Some(0), &[]);
+        merged.grammar.enforce_digram_uniqueness(&[head]);
+        merged
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::symbol::Symbol;
+
+    fn assert_constraints<T: Hash + Eq + Clone>(seq: &Sequitur<T>) {
+        let refs = seq.grammar.rule_refs(&[]);
+        for (&rule_id, &head) in seq.rules() {
+            if rule_id == 0 {
+                continue;
+            }
+            let Symbol::RuleHead { count, .. } = seq.grammar.symbols[head].symbol else {
+                unreachable!();
+            };
+            assert!(count >= 2, "rule {rule_id} used {count} times");
+            assert_eq!(count as usize, refs[&rule_id].len());
+        }
+    }
+
+    #[test]
+    fn test_parallel_roundtrip() {
+        let input: Vec<u32> = (0..5000).map(|i| (i * i) % 37).collect();
+        for threads in [1, 2, 3, 8] {
+            let seq = Sequitur::build_parallel(&input, threads);
+            assert!(seq.iter().eq(input.iter()));
+            assert_eq!(seq.len(), input.len());
+            assert_constraints(&seq);
+        }
+    }
+
+    #[test]
+    fn test_parallel_unifies_chunk_rules() {
+        // Every chunk holds the same text, so their rules should be shared.
+        let input = "the quick brown fox. ".repeat(16);
+        let chars: Vec<char> = input.chars().collect();
+        let sequential = Sequitur::build_parallel(&chars, 1);
+        let parallel = Sequitur::build_parallel(&chars, 4);
+
+        assert_eq!(parallel.iter().collect::<String>(), input);
+        assert!(parallel.rules().len() <= sequential.rules().len() + 2);
+        assert_constraints(&parallel);
+    }
+
+    #[test]
+    fn test_parallel_small_inputs() {
+        assert!(Sequitur::<u8>::build_parallel(&[], 4).is_empty());
+
+        let seq = Sequitur::build_parallel(b"ab", 4);
+        assert_eq!(seq.iter().copied().collect::<Vec<_>>(), b"ab");
+    }
+
+    #[test]
+    fn test_parallel_rule_inlined_during_replay() {
+        // Replaying Rule 0 inlines a rule while the links around a new rule
+        // are still being checked.
+        let input = [2u8, 1, 2, 2, 1, 1, 2, 2, 1, 1, 0, 0, 2, 2, 1, 1];
+        let seq = Sequitur::build_parallel(&input, 2);
+        assert!(seq.iter().eq(input.iter()));
+        assert!(seq.try_iter().is_ok());
+        assert_constraints(&seq);
+    }
+
+    #[test]
+    fn test_push_after_parallel() {
+        let mut seq = Sequitur::build_parallel(b"abcabcabcabc", 3);
+        seq.extend(b"abcd".iter().copied());
+        assert!(seq.iter().eq(b"abcabcabcabcabcd".iter()));
+        assert_constraints(&seq);
+    }
+}
@@ -0,0 +1,599 @@
+use crate::grammar::Grammar;
+use crate::symbol::{Symbol, SymbolHash, SymbolNode};
+use ahash::AHashSet as HashSet;
+use slotmap::DefaultKey;
+use std::hash::Hash;
+use std::iter;
+
+/// Digram identity used as the key of the digram index.
+type Digram = (SymbolHash, SymbolHash);
+
+/// Generalized grammar maintenance for non-default [`GrammarConfig`]s.
+///
+/// With `k = min_rule_uses` and `n = min_rule_length`, a rule is created once a
+/// repeat occurs `k` times without overlapping and expands to at least `n`
+/// values, and a rule used fewer than `k` times is inlined at every use. Since
+/// digrams may then repeat, every occurrence is tracked: the digram index holds
+/// one occurrence and `pending_digrams` the others. Both are validated lazily,
+/// so entries for symbols that have since been removed or relinked are simply
+/// skipped.
+///
+/// [`GrammarConfig`]: crate::builder::GrammarConfig
+impl<T: Hash + Eq + Clone> Grammar<T> {
+    // ========================================================================
+    // Digram Tracking
+    // ========================================================================
+
+    /// Returns the digram starting at `key`, if `key` is live and neither symbol
+    /// is a sentinel.
+    pub(crate) fn digram_at(&self, key: DefaultKey) -> Option<Digram> {
+        let node = self.symbols.get(key)?;
+        if self.is_sequence_start(&node.symbol) {
+            return None;
+        }
+        let next = &self.symbols[node.next?].symbol;
+        if self.is_sequence_end(next) {
+            return None;
+        }
+        Some((
+            SymbolHash::from_symbol(&node.symbol),
+            SymbolHash::from_symbol(next),
+        ))
+    }
+
+    /// Checks that the digrams starting at `a` and `b` are equal (hash collision
+    /// check).
+    fn digrams_equal(&self, a: DefaultKey, b: DefaultKey) -> bool {
+        let (a_next, b_next) = (self.symbols[a].next.unwrap(), self.symbols[b].next.unwrap());
+        self.symbols[a].symbol.equals(&self.symbols[b].symbol)
+            && self.symbols[a_next]
+                .symbol
+                .equals(&self.symbols[b_next].symbol)
+    }
+
+    /// Records an occurrence of a digram.
+    fn record_digram(&mut self, digram: Digram, key: DefaultKey) {
+        match self.digram_index.get(&digram).copied() {
+            Some(indexed) if indexed == key => {}
+            Some(indexed) if self.digram_at(indexed) == Some(digram) => {
+                self.pending_digrams.entry(digram).or_default().push(key);
+            }
+            _ => {
+                self.digram_index.insert(digram, key);
+            }
+        }
+    }
+
+    /// Returns the live occurrences of the digram at `first`, other than `first`
+    /// itself, and drops stale entries from the index.
+    fn live_occurrences(&mut self, digram: Digram, first: DefaultKey) -> Vec<DefaultKey> {
+        let mut stored: Vec<DefaultKey> = self
+            .digram_index
+            .get(&digram)
+            .copied()
+            .into_iter()
+            .collect();
+        stored.extend(self.pending_digrams.remove(&digram).unwrap_or_default());
+
+        let mut seen = HashSet::default();
+        stored.retain(|&key| seen.insert(key) && self.digram_at(key) == Some(digram));
+
+        let live = stored
+            .iter()
+            .copied()
+            .filter(|&key| key != first && self.digrams_equal(key, first))
+            .collect();
+
+        match stored.split_first() {
+            Some((&indexed, rest)) => {
+                self.digram_index.insert(digram, indexed);
+                if !rest.is_empty() {
+                    self.pending_digrams.insert(digram, rest.to_vec());
+                }
+            }
+            None => {
+                self.digram_index.remove(&digram);
+            }
+        }
+        live
+    }
+
+    /// Records every digram and RuleRef in the given sequences from scratch.
+    pub(crate) fn rebuild_relaxed_index(&mut self, doc_heads: &[DefaultKey]) {
+        self.digram_index.clear();
+        self.pending_digrams.clear();
+        self.ref_locations.clear();
+        self.expansion_lengths.clear();
+
+        let heads: Vec<_> = self.rule_index.values().chain(doc_heads).copied().collect();
+        for head in heads {
+            let keys: Vec<_> = self.body_keys(head).collect();
+            for &key in &keys {
+                if let Symbol::RuleRef { rule_id } = self.symbols[key].symbol {
+                    self.note_ref(rule_id, key);
+                }
+                if let Some(digram) = self.digram_at(key) {
+                    self.record_digram(digram, key);
+                }
+            }
+        }
+    }
+
+    // ========================================================================
+    // Segments
+    // ========================================================================
+
+    /// Returns the keys of the `len` symbols starting at `start`.
+    fn segment(&self, start: DefaultKey, len: usize) -> Vec<DefaultKey> {
+        iter::successors(Some(start), |&key| self.symbols[key].next)
+            .take(len)
+            .collect()
+    }
+
+    /// Returns the expanded length of the `len` symbols starting at `start`.
+    fn segment_expanded_len(&mut self, start: DefaultKey, len: usize) -> usize {
+        let rule_ids: Vec<Option<u32>> = self
+            .segment(start, len)
+            .into_iter()
+            .map(|key| match self.symbols[key].symbol {
+                Symbol::RuleRef { rule_id } => Some(rule_id),
+                _ => None,
+            })
+            .collect();
+        rule_ids
+            .into_iter()
+            .map(|rule_id| rule_id.map_or(1, |rule_id| self.expanded_len(rule_id)))
+            .sum()
+    }
+
+    /// Returns the expanded length of a rule, caching it.
+    ///
+    /// A rule's expansion never changes while it exists, so the cache is only
+    /// cleared when the rule is deleted.
+    fn expanded_len(&mut self, rule_id: u32) -> usize {
+        if let Some(&len) = self.expansion_lengths.get(&rule_id) {
+            return len;
+        }
+        let head = self.rule_index[&rule_id];
+        let body_len = self.body_keys(head).count();
+        let first = self.symbols[head].next.unwrap();
+        let len = self.segment_expanded_len(first, body_len);
+        self.expansion_lengths.insert(rule_id, len);
+        len
+    }
+
+    /// Picks, in order, the segments that don't overlap any segment picked
+    /// before them.
+    fn disjoint_segments<I>(&self, starts: I, len: usize) -> Vec<DefaultKey>
+    where
+        I: IntoIterator<Item = DefaultKey>,
+    {
+        let mut used = HashSet::default();
+        let mut picked = Vec::new();
+        for start in starts {
+            let keys = self.segment(start, len);
+            if keys.iter().all(|key| !used.contains(key)) {
+                used.extend(keys);
+                picked.push(start);
+            }
+        }
+        picked
+    }
+
+    /// Orders segments so that overlapping ones, which lie in the same run, come
+    /// in sequence order and [`disjoint_segments`](Self::disjoint_segments)
+    /// keeps every other one of them.
+    fn in_sequence_order(&self, starts: &[DefaultKey], len: usize) -> Vec<DefaultKey> {
+        let members: HashSet<_> = starts.iter().copied().collect();
+        let mut ordered = Vec::with_capacity(starts.len());
+        for &start in starts {
+            // Segments overlapping an earlier one are emitted from that one.
+            let overlapping =
+                iter::successors(self.symbols[start].prev, |&key| self.symbols[key].prev)
+                    .take(len - 1)
+                    .any(|key| members.contains(&key));
+            if overlapping {
+                continue;
+            }
+
+            let mut gap = 0;
+            let mut current = Some(start);
+            while let Some(key) = current.filter(|_| gap < len) {
+                if members.contains(&key) {
+                    ordered.push(key);
+                    gap = 0;
+                }
+                gap += 1;
+                current = self.symbols[key].next;
+            }
+        }
+        ordered
+    }
+
+    /// Returns the RuleHead of the rule whose whole body is the `len` symbols
+    /// starting at `start`, if there is one.
+    fn whole_rule(&self, start: DefaultKey, len: usize) -> Option<DefaultKey> {
+        let head = self.symbols[start].prev?;
+        // Rule 0 has a count of zero and must never be referenced.
+        let Symbol::RuleHead { count, tail, .. } = self.symbols[head].symbol else {
+            return None;
+        };
+        let last = *self.segment(start, len).last()?;
+        (count > 0 && self.symbols[last].next == Some(tail)).then_some(head)
+    }
+
+    // ========================================================================
+    // Rule Maintenance
+    // ========================================================================
+
+    /// Relaxed counterpart of [`link_made`](Self::link_made).
+    ///
+    /// Extends the new digram backwards over symbols shared with its other
+    /// occurrences until either a whole rule body matches, which is reused, or
+    /// enough non-overlapping occurrences of a long enough repeat are found, which
+    /// become a new rule. Otherwise the occurrence is just recorded.
+    pub(crate) fn link_made_relaxed(&mut self, first: DefaultKey) {
+        let Some(digram) = self.digram_at(first) else {
+            return;
+        };
+        let candidates = self.live_occurrences(digram, first);
+        let mut candidates = self.in_sequence_order(&candidates, 2);
+        let min_uses = self.config.min_rule_uses as usize;
+        let min_length = self.config.min_rule_length;
+
+        let mut start = first;
+        let mut len = 2;
+        while !candidates.is_empty() {
+            for &candidate in &candidates {
+                if let Some(head) = self.whole_rule(candidate, len) {
+                    if self.disjoint_segments([start, candidate], len).len() == 2 {
+                        let rule_ref = self.replace_segment(start, len, head);
+                        self.after_replacement(&[rule_ref], head);
+                        return;
+                    }
+                }
+            }
+
+            let expanded = (min_length > 2).then(|| self.segment_expanded_len(start, len));
+            if expanded.is_none_or(|expanded| expanded >= min_length) {
+                let starts = self
+                    .disjoint_segments(iter::once(start).chain(candidates.iter().copied()), len);
+                if starts.len() >= min_uses {
+                    self.create_rule_from_segments(&starts, len, expanded);
+                    return;
+                }
+            }
+
+            // Extend every occurrence by the symbol before it.
+            let Some(prev) = self.symbols[start].prev else {
+                break;
+            };
+            if self.is_sequence_start(&self.symbols[prev].symbol) {
+                break;
+            }
+            candidates.retain_mut(|candidate| match self.symbols[*candidate].prev {
+                Some(before)
+                    if !self.is_sequence_start(&self.symbols[before].symbol)
+                        && self.symbols[before]
+                            .symbol
+                            .equals(&self.symbols[prev].symbol) =>
+                {
+                    *candidate = before;
+                    true
+                }
+                _ => false,
+            });
+            start = prev;
+            len += 1;
+        }
+
+        self.record_digram(digram, first);
+    }
+
+    /// Creates a rule from non-overlapping equal segments, the first of which is
+    /// copied into the rule body, and replaces them all.
+    fn create_rule_from_segments(
+        &mut self,
+        starts: &[DefaultKey],
+        len: usize,
+        expanded: Option<usize>,
+    ) {
+        let rule_id = self.id_gen.get();
+        let tail = self.symbols.insert(SymbolNode::new(Symbol::RuleTail));
+        let head = self.symbols.insert(SymbolNode::new(Symbol::RuleHead {
+            rule_id,
+            count: 0,
+            tail,
+        }));
+
+        let mut prev = head;
+        for key in self.segment(starts[0], len) {
+            let copy = self
+                .symbols
+                .insert(SymbolNode::new(self.symbols[key].symbol.clone_symbol()));
+            self.symbols[copy].prev = Some(prev);
+            self.symbols[prev].next = Some(copy);
+            self.increment_if_rule(copy);
+            if let Symbol::RuleRef { rule_id } = self.symbols[copy].symbol {
+                self.note_ref(rule_id, copy);
+            }
+            prev = copy;
+        }
+        self.symbols[prev].next = Some(tail);
+        self.symbols[tail].prev = Some(prev);
+
+        self.rule_index.insert(rule_id, head);
+        if let Some(expanded) = expanded {
+            self.expansion_lengths.insert(rule_id, expanded);
+        }
+
+        let refs: Vec<_> = starts
+            .iter()
+            .map(|&start| self.replace_segment(start, len, head))
+            .collect();
+
+        let body: Vec<_> = self.body_keys(head).collect();
+        for key in body {
+            if let Some(digram) = self.digram_at(key) {
+                self.record_digram(digram, key);
+            }
+        }
+
+        self.after_replacement(&refs, head);
+    }
+
+    /// Replaces the `len` symbols starting at `start` with a reference to the
+    /// rule at `head`, returning the key of the new RuleRef.
+    fn replace_segment(&mut self, start: DefaultKey, len: usize, head: DefaultKey) -> DefaultKey {
+        let keys = self.segment(start, len);
+        let before = self.symbols[start].prev;
+        let after = self.symbols[*keys.last().unwrap()].next;
+
+        for &key in &keys {
+            self.decrement_if_rule(key);
+        }
+
+        let Symbol::RuleHead { rule_id, .. } = self.symbols[head].symbol else {
+            unreachable!("rule_head must be a RuleHead");
+        };
+        let rule_ref = self
+            .symbols
+            .insert(SymbolNode::new(Symbol::RuleRef { rule_id }));
+        self.symbols[rule_ref].prev = before;
+        self.symbols[rule_ref].next = after;
+        if let Some(before) = before {
+            self.symbols[before].next = Some(rule_ref);
+        }
+        if let Some(after) = after {
+            self.symbols[after].prev = Some(rule_ref);
+        }
+        self.increment_rule_count(head);
+        self.note_ref(rule_id, rule_ref);
+
+        for key in keys {
+            self.symbols.remove(key);
+        }
+        rule_ref
+    }
+
+    /// Restores the constraints after segments were replaced with references to
+    /// the rule at `head`: rules in the body that lost uses are inlined, then the
+    /// digrams around the new references are checked.
+    fn after_replacement(&mut self, refs: &[DefaultKey], head: DefaultKey) {
+        let body: Vec<_> = self.body_keys(head).collect();
+        for key in body {
+            self.expand_relaxed(key);
+        }
+
+        for &rule_ref in refs {
+            if let Some(prev) = self.symbols.get(rule_ref).and_then(|node| node.prev) {
+                self.link_made_relaxed(prev);
+            }
+            self.link_made_relaxed(rule_ref);
+        }
+    }
+
+    /// Relaxed counterpart of [`expand_rule_if_necessary`](Self::expand_rule_if_necessary).
+    ///
+    /// Inlines the referenced rule at every use if it is used fewer than
+    /// `min_rule_uses` times. All but one use get a copy of the body, which is
+    /// then moved into the last one.
+    pub(crate) fn expand_relaxed(&mut self, key: DefaultKey) {
+        let Some(Symbol::RuleRef { rule_id }) = self.symbols.get(key).map(|node| &node.symbol)
+        else {
+            return;
+        };
+        let rule_id = *rule_id;
+        let Some(&head) = self.rule_index.get(&rule_id) else {
+            return;
+        };
+        let Symbol::RuleHead { count, tail, .. } = self.symbols[head].symbol else {
+            unreachable!("rule index should only point at RuleHeads");
+        };
+        if count >= self.config.min_rule_uses {
+            return;
+        }
+
+        let locations = self.take_ref_locations(rule_id, count);
+        let Some((&last, copies)) = locations.split_last() else {
+            return;
+        };
+
+        let body: Vec<Symbol<T>> = self
+            .body_keys(head)
+            .map(|key| self.symbols[key].symbol.clone_symbol())
+            .collect();
+        let body_first = self.symbols[head].next.expect("RuleHead should have next");
+        let body_last = self.symbols[tail].prev.expect("RuleTail should have prev");
+
+        self.rule_index.remove(&rule_id);
+        let freed = self.id_gen.free(rule_id);
+        debug_assert!(freed, "rule {rule_id} should have an allocated ID");
+        self.expansion_lengths.remove(&rule_id);
+
+        // Digrams that were formed by the inlining, by their first key.
+        let mut relink = Vec::new();
+
+        for &location in copies {
+            let before = self.symbols[location].prev;
+            let after = self.symbols[location].next;
+            relink.extend(before);
+
+            let mut prev = before;
+            for symbol in &body {
+                let copy = self.symbols.insert(SymbolNode::new(symbol.clone_symbol()));
+                self.increment_if_rule(copy);
+                if let Symbol::RuleRef { rule_id } = symbol {
+                    self.note_ref(*rule_id, copy);
+                }
+                self.symbols[copy].prev = prev;
+                if let Some(prev) = prev {
+                    self.symbols[prev].next = Some(copy);
+                }
+                relink.push(copy);
+                prev = Some(copy);
+            }
+            if let Some(prev) = prev {
+                self.symbols[prev].next = after;
+            }
+            if let Some(after) = after {
+                self.symbols[after].prev = prev;
+            }
+            self.symbols.remove(location);
+        }
+
+        let before = self.symbols[last].prev;
+        let after = self.symbols[last].next;
+        self.symbols[body_first].prev = before;
+        self.symbols[body_last].next = after;
+        if let Some(before) = before {
+            self.symbols[before].next = Some(body_first);
+        }
+        if let Some(after) = after {
+            self.symbols[after].prev = Some(body_last);
+        }
+        relink.extend(before);
+        relink.push(body_last);
+
+        self.symbols.remove(head);
+        self.symbols.remove(tail);
+        self.symbols.remove(last);
+
+        for key in relink {
+            self.link_made_relaxed(key);
+        }
+    }
+
+    /// Remembers the location of a new RuleRef.
+    pub(crate) fn note_ref(&mut self, rule_id: u32, key: DefaultKey) {
+        let symbols = &self.symbols;
+        let locations = self.ref_locations.entry(rule_id).or_default();
+        locations.push(key);
+
+        // Drop locations that have since been replaced, amortized over pushes.
+        if locations.len() >= 16 && locations.len().is_power_of_two() {
+            locations.retain(|&key| {
+                matches!(
+                    symbols.get(key).map(|node| &node.symbol),
+                    Some(Symbol::RuleRef { rule_id: id }) if *id == rule_id
+                )
+            });
+        }
+    }
+
+    /// Removes and returns the locations of the `count` RuleRefs to a rule.
+    ///
+    /// Every RuleRef is recorded when it is created, or when the bookkeeping
+    /// is rebuilt, so only locations that have since been replaced need to be
+    /// dropped.
+    fn take_ref_locations(&mut self, rule_id: u32, count: u32) -> Vec<DefaultKey> {
+        let is_ref = |node: &SymbolNode<T>| matches!(node.symbol, Symbol::RuleRef { rule_id: id } if id == rule_id);
+
+        let mut seen = HashSet::default();
+        let mut locations = self.ref_locations.remove(&rule_id).unwrap_or_default();
+        locations.retain(|&key| seen.insert(key) && self.symbols.get(key).is_some_and(is_ref));
+        debug_assert_eq!(
+            locations.len(),
+            count as usize,
+            "RuleRef locations of rule {rule_id} should be complete"
+        );
+        locations
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Sequitur;
+
+    #[test]
+    fn test_third_occurrence_creates_rule() {
+        let mut seq = Sequitur::builder().min_rule_uses(3).build();
+        seq.extend("abXab".chars());
+        assert_eq!(seq.rules().len(), 1);
+
+        seq.extend("Yab".chars());
+        assert_eq!(seq.rules().len(), 2);
+        assert_eq!(seq.iter().collect::<String>(), "abXabYab");
+    }
+
+    #[test]
+    fn test_underused_rule_is_inlined() {
+        // "ab" becomes a rule used three times, then all three uses are absorbed
+        // into "abc", which leaves it with a single use.
+        let input = "abcabcabc";
+        let mut seq = Sequitur::builder().min_rule_uses(3).build();
+        seq.extend(input.chars());
+
+        assert_eq!(seq.rules().len(), 2);
+        let lengths = seq.grammar.rule_lengths();
+        assert!(lengths.iter().any(|(&id, &len)| id != 0 && len == 3));
+        assert_eq!(seq.iter().collect::<String>(), input);
+    }
+
+    #[test]
+    fn test_repeat_grows_until_long_enough() {
+        let mut seq = Sequitur::builder().min_rule_length(3).build();
+        seq.extend("abXab".chars());
+        assert_eq!(seq.rules().len(), 1);
+
+        seq.extend("cYabc".chars());
+        assert_eq!(seq.rules().len(), 2);
+        assert_eq!(seq.iter().collect::<String>(), "abXabcYabc");
+    }
+
+    #[test]
+    fn test_pushes_after_removing_document() {
+        // Removing a document rebuilds the bookkeeping of the remaining rules;
+        // the last push then inlines a rule that existed before the removal.
+        let mut docs = Sequitur::builder().min_rule_uses(3).build_documents();
+        docs.extend_document(1, "bb".chars());
+        docs.extend_document(2, "cbcbacbacc".chars());
+        docs.remove_document(&1);
+
+        docs.extend_document(2, "ba".chars());
+        assert_eq!(
+            docs.iter_document(&2).unwrap().collect::<String>(),
+            "cbcbacbaccba"
+        );
+        assert!(docs.try_iter_document(&2).is_ok());
+    }
+
+    #[test]
+    fn test_overlapping_runs() {
+        let input = "a".repeat(40);
+        let mut seq = Sequitur::builder().min_rule_uses(3).build();
+        seq.extend(input.chars());
+        assert_eq!(seq.iter().collect::<String>(), input);
+    }
+
+    #[test]
+    fn test_overlapping_occurrences_picked_in_order() {
+        // Of the overlapping "aa aa" occurrences in "aa aa aa aa", the first and
+        // third are picked, so the trailing one makes three.
+        let mut seq = Sequitur::builder().min_rule_uses(3).build();
+        seq.extend("abaaaaaaaaababaaaa".chars());
+        assert_eq!(seq.iter().collect::<String>(), "abaaaaaaaaababaaaa");
+        assert_eq!(seq.rules().len(), 3);
+        assert_eq!(seq.grammar.body_keys(seq.rules()[&0]).count(), 6);
+    }
+}
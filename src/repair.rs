@@ -0,0 +1,562 @@
+use crate::grammar::Grammar;
+use crate::interner::Interner;
+use crate::sequitur::Sequitur;
+use crate::symbol::{Symbol, SymbolHash, SymbolNode};
+use ahash::{AHashMap as HashMap, AHashSet as HashSet};
+use slotmap::{DefaultKey, SecondaryMap};
+use std::collections::BinaryHeap;
+use std::hash::Hash;
+use std::iter;
+
+/// Grammar construction algorithm.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Algorithm {
+    /// Online Sequitur: values are processed one at a time as they arrive
+    #[default]
+    Sequitur,
+    /// Offline Re-Pair: the whole input is read first, then the most frequent
+    /// pair is repeatedly replaced by a new rule. Slower and needs the full input,
+    /// but usually produces smaller grammars.
+    RePair,
+}
+
+/// Compact code identifying a symbol in the sequence being compressed.
+///
+/// Values use their interned index, rule references have the high bit set.
+type Code = u64;
+
+const RULE_CODE: Code = 1 << 63;
+
+impl<T: Hash + Eq + Clone> Sequitur<T> {
+    /// Builds a grammar for `iter` with the chosen algorithm.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use sequitur_rs::{Algorithm, Sequitur};
+    ///
+    /// let input = "abcabdabcabd";
+    /// let online = Sequitur::compress(input.chars(), Algorithm::Sequitur);
+    /// let offline = Sequitur::compress(input.chars(), Algorithm::RePair);
+    ///
+    /// assert_eq!(online.iter().collect::<String>(), input);
+    /// assert_eq!(offline.iter().collect::<String>(), input);
+    /// ```
+    pub fn compress<I: IntoIterator<Item = T>>(iter: I, algorithm: Algorithm) -> Self {
+        match algorithm {
+            Algorithm::Sequitur => {
+                let mut seq = Self::new();
+                seq.extend(iter);
+                seq
+            }
+            Algorithm::RePair => Self::repair(iter),
+        }
+    }
+
+    /// Builds a grammar for `iter` with the offline Re-Pair algorithm.
+    ///
+    /// While some pair of adjacent symbols occurs at least twice without
+    /// overlapping, the most frequent one is replaced everywhere by a new rule.
+    /// Rules left with a single use afterwards are inlined, and digrams repeated
+    /// across rule bodies are factored out, so the result satisfies both Sequitur
+    /// constraints.
+    ///
+    /// The result is an ordinary [`Sequitur`]: it can be iterated, inspected and
+    /// extended with [`push`](Self::push) like one built online.
+    pub fn repair<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut seq = Self::new();
+        let head = seq.grammar.rule_index[&0];
+
+        for value in iter {
+            seq.append_unlinked(Symbol::Value(value));
+            seq.length += 1;
+        }
+
+        let mut repair = RePair::new(&mut seq.grammar, 2);
+        repair.add_sequence(head);
+        repair.run();
+
+        seq.grammar.recount_and_inline_single_use(Some(0), &[]);
+        seq.grammar.enforce_digram_uniqueness(&[head]);
+        seq
+    }
+
+    /// Appends a symbol before the end of Rule 0 without enforcing any
+    /// constraint or counting it towards the length.
+    pub(crate) fn append_unlinked(&mut self, symbol: Symbol<T>) -> DefaultKey {
+        let key = self.grammar.symbols.insert(SymbolNode::new(symbol));
+        let tail = self.sequence_end;
+        let prev = self.grammar.symbols[tail].prev;
+
+        self.grammar.symbols[key].prev = prev;
+        self.grammar.symbols[key].next = Some(tail);
+        self.grammar.symbols[tail].prev = Some(key);
+        if let Some(prev) = prev {
+            self.grammar.symbols[prev].next = Some(key);
+        }
+        key
+    }
+}
+
+/// Working state of a Re-Pair run over one or more sequences.
+///
+/// Occurrence lists and the priority queue are maintained lazily: entries may
+/// point at pairs that no longer exist, and are validated when a pair is chosen.
+pub(crate) struct RePair<'a, T> {
+    grammar: &'a mut Grammar<T>,
+    /// Codes assigned to values
+    values: Interner<T>,
+    /// Code of every symbol in the sequences; rule body symbols have none
+    codes: SecondaryMap<DefaultKey, Code>,
+    /// Keys of the first symbol of each (possibly stale) pair occurrence
+    occurrences: HashMap<(Code, Code), Vec<DefaultKey>>,
+    /// Pairs by claimed occurrence count
+    queue: BinaryHeap<(usize, (Code, Code))>,
+    /// Minimum number of non-overlapping occurrences for a pair to be replaced
+    min_uses: usize,
+    /// Number of rules created so far
+    rules_created: usize,
+}
+
+impl<'a, T: Hash + Eq + Clone> RePair<'a, T> {
+    pub(crate) fn new(grammar: &'a mut Grammar<T>, min_uses: usize) -> Self {
+        Self {
+            grammar,
+            values: Interner::new(),
+            codes: SecondaryMap::new(),
+            occurrences: HashMap::default(),
+            queue: BinaryHeap::new(),
+            min_uses: min_uses.max(2),
+            rules_created: 0,
+        }
+    }
+
+    /// Adds the body of a rule or document to the sequences being compressed.
+    pub(crate) fn add_sequence(&mut self, head: DefaultKey) {
+        let keys: Vec<_> = self.grammar.body_keys(head).collect();
+        for &key in &keys {
+            let code = match &self.grammar.symbols[key].symbol {
+                Symbol::Value(value) => self.values.intern(value) as Code,
+                Symbol::RuleRef { rule_id } => RULE_CODE | Code::from(*rule_id),
+                _ => unreachable!("sequence bodies only contain values and rule references"),
+            };
+            self.codes.insert(key, code);
+        }
+        for pair in keys.windows(2) {
+            let pair_codes = (self.codes[pair[0]], self.codes[pair[1]]);
+            self.record(pair_codes, pair[0]);
+        }
+    }
+
+    /// Replaces pairs until none occurs often enough, returning the number of
+    /// rules created.
+    pub(crate) fn run(&mut self) -> usize {
+        while let Some((claimed, pair)) = self.queue.pop() {
+            let Some(keys) = self.occurrences.get_mut(&pair) else {
+                continue;
+            };
+            if keys.len() != claimed {
+                // A newer entry for this pair is queued.
+                continue;
+            }
+
+            let candidates = std::mem::take(keys);
+            let valid = self.non_overlapping(pair, candidates);
+            if valid.len() < self.min_uses {
+                self.occurrences.remove(&pair);
+                continue;
+            }
+            if valid.len() < claimed {
+                self.queue.push((valid.len(), pair));
+                self.occurrences.insert(pair, valid);
+                continue;
+            }
+
+            self.occurrences.remove(&pair);
+            self.replace_all(&valid);
+            self.rules_created += 1;
+        }
+        self.rules_created
+    }
+
+    /// Filters occurrences down to those still present, dropping overlaps.
+    fn non_overlapping(&self, pair: (Code, Code), keys: Vec<DefaultKey>) -> Vec<DefaultKey> {
+        let mut used = HashSet::default();
+        keys.into_iter()
+            .filter(|&first| {
+                let Some(second) = self.pair_at(first, pair) else {
+                    return false;
+                };
+                if used.contains(&first) || used.contains(&second) {
+                    return false;
+                }
+                used.insert(first);
+                used.insert(second);
+                true
+            })
+            .collect()
+    }
+
+    /// Returns the second key of the pair starting at `first` if it is `pair`.
+    fn pair_at(&self, first: DefaultKey, pair: (Code, Code)) -> Option<DefaultKey> {
+        if self.codes.get(first) != Some(&pair.0) {
+            return None;
+        }
+        let second = self.grammar.symbols[first].next?;
+        (self.codes.get(second) == Some(&pair.1)).then_some(second)
+    }
+
+    /// Creates a rule for the pair at `occurrences[0]` and replaces every
+    /// occurrence with a reference to it.
+    fn replace_all(&mut self, occurrences: &[DefaultKey]) {
+        let symbols = &mut self.grammar.symbols;
+        let first = occurrences[0];
+        let second = symbols[first].next.expect("pair should have a second");
+
+        let rule_id = self.grammar.id_gen.get();
+        let tail = symbols.insert(SymbolNode::new(Symbol::RuleTail));
+        let head = symbols.insert(SymbolNode::new(Symbol::RuleHead {
+            rule_id,
+            count: 0,
+            tail,
+        }));
+        let body_first = symbols.insert(SymbolNode::new(symbols[first].symbol.clone_symbol()));
+        let body_second = symbols.insert(SymbolNode::new(symbols[second].symbol.clone_symbol()));
+
+        symbols[head].next = Some(body_first);
+        symbols[body_first].prev = Some(head);
+        symbols[body_first].next = Some(body_second);
+        symbols[body_second].prev = Some(body_first);
+        symbols[body_second].next = Some(tail);
+        symbols[tail].prev = Some(body_second);
+        self.grammar.rule_index.insert(rule_id, head);
+
+        let code = RULE_CODE | Code::from(rule_id);
+        for &first in occurrences {
+            self.replace(first, rule_id, code);
+        }
+    }
+
+    /// Replaces the pair starting at `first` with a RuleRef and records the
+    /// pairs it forms with its neighbors.
+    fn replace(&mut self, first: DefaultKey, rule_id: u32, code: Code) {
+        let symbols = &mut self.grammar.symbols;
+        let second = symbols[first].next.expect("pair should have a second");
+        let prev = symbols[first].prev.expect("Rule 0 symbols have a prev");
+        let next = symbols[second].next.expect("Rule 0 symbols have a next");
+
+        let key = symbols.insert(SymbolNode::new(Symbol::RuleRef { rule_id }));
+        symbols[key].prev = Some(prev);
+        symbols[key].next = Some(next);
+        symbols[prev].next = Some(key);
+        symbols[next].prev = Some(key);
+
+        symbols.remove(first);
+        symbols.remove(second);
+        self.codes.remove(first);
+        self.codes.remove(second);
+        self.codes.insert(key, code);
+
+        if let Some(&prev_code) = self.codes.get(prev) {
+            self.record((prev_code, code), prev);
+        }
+        if let Some(&next_code) = self.codes.get(next) {
+            self.record((code, next_code), key);
+        }
+    }
+
+    fn record(&mut self, pair: (Code, Code), first: DefaultKey) {
+        let keys = self.occurrences.entry(pair).or_default();
+        keys.push(first);
+        if keys.len() >= self.min_uses {
+            self.queue.push((keys.len(), pair));
+        }
+    }
+}
+
+impl<T: Hash + Eq + Clone> Grammar<T> {
+    /// Recomputes every rule's reference count from the rule and document bodies
+    /// and inlines rules referenced only once, returning how many were inlined.
+    ///
+    /// `root_rule` is the rule holding the main sequence, if any; it is never
+    /// inlined.
+    pub fn recount_and_inline_single_use(
+        &mut self,
+        root_rule: Option<u32>,
+        doc_heads: &[DefaultKey],
+    ) -> usize {
+        let refs = self.rule_refs(doc_heads);
+
+        let mut single_use = Vec::new();
+        for (&rule_id, &head) in &self.rule_index {
+            if Some(rule_id) == root_rule {
+                continue;
+            }
+            let uses = refs.get(&rule_id).map_or(0, Vec::len);
+            if let Symbol::RuleHead { count, .. } = &mut self.symbols[head].symbol {
+                *count = uses as u32;
+            }
+            if uses == 1 {
+                single_use.push((rule_id, refs[&rule_id][0]));
+            }
+        }
+
+        // Inlining moves body symbols without changing their keys, so the
+        // recorded reference locations stay valid.
+        for &(rule_id, rule_ref) in &single_use {
+            self.inline_rule(rule_id, rule_ref);
+        }
+        single_use.len()
+    }
+
+    /// Collects the location of every RuleRef in the rule and document bodies,
+    /// by referenced rule.
+    pub fn rule_refs(&self, doc_heads: &[DefaultKey]) -> HashMap<u32, Vec<DefaultKey>> {
+        let mut refs: HashMap<u32, Vec<DefaultKey>> = HashMap::default();
+        for &head in self.rule_index.values().chain(doc_heads) {
+            for key in self.body_keys(head) {
+                if let Symbol::RuleRef { rule_id } = self.symbols[key].symbol {
+                    refs.entry(rule_id).or_default().push(key);
+                }
+            }
+        }
+        refs
+    }
+
+    /// Replaces a RuleRef with the body of its rule and deletes the rule.
+    pub fn inline_rule(&mut self, rule_id: u32, rule_ref: DefaultKey) {
+        let head = self
+            .rule_index
+            .remove(&rule_id)
+            .expect("inlined rule should exist");
+        let Symbol::RuleHead { tail, .. } = self.symbols[head].symbol else {
+            unreachable!("rule index should only point at RuleHeads");
+        };
+
+        let body_first = self.symbols[head].next.expect("RuleHead should have next");
+        let body_last = self.symbols[tail].prev.expect("RuleTail should have prev");
+        let before = self.symbols[rule_ref].prev;
+        let after = self.symbols[rule_ref].next;
+
+        self.symbols[body_first].prev = before;
+        self.symbols[body_last].next = after;
+        if let Some(before) = before {
+            self.symbols[before].next = Some(body_first);
+        }
+        if let Some(after) = after {
+            self.symbols[after].prev = Some(body_last);
+        }
+
+        self.symbols.remove(head);
+        self.symbols.remove(tail);
+        self.symbols.remove(rule_ref);
+        let freed = self.id_gen.free(rule_id);
+        debug_assert!(freed, "rule {rule_id} should have an allocated ID");
+    }
+
+    /// Re-establishes digram uniqueness over the whole grammar.
+    ///
+    /// Each rule body is detached and re-appended one symbol at a time, exactly
+    /// as [`Sequitur::push`] appends to Rule 0, so [`link_made`](Self::link_made)
+    /// sees every digram once. The rules reachable from `sequences` are replayed
+    /// children first and the top-level sequences themselves last, so any rule
+    /// that gets inlined has already been replayed.
+    pub(crate) fn enforce_digram_uniqueness(&mut self, sequences: &[DefaultKey]) {
+        self.digram_index.clear();
+
+        let rules: Vec<DefaultKey> = self
+            .rules_children_first(sequences)
+            .into_iter()
+            .map(|rule_id| self.rule_index[&rule_id])
+            .collect();
+
+        for head in rules.into_iter().chain(sequences.iter().copied()) {
+            // Rules inlined while replaying their parents are gone.
+            if !self.symbols.contains_key(head) {
+                continue;
+            }
+            let suffix = self.detach_suffix(head);
+            self.replay_suffix(suffix);
+        }
+    }
+
+    /// Replays the body of `head` from scratch, dropping its digrams from the
+    /// index first so none of them is matched against itself.
+    pub(crate) fn replay_sequence(&mut self, head: DefaultKey) {
+        let keys: Vec<_> = self.body_keys(head).collect();
+        let own: HashSet<DefaultKey> = keys.iter().copied().collect();
+        for pair in keys.windows(2) {
+            let digram = (
+                SymbolHash::from_symbol(&self.symbols[pair[0]].symbol),
+                SymbolHash::from_symbol(&self.symbols[pair[1]].symbol),
+            );
+            if self
+                .digram_index
+                .get(&digram)
+                .is_some_and(|key| own.contains(key))
+            {
+                self.digram_index.remove(&digram);
+            }
+        }
+        let suffix = self.detach_suffix(head);
+        self.replay_suffix(suffix);
+    }
+
+    /// Moves the symbols after `start` out of its sequence and ends the
+    /// sequence in a placeholder, so they can be replayed with
+    /// [`replay_suffix`](Self::replay_suffix).
+    ///
+    /// While the sequence is partial it must not be mistaken for a complete
+    /// rule, hence the placeholder rather than its tail. The detached symbols
+    /// stay linked behind a head of their own, as inlining a rule while
+    /// replaying can replace them.
+    pub(crate) fn detach_suffix(&mut self, start: DefaultKey) -> Suffix {
+        let first = self.symbols[start]
+            .next
+            .expect("sequences should end in a tail");
+        let tail = iter::successors(Some(first), |&key| self.symbols[key].next)
+            .find(|&key| self.is_sequence_end(&self.symbols[key].symbol))
+            .expect("sequences should end in a tail");
+
+        let pending = self
+            .symbols
+            .insert(SymbolNode::new(Symbol::DocHead { tail }));
+        self.symbols[pending].next = Some(first);
+        self.symbols[first].prev = Some(pending);
+
+        let end = self.symbols.insert(SymbolNode::new(Symbol::DocTail));
+        self.symbols[start].next = Some(end);
+        self.symbols[end].prev = Some(start);
+        Suffix { pending, end, tail }
+    }
+
+    /// Re-appends detached symbols one at a time, exactly as
+    /// [`Sequitur::push`] appends to Rule 0, so [`link_made`](Self::link_made)
+    /// sees every digram they form.
+    pub(crate) fn replay_suffix(&mut self, suffix: Suffix) {
+        let Suffix { pending, end, tail } = suffix;
+        loop {
+            let key = self.symbols[pending]
+                .next
+                .expect("detached symbols should end in the tail");
+            if key == tail {
+                break;
+            }
+            let after = self.symbols[key]
+                .next
+                .expect("detached symbols should end in the tail");
+            self.symbols[pending].next = Some(after);
+            self.symbols[after].prev = Some(pending);
+
+            let prev = self.symbols[end]
+                .prev
+                .expect("placeholder should have prev");
+            self.symbols[key].prev = Some(prev);
+            self.symbols[key].next = Some(end);
+            self.symbols[prev].next = Some(key);
+            self.symbols[end].prev = Some(key);
+            if !self.is_sequence_start(&self.symbols[prev].symbol) {
+                self.link_made(prev);
+            }
+        }
+
+        let last = self.symbols[end]
+            .prev
+            .expect("placeholder should have prev");
+        self.symbols[last].next = Some(tail);
+        self.symbols[tail].prev = Some(last);
+        self.symbols.remove(end);
+        self.symbols.remove(pending);
+    }
+}
+
+/// The symbols of a sequence detached by
+/// [`detach_suffix`](Grammar::detach_suffix), waiting to be replayed.
+pub(crate) struct Suffix {
+    /// Placeholder heading the detached symbols
+    pending: DefaultKey,
+    /// Placeholder ending the sequence meanwhile
+    end: DefaultKey,
+    tail: DefaultKey,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_rule_utility<T: Hash + Eq + Clone>(seq: &Sequitur<T>) {
+        for (&rule_id, &head) in &seq.grammar.rule_index {
+            if rule_id == 0 {
+                continue;
+            }
+            let Symbol::RuleHead { count, .. } = seq.grammar.symbols[head].symbol else {
+                unreachable!();
+            };
+            assert!(count >= 2, "rule {rule_id} used {count} times");
+        }
+    }
+
+    #[test]
+    fn test_repair_roundtrip() {
+        let input = "abracadabra abracadabra abracadabra";
+        let seq = Sequitur::repair(input.chars());
+
+        assert_eq!(seq.iter().collect::<String>(), input);
+        assert_eq!(seq.len(), input.len());
+        assert!(seq.stats().num_rules > 1);
+        assert_rule_utility(&seq);
+    }
+
+    #[test]
+    fn test_repair_runs() {
+        // Overlapping pairs in runs must not be replaced twice.
+        for len in [0, 1, 2, 3, 4, 5, 7, 8, 9, 16, 33] {
+            let input = vec![b'a'; len];
+            let seq = Sequitur::repair(input.iter().copied());
+            assert_eq!(seq.iter().copied().collect::<Vec<_>>(), input);
+            assert_rule_utility(&seq);
+        }
+    }
+
+    #[test]
+    fn test_repair_rule_zero_pairs_unique() {
+        let input: Vec<u8> = (0..2000u32).map(|i| ((i * 7) % 11) as u8).collect();
+        let seq = Sequitur::repair(input.iter().copied());
+        assert!(seq.iter().copied().eq(input.iter().copied()));
+
+        let head = seq.grammar.rule_index[&0];
+        let keys: Vec<_> = seq.grammar.body_keys(head).collect();
+        let mut seen = HashSet::default();
+        let mut prev_pair = None;
+        for pair in keys.windows(2) {
+            let symbols = (
+                format!("{:?}", seq.grammar.symbols[pair[0]].symbol),
+                format!("{:?}", seq.grammar.symbols[pair[1]].symbol),
+            );
+            // Overlapping repeats (as in "xxx") are allowed.
+            if prev_pair.as_ref() != Some(&symbols) {
+                assert!(seen.insert(symbols.clone()), "repeated pair {symbols:?}");
+            }
+            prev_pair = Some(symbols);
+        }
+    }
+
+    #[test]
+    fn test_repair_smaller_than_sequitur() {
+        let input = "the quick brown fox jumps over the lazy dog ".repeat(50);
+        let online = Sequitur::compress(input.chars(), Algorithm::Sequitur);
+        let offline = Sequitur::compress(input.chars(), Algorithm::RePair);
+
+        assert!(offline.iter().copied().eq(input.chars()));
+        assert!(offline.stats().grammar_symbols <= online.stats().grammar_symbols);
+    }
+
+    #[test]
+    fn test_push_after_repair() {
+        let mut seq = Sequitur::repair("abcabcabc".chars());
+        seq.extend("abcabcxyzxyz".chars());
+
+        assert_eq!(seq.iter().collect::<String>(), "abcabcabcabcabcxyzxyz");
+        assert_eq!(seq.len(), 21);
+    }
+}
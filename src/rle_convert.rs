@@ -0,0 +1,515 @@
+use crate::documents::SequiturDocuments;
+use crate::grammar::Grammar;
+use crate::rle_documents::SequiturDocumentsRle;
+use crate::rle_grammar::RleGrammar;
+use crate::rle_sequitur::SequiturRle;
+use crate::rle_symbol::RleSymbolNode;
+use crate::sequitur::Sequitur;
+use crate::symbol::Symbol;
+use ahash::{AHashMap as HashMap, AHashSet as HashSet};
+use slotmap::DefaultKey;
+use std::hash::Hash;
+
+/// Converts a grammar to run-length encoding without expanding it.
+///
+/// Adjacent identical symbols in each rule body are collapsed into one node
+/// holding their run, rules whose body collapses to a single node are inlined
+/// as that node, and the RLE constraints are then re-established.
+///
+/// # Example
+///
+/// ```
+/// use sequitur_rs::{Sequitur, SequiturRle};
+///
+/// let mut seq = Sequitur::new();
+/// seq.extend("ab".repeat(64).chars());
+///
+/// let rle = SequiturRle::from(seq);
+/// assert_eq!(rle.iter().collect::<String>(), "ab".repeat(64));
+/// assert_eq!(rle.stats().num_rules, 2);
+/// ```
+impl<T: Hash + Eq + Clone> From<Sequitur<T>> for SequiturRle<T> {
+    fn from(seq: Sequitur<T>) -> Self {
+        let mut rle = Self::new();
+        let head = rle.grammar.rule_index[&0];
+        let replay = rle.grammar.import_collapsed(
+            &seq.grammar,
+            &[(seq.grammar.rule_index[&0], rle.sequence_end)],
+        );
+        rle.length = seq.length;
+
+        rle.grammar.recount(&[]);
+        rle.grammar
+            .enforce_digram_uniqueness(replay.into_iter().chain([head]));
+        rle
+    }
+}
+
+/// Converts a run-length encoded grammar to a plain one without expanding it.
+///
+/// A run of `k` copies of a symbol becomes O(log k) rules that double it, the
+/// rules are shared by every run of the same symbol, and the Sequitur
+/// constraints are then re-established.
+///
+/// # Example
+///
+/// ```
+/// use sequitur_rs::{Sequitur, SequiturRle};
+///
+/// let mut rle = SequiturRle::new();
+/// rle.push_run('x', 1 << 40);
+///
+/// let seq = Sequitur::from(rle);
+/// assert_eq!(seq.len(), 1 << 40);
+/// assert!(seq.stats().num_rules <= 41);
+/// ```
+impl<T: Hash + Eq + Clone> From<SequiturRle<T>> for Sequitur<T> {
+    fn from(rle: SequiturRle<T>) -> Self {
+        let mut seq = Self::new();
+        let head = seq.grammar.rule_index[&0];
+        seq.grammar.import_runs(
+            &rle.grammar,
+            &[(rle.grammar.rule_index[&0], seq.sequence_end)],
+        );
+        seq.length = rle.length;
+
+        seq.grammar.recount_and_inline_single_use(Some(0), &[]);
+        seq.grammar.enforce_digram_uniqueness(&[head]);
+        seq
+    }
+}
+
+/// Converts every document to run-length encoding without expanding it, like
+/// the conversion of a single [`Sequitur`].
+impl<T: Hash + Eq + Clone, DocId: Hash + Eq + Clone> From<SequiturDocuments<T, DocId>>
+    for SequiturDocumentsRle<T, DocId>
+{
+    fn from(docs: SequiturDocuments<T, DocId>) -> Self {
+        let mut rle = Self::new();
+        let mut sequences = Vec::with_capacity(docs.documents.len());
+        for (doc_id, info) in docs.documents {
+            rle.create_document(doc_id.clone());
+            let rle_info = rle.documents.get_mut(&doc_id).unwrap();
+            rle_info.length = info.length;
+            sequences.push((info.head, rle_info.tail));
+        }
+        let replay = rle.grammar.import_collapsed(&docs.grammar, &sequences);
+
+        let heads: Vec<_> = rle.documents.values().map(|info| info.head).collect();
+        rle.grammar.recount(&heads);
+        rle.grammar
+            .enforce_digram_uniqueness(replay.into_iter().chain(heads));
+        rle
+    }
+}
+
+/// Converts every document to a plain grammar without expanding it, like the
+/// conversion of a single [`SequiturRle`].
+impl<T: Hash + Eq + Clone, DocId: Hash + Eq + Clone> From<SequiturDocumentsRle<T, DocId>>
+    for SequiturDocuments<T, DocId>
+{
+    fn from(rle: SequiturDocumentsRle<T, DocId>) -> Self {
+        let mut docs = Self::new();
+        let mut sequences = Vec::with_capacity(rle.documents.len());
+        for (doc_id, info) in rle.documents {
+            docs.create_document(doc_id.clone());
+            let plain_info = docs.documents.get_mut(&doc_id).unwrap();
+            plain_info.length = info.length;
+            sequences.push((info.head, plain_info.tail));
+        }
+        docs.grammar.import_runs(&rle.grammar, &sequences);
+
+        let heads: Vec<_> = docs.documents.values().map(|info| info.head).collect();
+        docs.grammar.recount_and_inline_single_use(None, &heads);
+        docs.grammar.enforce_digram_uniqueness(&heads);
+        docs
+    }
+}
+
+/// What a rule of a plain grammar becomes in an RLE grammar.
+enum Collapsed<T> {
+    /// A rule whose body collapsed to several nodes, with its new ID
+    Rule(u32),
+    /// A rule whose body collapsed to a single node, inlined where it is used
+    Node(Symbol<T>, u64),
+}
+
+/// Collapses a plain rule or sequence body into `(symbol, run)` nodes.
+fn collapse_body<T: Hash + Eq + Clone>(
+    grammar: &Grammar<T>,
+    head: DefaultKey,
+    collapsed: &HashMap<u32, Collapsed<T>>,
+) -> Vec<(Symbol<T>, u64)> {
+    let mut nodes: Vec<(Symbol<T>, u64)> = Vec::new();
+    for key in grammar.body_keys(head) {
+        let (symbol, run) = match &grammar.symbols[key].symbol {
+            Symbol::Value(value) => (Symbol::Value(value.clone()), 1),
+            Symbol::RuleRef { rule_id } => match &collapsed[rule_id] {
+                &Collapsed::Rule(rule_id) => (Symbol::RuleRef { rule_id }, 1),
+                Collapsed::Node(symbol, run) => (symbol.clone_symbol(), *run),
+            },
+            _ => unreachable!("rule bodies hold only values and references"),
+        };
+
+        // Runs that would overflow when combined stay separate nodes.
+        if let Some((last, last_run)) = nodes.last_mut() {
+            if last.equals(&symbol) {
+                if let Some(merged) = last_run.checked_add(run) {
+                    *last_run = merged;
+                    continue;
+                }
+            }
+        }
+        nodes.push((symbol, run));
+    }
+    nodes
+}
+
+impl<T: Hash + Eq + Clone> RleGrammar<T> {
+    /// Copies the rules of a plain grammar reachable from the given sequences,
+    /// and the sequence bodies before the given tails, collapsing repeated
+    /// symbols into runs, without enforcing any constraint or setting
+    /// reference counts.
+    ///
+    /// `sequences` pairs the head of each plain sequence with the tail of the
+    /// RLE sequence receiving it. Returns the heads of the new rules, children
+    /// first.
+    fn import_collapsed(
+        &mut self,
+        grammar: &Grammar<T>,
+        sequences: &[(DefaultKey, DefaultKey)],
+    ) -> Vec<DefaultKey> {
+        let plain_heads: Vec<_> = sequences.iter().map(|&(head, _)| head).collect();
+        let mut collapsed: HashMap<u32, Collapsed<T>> = HashMap::default();
+        let mut heads = Vec::new();
+
+        // Children first, so a rule is collapsed before any body using it.
+        for rule_id in grammar.rules_children_first(&plain_heads) {
+            let nodes = collapse_body(grammar, grammar.rule_index[&rule_id], &collapsed);
+            let entry = match <[_; 1]>::try_from(nodes) {
+                Ok([(symbol, run)]) => Collapsed::Node(symbol, run),
+                Err(nodes) => {
+                    let (new_id, head) = self.append_rule(nodes);
+                    heads.push(head);
+                    Collapsed::Rule(new_id)
+                }
+            };
+            collapsed.insert(rule_id, entry);
+        }
+        for &(plain_head, tail) in sequences {
+            for (symbol, run) in collapse_body(grammar, plain_head, &collapsed) {
+                self.insert_unlinked_before(tail, symbol, run);
+            }
+        }
+        heads
+    }
+
+    /// Creates a rule with the given body and no uses, without enforcing any
+    /// constraint, and returns its ID and RuleHead.
+    fn append_rule(&mut self, body: Vec<(Symbol<T>, u64)>) -> (u32, DefaultKey) {
+        let rule_id = self.id_gen.get();
+        let tail = self.symbols.insert(RleSymbolNode::new(Symbol::RuleTail));
+        let head = self.symbols.insert(RleSymbolNode::new(Symbol::RuleHead {
+            rule_id,
+            count: 0,
+            tail,
+        }));
+        self.symbols[head].next = Some(tail);
+        self.symbols[tail].prev = Some(head);
+        for (symbol, run) in body {
+            self.insert_unlinked_before(tail, symbol, run);
+        }
+
+        self.rule_index.insert(rule_id, head);
+        (rule_id, head)
+    }
+
+    /// Inserts a node before `next` without enforcing any constraint.
+    fn insert_unlinked_before(&mut self, next: DefaultKey, symbol: Symbol<T>, run: u64) {
+        let key = self.symbols.insert(RleSymbolNode::with_run(symbol, run));
+        let prev = self.symbols[next].prev;
+
+        self.symbols[key].prev = prev;
+        self.symbols[key].next = Some(next);
+        self.symbols[next].prev = Some(key);
+        if let Some(prev) = prev {
+            self.symbols[prev].next = Some(key);
+        }
+    }
+
+    /// Recomputes every rule's reference count from the rule and document
+    /// bodies, counting a reference with run `k` as `k` uses.
+    ///
+    /// Counts beyond `u32::MAX` saturate; they only need to tell single uses
+    /// apart.
+    fn recount(&mut self, doc_heads: &[DefaultKey]) {
+        let mut uses: HashMap<u32, u64> = HashMap::default();
+        for &head in self.rule_index.values().chain(doc_heads) {
+            for key in self.body_keys(head) {
+                let node = &self.symbols[key];
+                if let Symbol::RuleRef { rule_id } = node.symbol {
+                    let total = uses.entry(rule_id).or_default();
+                    *total = total.saturating_add(node.run);
+                }
+            }
+        }
+
+        for (rule_id, &head) in &self.rule_index {
+            let used = uses.get(rule_id).copied().unwrap_or(0);
+            if let Symbol::RuleHead { count, .. } = &mut self.symbols[head].symbol {
+                *count = u32::try_from(used).unwrap_or(u32::MAX);
+            }
+        }
+    }
+}
+
+/// A symbol repeated by a run, as a hashable key for its doubling rules.
+#[derive(Clone, PartialEq, Eq, Hash)]
+enum Unit<T> {
+    Value(T),
+    Rule(u32),
+}
+
+impl<T: Clone> Unit<T> {
+    fn symbol(&self) -> Symbol<T> {
+        match self {
+            Unit::Value(value) => Symbol::Value(value.clone()),
+            &Unit::Rule(rule_id) => Symbol::RuleRef { rule_id },
+        }
+    }
+}
+
+impl<T: Hash + Eq + Clone> Grammar<T> {
+    /// Copies every rule of an RLE grammar, and the sequence bodies before the
+    /// given tails, expanding runs into rules, without enforcing any
+    /// constraint or setting reference counts.
+    ///
+    /// `sequences` pairs the head of each RLE sequence with the tail of the
+    /// plain sequence receiving it.
+    fn import_runs(&mut self, rle: &RleGrammar<T>, sequences: &[(DefaultKey, DefaultKey)]) {
+        let roots: HashSet<DefaultKey> = sequences.iter().map(|&(head, _)| head).collect();
+
+        // Every rule gets its ID before any body is copied, so bodies can be
+        // copied in any order.
+        let mut ids: HashMap<u32, u32> = HashMap::default();
+        let mut bodies = Vec::with_capacity(rle.rule_index.len() + sequences.len());
+        for (&rule_id, &rle_head) in &rle.rule_index {
+            if roots.contains(&rle_head) {
+                continue;
+            }
+            let (new_id, head) = self.append_rule(Vec::new());
+            let Symbol::RuleHead { tail, .. } = self.symbols[head].symbol else {
+                unreachable!("append_rule should return a RuleHead");
+            };
+            ids.insert(rule_id, new_id);
+            bodies.push((rle_head, tail));
+        }
+        bodies.extend_from_slice(sequences);
+
+        let mut doublings: HashMap<Unit<T>, Vec<u32>> = HashMap::default();
+        for (rle_head, tail) in bodies {
+            for key in rle.body_keys(rle_head) {
+                let node = &rle.symbols[key];
+                let unit = match &node.symbol {
+                    Symbol::Value(value) => Unit::Value(value.clone()),
+                    Symbol::RuleRef { rule_id } => Unit::Rule(ids[rule_id]),
+                    _ => unreachable!("rule bodies hold only values and references"),
+                };
+                let doublings = doublings.entry(unit.clone()).or_default();
+                self.insert_run_before(tail, &unit, node.run, doublings);
+            }
+        }
+    }
+
+    /// Inserts `run` copies of `unit` before `next`, one symbol per set bit of
+    /// `run`.
+    ///
+    /// `doublings[j]` is a rule expanding to `2^(j + 1)` copies of `unit`;
+    /// missing ones are created.
+    fn insert_run_before(
+        &mut self,
+        next: DefaultKey,
+        unit: &Unit<T>,
+        run: u64,
+        doublings: &mut Vec<u32>,
+    ) {
+        let bits = (u64::BITS - run.leading_zeros()) as usize;
+        while doublings.len() + 1 < bits {
+            let half = match doublings.last() {
+                Some(&rule_id) => Symbol::RuleRef { rule_id },
+                None => unit.symbol(),
+            };
+            let (rule_id, _) = self.append_rule(vec![half.clone_symbol(), half]);
+            doublings.push(rule_id);
+        }
+
+        for bit in (0..bits).rev().filter(|&bit| run >> bit & 1 == 1) {
+            let symbol = match bit {
+                0 => unit.symbol(),
+                _ => Symbol::RuleRef {
+                    rule_id: doublings[bit - 1],
+                },
+            };
+            self.insert_unlinked_before(next, symbol);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INPUTS: &[&str] = &[
+        "",
+        "a",
+        "abcabcabcabc",
+        "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa",
+        "abababababababababababababababababababababab",
+        "aaabbbcccaaabbbcccaaabbbdddaaabbb",
+        "xxyyxxyyxyxyxxxyyyxxxyyyzzzxxyyxxyy",
+        "the quick brown fox jumps over the lazy dog, the quick brown fox",
+    ];
+
+    fn assert_plain_valid(seq: &Sequitur<char>) {
+        let head = seq.grammar.rule_index[&0];
+        seq.grammar.validate(&[(head, seq.len())], Some(0)).unwrap();
+        for (&rule_id, &head) in &seq.grammar.rule_index {
+            let Symbol::RuleHead { count, .. } = seq.grammar.symbols[head].symbol else {
+                unreachable!();
+            };
+            assert!(
+                rule_id == 0 || count >= 2,
+                "rule {rule_id} used {count} times"
+            );
+        }
+    }
+
+    fn assert_rle_valid<T: Hash + Eq + Clone>(grammar: &RleGrammar<T>) {
+        for &head in grammar.rule_index.values() {
+            let nodes: Vec<_> = grammar.body_keys(head).collect();
+            for pair in nodes.windows(2) {
+                assert!(
+                    !grammar.symbols[pair[0]]
+                        .symbol
+                        .equals(&grammar.symbols[pair[1]].symbol),
+                    "adjacent identical symbols"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_plain_to_rle() {
+        for input in INPUTS {
+            let mut seq = Sequitur::new();
+            seq.extend(input.chars());
+
+            let rle = SequiturRle::from(seq);
+            assert_eq!(rle.iter().collect::<String>(), *input);
+            assert_eq!(rle.len(), input.len());
+            assert_rle_valid(&rle.grammar);
+            assert!(rle.try_iter().is_ok());
+        }
+    }
+
+    #[test]
+    fn test_rle_to_plain() {
+        for input in INPUTS {
+            let mut rle = SequiturRle::new();
+            rle.extend(input.chars());
+
+            let seq = Sequitur::from(rle);
+            assert_eq!(seq.iter().collect::<String>(), *input);
+            assert_eq!(seq.len(), input.len());
+            assert_plain_valid(&seq);
+        }
+    }
+
+    #[test]
+    fn test_runs_collapse() {
+        // Plain Sequitur needs O(log k) rules for a run; RLE needs none.
+        let mut seq = Sequitur::new();
+        seq.extend(std::iter::repeat_n('a', 1000));
+        assert!(seq.stats().num_rules > 5);
+
+        let rle = SequiturRle::from(seq);
+        assert_eq!(rle.stats().grammar_nodes, 1);
+        assert_eq!(rle.stats().num_rules, 1);
+        assert_eq!(rle.iter_runs().collect::<Vec<_>>(), [(&'a', 1000)]);
+    }
+
+    #[test]
+    fn test_long_runs_are_not_expanded() {
+        let mut rle = SequiturRle::new();
+        rle.push_run('x', u64::from(u32::MAX) * 3);
+        rle.push_run('y', 1);
+        rle.push_run('x', 1 << 33);
+
+        let seq = Sequitur::from(rle);
+        assert_eq!(seq.len() as u64, u64::from(u32::MAX) * 3 + 1 + (1 << 33));
+        // The doubling rules are shared by both runs of 'x'.
+        assert!(seq.stats().num_rules <= 40);
+        assert_plain_valid(&seq);
+
+        let mut iter = seq.iter();
+        assert_eq!(iter.nth(u32::MAX as usize * 3 - 1), Some(&'x'));
+        assert_eq!(iter.next(), Some(&'y'));
+        assert_eq!(iter.next(), Some(&'x'));
+    }
+
+    #[test]
+    fn test_roundtrip_keeps_compressing() {
+        let input = "abcab".repeat(20);
+        let mut seq = Sequitur::new();
+        seq.extend(input.chars());
+
+        let mut rle = SequiturRle::from(seq);
+        assert!(rle.try_iter().is_ok());
+        rle.extend("abcab".chars());
+        assert!(rle.try_iter().is_ok());
+        let mut seq = Sequitur::from(rle);
+        seq.extend("abcab".chars());
+
+        assert_eq!(seq.iter().collect::<String>(), "abcab".repeat(22));
+        assert_plain_valid(&seq);
+        assert!(seq.try_iter().is_ok());
+    }
+
+    #[test]
+    fn test_documents() {
+        let mut docs = SequiturDocuments::new();
+        docs.extend_document(1, "aaaabbbbaaaabbbb".chars());
+        docs.extend_document(2, "aaaabbbbcccc".chars());
+        docs.extend_document(3, "xyxyxyxyxy".chars());
+
+        let rle = SequiturDocumentsRle::from(docs);
+        assert_eq!(rle.num_documents(), 3);
+        assert_rle_valid(&rle.grammar);
+        for (doc, text) in [
+            (1, "aaaabbbbaaaabbbb"),
+            (2, "aaaabbbbcccc"),
+            (3, "xyxyxyxyxy"),
+        ] {
+            assert_eq!(rle.iter_document(&doc).unwrap().collect::<String>(), text);
+            assert_eq!(rle.document_len(&doc), Some(text.len()));
+            assert!(rle.try_iter_document(&doc).is_ok());
+        }
+
+        let docs = SequiturDocuments::from(rle);
+        assert_eq!(docs.num_documents(), 3);
+        for (doc, text) in [
+            (1, "aaaabbbbaaaabbbb"),
+            (2, "aaaabbbbcccc"),
+            (3, "xyxyxyxyxy"),
+        ] {
+            assert_eq!(docs.iter_document(&doc).unwrap().collect::<String>(), text);
+            assert_eq!(docs.document_len(&doc), Some(text.len()));
+        }
+        let heads: Vec<_> = docs
+            .documents
+            .values()
+            .map(|info| (info.head, info.length))
+            .collect();
+        docs.grammar.validate(&heads, None).unwrap();
+    }
+}
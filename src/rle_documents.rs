@@ -1,3 +1,4 @@
+use crate::error::SequiturError;
 use crate::rle_grammar::RleGrammar;
 use crate::rle_symbol::RleSymbolNode;
 use crate::symbol::Symbol;
@@ -121,6 +122,32 @@ impl<T: Hash + Eq + Clone, DocId: Hash + Eq + Clone> SequiturDocumentsRle<T, Doc
         }
     }
 
+    /// Adds a value to the specified document, or returns an error instead of
+    /// running out of rule IDs or overflowing the document's length.
+    ///
+    /// The collection is unchanged when an error is returned.
+    pub fn try_push_to_document(&mut self, doc_id: DocId, value: T) -> Result<(), SequiturError> {
+        let length = self.documents.get(&doc_id).map_or(0, |info| info.length);
+        self.grammar.check_capacity(length, 1)?;
+        self.push_to_document(doc_id, value);
+        Ok(())
+    }
+
+    /// Extends the document with multiple values, stopping at the first value
+    /// that [`try_push_to_document`](Self::try_push_to_document) rejects.
+    ///
+    /// Values before the rejected one remain in the document.
+    pub fn try_extend_document<I: IntoIterator<Item = T>>(
+        &mut self,
+        doc_id: DocId,
+        iter: I,
+    ) -> Result<(), SequiturError> {
+        for value in iter {
+            self.try_push_to_document(doc_id.clone(), value)?;
+        }
+        Ok(())
+    }
+
     /// Returns the number of values in a document (counting run lengths).
     pub fn document_len(&self, doc_id: &DocId) -> Option<usize> {
         self.documents.get(doc_id).map(|info| info.length)
@@ -211,7 +238,7 @@ impl<T: Hash + Eq + Clone, DocId: Hash + Eq + Clone> SequiturDocumentsRle<T, Doc
     }
 
     /// Creates a new empty document.
-    fn create_document(&mut self, doc_id: DocId) {
+    pub(crate) fn create_document(&mut self, doc_id: DocId) {
         let tail_key = self
             .grammar
             .symbols
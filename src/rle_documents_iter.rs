@@ -1,8 +1,11 @@
+use crate::error::SequiturError;
 use crate::rle_documents::SequiturDocumentsRle;
 use crate::rle_grammar::RleGrammar;
 use crate::symbol::Symbol;
+use ahash::AHashMap as HashMap;
 use slotmap::DefaultKey;
 use std::hash::Hash;
+use std::iter::FusedIterator;
 
 /// Iterator over a single document in SequiturDocumentsRle.
 ///
@@ -11,9 +14,19 @@ pub struct RleDocumentIter<'a, T, DocId> {
     grammar: &'a RleGrammar<T>,
     current: Option<DefaultKey>,
     /// Remaining count for the current symbol's run
-    remaining_run: u32,
+    remaining_run: u64,
     /// Stack for tracking rule expansion
     stack: Vec<StackEntry>,
+    /// Next value from the back
+    back: Option<DefaultKey>,
+    /// Remaining count for the back symbol's run
+    back_run: u64,
+    /// Stack for tracking rule expansion from the back
+    back_stack: Vec<StackEntry>,
+    /// Values not yet yielded from either end
+    remaining: usize,
+    /// Expanded rule lengths, filled in as [`nth`](Iterator::nth) needs them
+    lengths: HashMap<u32, usize>,
     _doc_id: std::marker::PhantomData<DocId>,
 }
 
@@ -21,7 +34,7 @@ pub struct RleDocumentIter<'a, T, DocId> {
 struct StackEntry {
     key: DefaultKey,
     /// Remaining run count when we descended into a rule
-    remaining_run: u32,
+    remaining_run: u64,
 }
 
 impl<'a, T: Hash + Eq + Clone, DocId: Hash + Eq + Clone> RleDocumentIter<'a, T, DocId> {
@@ -41,10 +54,19 @@ impl<'a, T: Hash + Eq + Clone, DocId: Hash + Eq + Clone> RleDocumentIter<'a, T,
             current: None,
             remaining_run: 0,
             stack: Vec::new(),
+            back: None,
+            back_run: 0,
+            back_stack: Vec::new(),
+            remaining: doc_info.length,
+            lengths: HashMap::default(),
             _doc_id: std::marker::PhantomData,
         };
 
         iter.resolve_to_value(start);
+        let end = sequitur.grammar.symbols[doc_info.tail]
+            .prev
+            .expect("DocTail should have prev");
+        iter.resolve_to_value_back(end);
         Some(iter)
     }
 
@@ -150,6 +172,231 @@ impl<'a, T: Hash + Eq + Clone, DocId: Hash + Eq + Clone> RleDocumentIter<'a, T,
             self.remaining_run = 0;
         }
     }
+
+    /// Returns the next maximal run of equal values and consumes it.
+    ///
+    /// Consecutive nodes holding the same value are merged, so a run that
+    /// spans the end of one rule and the start of another is reported once.
+    fn next_run(&mut self) -> Option<(&'a T, u64)> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let grammar = self.grammar;
+        let Symbol::Value(value) = &grammar.symbols[self.current?].symbol else {
+            unreachable!("current should always be a Value symbol");
+        };
+
+        let mut run = 0u64;
+        while self.remaining > 0 {
+            let Some(key) = self.current else { break };
+            match &grammar.symbols[key].symbol {
+                Symbol::Value(next) if next == value => {}
+                _ => break,
+            }
+            // Don't consume values the back cursor has already yielded
+            let take = self.remaining_run.min(self.remaining as u64);
+            let Some(total) = run.checked_add(take) else {
+                break;
+            };
+            run = total;
+            self.remaining -= take as usize;
+            self.remaining_run = 1;
+            self.advance();
+        }
+        Some((value, run))
+    }
+
+    /// Resolves backward through the grammar to find the previous Value symbol.
+    ///
+    /// Mirrors [`resolve_to_value`](Self::resolve_to_value), entering rules at
+    /// their last symbol and re-entering them once per remaining run.
+    fn resolve_to_value_back(&mut self, mut key: DefaultKey) {
+        loop {
+            match &self.grammar.symbols[key].symbol {
+                Symbol::Value(_) => {
+                    self.back = Some(key);
+                    self.back_run = self.grammar.symbols[key].run;
+                    return;
+                }
+
+                Symbol::RuleRef { rule_id } => {
+                    let run = self.grammar.symbols[key].run;
+                    self.back_stack.push(StackEntry {
+                        key,
+                        remaining_run: run,
+                    });
+                    key = self.rule_last(*rule_id);
+                }
+
+                Symbol::RuleTail | Symbol::DocTail => {
+                    key = self.grammar.symbols[key]
+                        .prev
+                        .expect("Tail should have prev");
+                }
+
+                Symbol::RuleHead { .. } => {
+                    if let Some(entry) = self.back_stack.pop() {
+                        let new_remaining = entry.remaining_run - 1;
+                        if new_remaining > 0 {
+                            self.back_stack.push(StackEntry {
+                                key: entry.key,
+                                remaining_run: new_remaining,
+                            });
+
+                            if let Symbol::RuleRef { rule_id } =
+                                self.grammar.symbols[entry.key].symbol
+                            {
+                                key = self.rule_last(rule_id);
+                                continue;
+                            }
+                        }
+
+                        if let Some(prev) = self.grammar.symbols[entry.key].prev {
+                            key = prev;
+                            continue;
+                        }
+                    }
+
+                    self.back = None;
+                    self.back_run = 0;
+                    return;
+                }
+
+                Symbol::DocHead { .. } => {
+                    // Start of document - but check if we're inside a rule
+                    if let Some(entry) = self.back_stack.pop() {
+                        if let Some(prev) = self.grammar.symbols[entry.key].prev {
+                            key = prev;
+                            continue;
+                        }
+                    }
+                    self.back = None;
+                    self.back_run = 0;
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Moves the front cursor past `n` values, stepping over whole runs and
+    /// rule expansions where possible.
+    ///
+    /// At least `n` values must remain after the current one.
+    fn skip_forward(&mut self, mut n: usize) {
+        let run = self.remaining_run as usize;
+        if n < run {
+            self.remaining_run -= n as u64;
+            return;
+        }
+        n -= run;
+
+        let current = self.current.expect("a value should remain");
+        let mut key = self.grammar.symbols[current]
+            .next
+            .expect("Value should have next");
+        loop {
+            if n == 0 {
+                self.resolve_to_value(key);
+                return;
+            }
+            match self.grammar.symbols[key].symbol {
+                Symbol::Value(_) => {
+                    let run = self.grammar.symbols[key].run as usize;
+                    if n < run {
+                        self.current = Some(key);
+                        self.remaining_run = (run - n) as u64;
+                        return;
+                    }
+                    n -= run;
+                    key = self.grammar.symbols[key]
+                        .next
+                        .expect("Value should have next");
+                }
+
+                Symbol::RuleRef { .. } => {
+                    let run = self.grammar.symbols[key].run;
+                    key = self.skip_rule_runs(key, run, &mut n);
+                }
+
+                Symbol::RuleHead { .. } | Symbol::DocHead { .. } => {
+                    key = self.grammar.symbols[key]
+                        .next
+                        .expect("Head should have next");
+                }
+
+                Symbol::RuleTail => {
+                    // Finished one repetition; skip whole ones that are left
+                    let entry = self.stack.pop().expect("fewer values remain than skipped");
+                    key = self.skip_rule_runs(entry.key, entry.remaining_run - 1, &mut n);
+                }
+
+                Symbol::DocTail => {
+                    self.resolve_to_value(key);
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Skips as many of the `runs` remaining expansions of the RuleRef at `key`
+    /// as fit in `n`, and returns the key to continue from: past the RuleRef if
+    /// all were skipped, or the start of the rule otherwise.
+    fn skip_rule_runs(&mut self, key: DefaultKey, runs: u64, n: &mut usize) -> DefaultKey {
+        let Symbol::RuleRef { rule_id } = self.grammar.symbols[key].symbol else {
+            unreachable!("only RuleRefs repeat rules");
+        };
+        let len = self.grammar.rule_length(rule_id, &mut self.lengths);
+        let whole = (*n / len).min(runs as usize);
+        *n -= whole * len;
+
+        if whole == runs as usize {
+            return self.grammar.symbols[key]
+                .next
+                .expect("RuleRef should have next");
+        }
+        self.stack.push(StackEntry {
+            key,
+            remaining_run: runs - whole as u64,
+        });
+        let rule_head = self.grammar.rule_index[&rule_id];
+        self.grammar.symbols[rule_head]
+            .next
+            .expect("Rule should have content")
+    }
+
+    /// Returns the last symbol in a rule's body.
+    fn rule_last(&self, rule_id: u32) -> DefaultKey {
+        let rule_head = *self
+            .grammar
+            .rule_index
+            .get(&rule_id)
+            .expect("Rule should exist");
+        let Symbol::RuleHead { tail, .. } = self.grammar.symbols[rule_head].symbol else {
+            unreachable!("rule index should point at RuleHeads");
+        };
+        self.grammar.symbols[tail]
+            .prev
+            .expect("Rule should have content")
+    }
+
+    /// Steps the back cursor to the previous value.
+    fn retreat(&mut self) {
+        if self.back_run > 1 {
+            self.back_run -= 1;
+            return;
+        }
+
+        let Some(back) = self.back else {
+            return;
+        };
+
+        if let Some(prev) = self.grammar.symbols[back].prev {
+            self.resolve_to_value_back(prev);
+        } else {
+            self.back = None;
+            self.back_run = 0;
+        }
+    }
 }
 
 impl<'a, T: Hash + Eq + Clone, DocId: Hash + Eq + Clone> Iterator
@@ -158,7 +405,11 @@ impl<'a, T: Hash + Eq + Clone, DocId: Hash + Eq + Clone> Iterator
     type Item = &'a T;
 
     fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
         let current_key = self.current?;
+        self.remaining -= 1;
 
         let value = match &self.grammar.symbols[current_key].symbol {
             Symbol::Value(v) => v,
@@ -169,6 +420,92 @@ impl<'a, T: Hash + Eq + Clone, DocId: Hash + Eq + Clone> Iterator
 
         Some(value)
     }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+
+    fn count(self) -> usize {
+        self.remaining
+    }
+
+    fn last(mut self) -> Option<Self::Item> {
+        self.next_back()
+    }
+
+    /// Skips `n` values, stepping over whole runs and rule expansions where
+    /// possible.
+    fn nth(&mut self, n: usize) -> Option<Self::Item> {
+        if n >= self.remaining {
+            self.remaining = 0;
+            return None;
+        }
+        if n > 0 {
+            self.skip_forward(n);
+            self.remaining -= n;
+        }
+        self.next()
+    }
+}
+
+impl<T: Hash + Eq + Clone, DocId: Hash + Eq + Clone> DoubleEndedIterator
+    for RleDocumentIter<'_, T, DocId>
+{
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let back_key = self.back?;
+        self.remaining -= 1;
+
+        let value = match &self.grammar.symbols[back_key].symbol {
+            Symbol::Value(v) => v,
+            _ => unreachable!("back should always be a Value symbol"),
+        };
+
+        self.retreat();
+
+        Some(value)
+    }
+}
+
+impl<T: Hash + Eq + Clone, DocId: Hash + Eq + Clone> ExactSizeIterator
+    for RleDocumentIter<'_, T, DocId>
+{
+}
+
+impl<T: Hash + Eq + Clone, DocId: Hash + Eq + Clone> FusedIterator
+    for RleDocumentIter<'_, T, DocId>
+{
+}
+
+/// Iterator over the maximal runs of a single document in SequiturDocumentsRle.
+///
+/// Yields `(value, count)` pairs without expanding runs element by element.
+/// Adjacent runs of the same value are merged, so consecutive pairs always
+/// hold different values unless a count would overflow `u64`.
+pub struct RleDocumentRunIter<'a, T, DocId> {
+    iter: RleDocumentIter<'a, T, DocId>,
+}
+
+impl<'a, T: Hash + Eq + Clone, DocId: Hash + Eq + Clone> Iterator
+    for RleDocumentRunIter<'a, T, DocId>
+{
+    type Item = (&'a T, u64);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next_run()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.iter.remaining;
+        (remaining.min(1), Some(remaining))
+    }
+}
+
+impl<T: Hash + Eq + Clone, DocId: Hash + Eq + Clone> FusedIterator
+    for RleDocumentRunIter<'_, T, DocId>
+{
 }
 
 impl<T: Hash + Eq + Clone, DocId: Hash + Eq + Clone> SequiturDocumentsRle<T, DocId> {
@@ -190,6 +527,49 @@ impl<T: Hash + Eq + Clone, DocId: Hash + Eq + Clone> SequiturDocumentsRle<T, Doc
     pub fn iter_document(&self, doc_id: &DocId) -> Option<RleDocumentIter<'_, T, DocId>> {
         RleDocumentIter::new(self, doc_id)
     }
+
+    /// Returns an iterator over the runs of a specific document.
+    ///
+    /// Returns `None` if the document doesn't exist.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use sequitur_rs::SequiturDocumentsRle;
+    ///
+    /// let mut docs = SequiturDocumentsRle::new();
+    /// docs.extend_document("row", "0000011100".chars());
+    ///
+    /// let runs: Vec<_> = docs.iter_document_runs(&"row").unwrap().collect();
+    /// assert_eq!(runs, [(&'0', 5), (&'1', 3), (&'0', 2)]);
+    /// ```
+    pub fn iter_document_runs(&self, doc_id: &DocId) -> Option<RleDocumentRunIter<'_, T, DocId>> {
+        self.iter_document(doc_id)
+            .map(|iter| RleDocumentRunIter { iter })
+    }
+
+    /// Returns an iterator over the values in a specific document after
+    /// checking that the grammar is consistent, so iteration can't panic.
+    ///
+    /// Returns `Ok(None)` if the document doesn't exist. The check walks the
+    /// whole grammar, so prefer [`iter_document`](Self::iter_document) for
+    /// collections that were built rather than restored.
+    pub fn try_iter_document(
+        &self,
+        doc_id: &DocId,
+    ) -> Result<Option<RleDocumentIter<'_, T, DocId>>, SequiturError> {
+        if !self.documents.contains_key(doc_id) {
+            return Ok(None);
+        }
+        // Rules are shared, so their counts depend on every document.
+        let sequences: Vec<_> = self
+            .documents
+            .values()
+            .map(|info| (info.head, info.length))
+            .collect();
+        self.grammar.validate(&sequences, None)?;
+        Ok(self.iter_document(doc_id))
+    }
 }
 
 #[cfg(test)]
@@ -277,4 +657,53 @@ mod tests {
         assert_eq!(result1, "aaabbb");
         assert_eq!(result2, "aaaccc");
     }
+
+    #[test]
+    fn test_iter_rev() {
+        let mut docs = SequiturDocumentsRle::new();
+        docs.extend_document(1, "aaabbbaaabbb".chars());
+        docs.extend_document(2, "caaabbbaaabbbc".chars());
+
+        let reversed: String = docs.iter_document(&2).unwrap().rev().collect();
+        assert_eq!(reversed, "cbbbaaabbbaaac");
+
+        let mut iter = docs.iter_document(&1).unwrap();
+        assert_eq!(iter.next_back(), Some(&'b'));
+        assert_eq!(iter.next(), Some(&'a'));
+        assert_eq!(iter.collect::<String>(), "aabbbaaabb");
+    }
+
+    #[test]
+    fn test_iter_exact_size_and_nth() {
+        let mut docs = SequiturDocumentsRle::new();
+        docs.extend_document(1, "xyxy".repeat(50).chars());
+        docs.extend_document(2, "xyxy".repeat(100).chars());
+
+        let mut iter = docs.iter_document(&2).unwrap();
+        assert_eq!(iter.len(), 400);
+        assert_eq!(iter.nth(251), Some(&'y'));
+        assert_eq!(iter.len(), 148);
+        assert!(iter.skip(100).copied().eq("xy".repeat(24).chars()));
+    }
+
+    #[test]
+    fn test_iter_document_runs() {
+        let mut docs = SequiturDocumentsRle::new();
+        docs.extend_document(1, "xxyyxxyy".chars());
+        docs.extend_document(2, "yxxyyxxyyx".chars());
+
+        let runs: Vec<_> = docs.iter_document_runs(&2).unwrap().collect();
+        assert_eq!(
+            runs,
+            [
+                (&'y', 1),
+                (&'x', 2),
+                (&'y', 2),
+                (&'x', 2),
+                (&'y', 2),
+                (&'x', 1)
+            ]
+        );
+        assert!(docs.iter_document_runs(&3).is_none());
+    }
 }
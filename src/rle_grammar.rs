@@ -37,6 +37,114 @@ impl<T> RleGrammar<T> {
             id_gen: IdGenerator::new(),
         }
     }
+
+    /// Returns an iterator over the keys of the nodes in a rule or document body.
+    ///
+    /// `head` must be a RuleHead or DocHead; the sentinels themselves are not yielded.
+    pub fn body_keys(&self, head: DefaultKey) -> RleBodyKeys<'_, T> {
+        RleBodyKeys {
+            grammar: self,
+            current: self.symbols[head].next,
+        }
+    }
+
+    // ========================================================================
+    // Analysis
+    // ========================================================================
+
+    /// Computes the expanded length of every rule, counting runs.
+    pub fn rule_lengths(&self) -> HashMap<u32, usize> {
+        let mut lengths = HashMap::default();
+        for &rule_id in self.rule_index.keys() {
+            self.rule_length(rule_id, &mut lengths);
+        }
+        lengths
+    }
+
+    pub(crate) fn rule_length(&self, rule_id: u32, lengths: &mut HashMap<u32, usize>) -> usize {
+        if let Some(&len) = lengths.get(&rule_id) {
+            return len;
+        }
+
+        let head = self.rule_index[&rule_id];
+        let mut len = 0;
+        for key in self.body_keys(head) {
+            let node = &self.symbols[key];
+            let unit = match node.symbol {
+                Symbol::RuleRef { rule_id } => self.rule_length(rule_id, lengths),
+                _ => 1,
+            };
+            len += unit * node.run as usize;
+        }
+
+        lengths.insert(rule_id, len);
+        len
+    }
+
+    /// Calls `f(rule_id, start, len)` for every rule occurrence, at any nesting
+    /// depth, in the expansion of the sequence starting at `head`.
+    ///
+    /// A RuleRef with run `k` is reported as `k` consecutive occurrences. `start`
+    /// is relative to the start of the sequence, and occurrences are reported in
+    /// order of their start.
+    pub fn for_each_rule_occurrence<F>(
+        &self,
+        head: DefaultKey,
+        lengths: &HashMap<u32, usize>,
+        f: &mut F,
+    ) where
+        F: FnMut(u32, usize, usize),
+    {
+        self.visit_occurrences(head, 0, lengths, f);
+    }
+
+    fn visit_occurrences<F>(
+        &self,
+        head: DefaultKey,
+        mut offset: usize,
+        lengths: &HashMap<u32, usize>,
+        f: &mut F,
+    ) where
+        F: FnMut(u32, usize, usize),
+    {
+        for key in self.body_keys(head) {
+            let node = &self.symbols[key];
+            if let Symbol::RuleRef { rule_id } = node.symbol {
+                let len = lengths[&rule_id];
+                let rule_head = self.rule_index[&rule_id];
+                for _ in 0..node.run {
+                    f(rule_id, offset, len);
+                    self.visit_occurrences(rule_head, offset, lengths, f);
+                    offset += len;
+                }
+            } else {
+                offset += node.run as usize;
+            }
+        }
+    }
+}
+
+/// Iterator over the node keys between a head sentinel and its tail.
+pub(crate) struct RleBodyKeys<'a, T> {
+    grammar: &'a RleGrammar<T>,
+    current: Option<DefaultKey>,
+}
+
+impl<T> Iterator for RleBodyKeys<'_, T> {
+    type Item = DefaultKey;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let key = self.current?;
+        if matches!(
+            self.grammar.symbols[key].symbol,
+            Symbol::RuleTail | Symbol::DocTail
+        ) {
+            self.current = None;
+            return None;
+        }
+        self.current = self.grammar.symbols[key].next;
+        Some(key)
+    }
 }
 
 impl<T: Hash + Eq + Clone> RleGrammar<T> {
@@ -66,6 +174,14 @@ impl<T: Hash + Eq + Clone> RleGrammar<T> {
             return false;
         }
 
+        // Leave runs that would overflow when combined as separate nodes
+        let Some(merged_run) = self.symbols[key]
+            .run
+            .checked_add(self.symbols[next_key].run)
+        else {
+            return false;
+        };
+
         // Remove digrams that will be invalidated
         if let Some(prev) = self.symbols[key].prev {
             self.remove_digram_from_index(prev);
@@ -74,8 +190,7 @@ impl<T: Hash + Eq + Clone> RleGrammar<T> {
         self.remove_digram_from_index(next_key);
 
         // Merge: add next's run to current
-        let next_run = self.symbols[next_key].run;
-        self.symbols[key].run += next_run;
+        self.symbols[key].run = merged_run;
 
         // Relink: skip over next_key
         let after_next = self.symbols[next_key].next;
@@ -101,7 +216,7 @@ impl<T: Hash + Eq + Clone> RleGrammar<T> {
     /// - New second node: run=2 (inserted after first)
     ///
     /// Returns the key of the new second node.
-    pub fn split_node(&mut self, key: DefaultKey, first_run: u32) -> DefaultKey {
+    pub fn split_node(&mut self, key: DefaultKey, first_run: u64) -> DefaultKey {
         let total_run = self.symbols[key].run;
         debug_assert!(
             first_run > 0 && first_run < total_run,
@@ -358,8 +473,8 @@ impl<T: Hash + Eq + Clone> RleGrammar<T> {
     fn prepare_digram_for_rule(
         &mut self,
         first: DefaultKey,
-        target_first_run: u32,
-        target_second_run: u32,
+        target_first_run: u64,
+        target_second_run: u64,
     ) -> (DefaultKey, DefaultKey) {
         let mut first_key = first;
         let mut second_key = self.symbols[first].next.unwrap();
@@ -500,7 +615,8 @@ impl<T: Hash + Eq + Clone> RleGrammar<T> {
         self.remove_digram_from_index(potential_rule);
 
         self.rule_index.remove(&rule_id);
-        self.id_gen.free(rule_id);
+        let freed = self.id_gen.free(rule_id);
+        debug_assert!(freed, "rule {rule_id} should have an allocated ID");
 
         self.symbols[rule_head].next = None;
         self.symbols[rule_first].prev = None;
@@ -728,6 +844,107 @@ impl<T: Hash + Eq + Clone> RleGrammar<T> {
         }
     }
 
+    /// Re-establishes the RLE constraints over the whole grammar.
+    ///
+    /// Each body in `heads` is detached and re-appended one node at a time, so
+    /// [`link_made`](Self::link_made) merges every pair of identical neighbours
+    /// into one run and sees every digram once. Rules must come before the
+    /// bodies using them, so any rule that gets inlined has already been
+    /// replayed. `heads` must cover every sequence, since rules the replay
+    /// leaves with a single use are then looked up in them and inlined.
+    pub(crate) fn enforce_digram_uniqueness(
+        &mut self,
+        heads: impl IntoIterator<Item = DefaultKey>,
+    ) {
+        self.digram_index.clear();
+
+        let heads: Vec<_> = heads.into_iter().collect();
+        for &head in &heads {
+            // A rule left with a single reference of run 1 while replaying its
+            // parents has been inlined.
+            if !self.symbols.contains_key(head) {
+                continue;
+            }
+            let (Symbol::RuleHead { tail, .. } | Symbol::DocHead { tail }) =
+                self.symbols[head].symbol
+            else {
+                unreachable!("sequences should start with a RuleHead or DocHead");
+            };
+            let keys: Vec<_> = self.body_keys(head).collect();
+
+            // The placeholder keeps a partial body from being taken for a
+            // complete rule. A node `link_made` merges into the run before it is
+            // removed, so each node goes after whatever now precedes the
+            // placeholder rather than after the previous key.
+            let end = self.symbols.insert(RleSymbolNode::new(Symbol::DocTail));
+            self.symbols[tail].prev = None;
+            self.symbols[head].next = Some(end);
+            self.symbols[end].prev = Some(head);
+
+            for key in keys {
+                let prev = self.symbols[end]
+                    .prev
+                    .expect("placeholder should have prev");
+                self.symbols[key].prev = Some(prev);
+                self.symbols[key].next = Some(end);
+                self.symbols[prev].next = Some(key);
+                self.symbols[end].prev = Some(key);
+                if prev != head {
+                    self.link_made(prev);
+                }
+            }
+
+            let last = self.symbols[end]
+                .prev
+                .expect("placeholder should have prev");
+            self.symbols[last].next = Some(tail);
+            self.symbols[tail].prev = Some(last);
+            self.symbols.remove(end);
+        }
+
+        self.inline_single_use_rules(&heads);
+    }
+
+    /// Inlines every rule referenced once with run 1 until none is left.
+    ///
+    /// The replay only inlines the rules in the bodies of the rules it uses,
+    /// so a rule that lost a use elsewhere can be left with one.
+    fn inline_single_use_rules(&mut self, heads: &[DefaultKey]) {
+        loop {
+            let mut sequences: Vec<_> = self.rule_index.values().copied().collect();
+            sequences.extend(heads.iter().filter(|&&head| {
+                self.symbols.contains_key(head)
+                    && matches!(self.symbols[head].symbol, Symbol::DocHead { .. })
+            }));
+            let single_uses: Vec<_> = sequences
+                .into_iter()
+                .flat_map(|head| self.body_keys(head))
+                .filter(|&key| self.is_single_use(key))
+                .collect();
+            if single_uses.is_empty() {
+                return;
+            }
+            for key in single_uses {
+                if self.symbols.contains_key(key) {
+                    self.expand_rule_if_necessary(key);
+                }
+            }
+        }
+    }
+
+    /// Whether `key` is the only reference to its rule, with run 1.
+    fn is_single_use(&self, key: DefaultKey) -> bool {
+        let node = &self.symbols[key];
+        let Symbol::RuleRef { rule_id } = node.symbol else {
+            return false;
+        };
+        node.run == 1
+            && matches!(
+                self.symbols[self.rule_index[&rule_id]].symbol,
+                Symbol::RuleHead { count: 1, .. }
+            )
+    }
+
     // ========================================================================
     // Helper methods
     // ========================================================================
@@ -1,8 +1,11 @@
+use crate::error::SequiturError;
 use crate::rle_grammar::RleGrammar;
 use crate::rle_sequitur::SequiturRle;
 use crate::symbol::Symbol;
+use ahash::AHashMap as HashMap;
 use slotmap::DefaultKey;
 use std::hash::Hash;
+use std::iter::FusedIterator;
 
 /// Iterator that reconstructs the original sequence from RLE-Sequitur.
 ///
@@ -11,34 +14,58 @@ pub struct RleSequiturIter<'a, T> {
     grammar: &'a RleGrammar<T>,
     current: Option<DefaultKey>,
     /// Remaining count for the current symbol's run
-    remaining_run: u32,
+    remaining_run: u64,
     /// Stack for tracking rule expansion
     stack: Vec<StackEntry>,
+    /// Next value from the back
+    back: Option<DefaultKey>,
+    /// Remaining count for the back symbol's run
+    back_run: u64,
+    /// Stack for tracking rule expansion from the back
+    back_stack: Vec<StackEntry>,
+    /// Values not yet yielded from either end
+    remaining: usize,
+    /// Expanded rule lengths, filled in as [`nth`](Iterator::nth) needs them
+    lengths: HashMap<u32, usize>,
 }
 
 /// Stack entry for tracking position during rule expansion.
 struct StackEntry {
     key: DefaultKey,
     /// Remaining run count when we descended into a rule
-    remaining_run: u32,
+    remaining_run: u64,
 }
 
 impl<'a, T: Hash + Eq + Clone> RleSequiturIter<'a, T> {
     pub(crate) fn new(sequitur: &'a SequiturRle<T>) -> Self {
-        let rule_0_head = *sequitur.rules().get(&0).expect("Rule 0 should exist");
-        let start = sequitur.grammar.symbols[rule_0_head]
-            .next
-            .expect("Rule 0 should have content");
-
         let mut iter = Self {
             grammar: &sequitur.grammar,
             current: None,
             remaining_run: 0,
             stack: Vec::new(),
+            back: None,
+            back_run: 0,
+            back_stack: Vec::new(),
+            remaining: sequitur.len(),
+            lengths: HashMap::default(),
         };
 
-        // Resolve to first Value
+        // Without Rule 0 the grammar is corrupt; yield nothing rather than
+        // panic, and leave reporting it to `try_iter`.
+        let symbols = &sequitur.grammar.symbols;
+        let ends = sequitur.rules().get(&0).and_then(|&head| {
+            let start = symbols.get(head)?.next?;
+            let end = symbols.get(sequitur.sequence_end)?.prev?;
+            Some((start, end))
+        });
+        let Some((start, end)) = ends else {
+            iter.remaining = 0;
+            return iter;
+        };
+
+        // Resolve to first and last Values
         iter.resolve_to_value(start);
+        iter.resolve_to_value_back(end);
         iter
     }
 
@@ -149,13 +176,236 @@ impl<'a, T: Hash + Eq + Clone> RleSequiturIter<'a, T> {
             self.remaining_run = 0;
         }
     }
+
+    /// Returns the next maximal run of equal values and consumes it.
+    ///
+    /// Consecutive nodes holding the same value are merged, so a run that
+    /// spans the end of one rule and the start of another is reported once.
+    fn next_run(&mut self) -> Option<(&'a T, u64)> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let grammar = self.grammar;
+        let Symbol::Value(value) = &grammar.symbols[self.current?].symbol else {
+            unreachable!("current should always be a Value symbol");
+        };
+
+        let mut run = 0u64;
+        while self.remaining > 0 {
+            let Some(key) = self.current else { break };
+            match &grammar.symbols[key].symbol {
+                Symbol::Value(next) if next == value => {}
+                _ => break,
+            }
+            // Don't consume values the back cursor has already yielded
+            let take = self.remaining_run.min(self.remaining as u64);
+            let Some(total) = run.checked_add(take) else {
+                break;
+            };
+            run = total;
+            self.remaining -= take as usize;
+            self.remaining_run = 1;
+            self.advance();
+        }
+        Some((value, run))
+    }
+
+    /// Resolves backward through the grammar to find the previous Value symbol.
+    ///
+    /// Mirrors [`resolve_to_value`](Self::resolve_to_value), entering rules at
+    /// their last symbol and re-entering them once per remaining run.
+    fn resolve_to_value_back(&mut self, mut key: DefaultKey) {
+        loop {
+            match &self.grammar.symbols[key].symbol {
+                Symbol::Value(_) => {
+                    self.back = Some(key);
+                    self.back_run = self.grammar.symbols[key].run;
+                    return;
+                }
+
+                Symbol::RuleRef { rule_id } => {
+                    let run = self.grammar.symbols[key].run;
+                    self.back_stack.push(StackEntry {
+                        key,
+                        remaining_run: run,
+                    });
+                    key = self.rule_last(*rule_id);
+                }
+
+                Symbol::RuleTail | Symbol::DocTail => {
+                    key = self.grammar.symbols[key]
+                        .prev
+                        .expect("Tail should have prev");
+                }
+
+                Symbol::RuleHead { .. } => {
+                    if let Some(entry) = self.back_stack.pop() {
+                        let new_remaining = entry.remaining_run - 1;
+                        if new_remaining > 0 {
+                            self.back_stack.push(StackEntry {
+                                key: entry.key,
+                                remaining_run: new_remaining,
+                            });
+
+                            if let Symbol::RuleRef { rule_id } =
+                                self.grammar.symbols[entry.key].symbol
+                            {
+                                key = self.rule_last(rule_id);
+                                continue;
+                            }
+                        }
+
+                        if let Some(prev) = self.grammar.symbols[entry.key].prev {
+                            key = prev;
+                            continue;
+                        }
+                    }
+
+                    self.back = None;
+                    self.back_run = 0;
+                    return;
+                }
+
+                Symbol::DocHead { .. } => {
+                    // Start of document
+                    self.back = None;
+                    self.back_run = 0;
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Moves the front cursor past `n` values, stepping over whole runs and
+    /// rule expansions where possible.
+    ///
+    /// At least `n` values must remain after the current one.
+    fn skip_forward(&mut self, mut n: usize) {
+        let run = self.remaining_run as usize;
+        if n < run {
+            self.remaining_run -= n as u64;
+            return;
+        }
+        n -= run;
+
+        let current = self.current.expect("a value should remain");
+        let mut key = self.grammar.symbols[current]
+            .next
+            .expect("Value should have next");
+        loop {
+            if n == 0 {
+                self.resolve_to_value(key);
+                return;
+            }
+            match self.grammar.symbols[key].symbol {
+                Symbol::Value(_) => {
+                    let run = self.grammar.symbols[key].run as usize;
+                    if n < run {
+                        self.current = Some(key);
+                        self.remaining_run = (run - n) as u64;
+                        return;
+                    }
+                    n -= run;
+                    key = self.grammar.symbols[key]
+                        .next
+                        .expect("Value should have next");
+                }
+
+                Symbol::RuleRef { .. } => {
+                    let run = self.grammar.symbols[key].run;
+                    key = self.skip_rule_runs(key, run, &mut n);
+                }
+
+                Symbol::RuleHead { .. } | Symbol::DocHead { .. } => {
+                    key = self.grammar.symbols[key]
+                        .next
+                        .expect("Head should have next");
+                }
+
+                Symbol::RuleTail => {
+                    // Finished one repetition; skip whole ones that are left
+                    let entry = self.stack.pop().expect("fewer values remain than skipped");
+                    key = self.skip_rule_runs(entry.key, entry.remaining_run - 1, &mut n);
+                }
+
+                Symbol::DocTail => {
+                    self.resolve_to_value(key);
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Skips as many of the `runs` remaining expansions of the RuleRef at `key`
+    /// as fit in `n`, and returns the key to continue from: past the RuleRef if
+    /// all were skipped, or the start of the rule otherwise.
+    fn skip_rule_runs(&mut self, key: DefaultKey, runs: u64, n: &mut usize) -> DefaultKey {
+        let Symbol::RuleRef { rule_id } = self.grammar.symbols[key].symbol else {
+            unreachable!("only RuleRefs repeat rules");
+        };
+        let len = self.grammar.rule_length(rule_id, &mut self.lengths);
+        let whole = (*n / len).min(runs as usize);
+        *n -= whole * len;
+
+        if whole == runs as usize {
+            return self.grammar.symbols[key]
+                .next
+                .expect("RuleRef should have next");
+        }
+        self.stack.push(StackEntry {
+            key,
+            remaining_run: runs - whole as u64,
+        });
+        let rule_head = self.grammar.rule_index[&rule_id];
+        self.grammar.symbols[rule_head]
+            .next
+            .expect("Rule should have content")
+    }
+
+    /// Returns the last symbol in a rule's body.
+    fn rule_last(&self, rule_id: u32) -> DefaultKey {
+        let rule_head = *self
+            .grammar
+            .rule_index
+            .get(&rule_id)
+            .expect("Rule should exist");
+        let Symbol::RuleHead { tail, .. } = self.grammar.symbols[rule_head].symbol else {
+            unreachable!("rule index should point at RuleHeads");
+        };
+        self.grammar.symbols[tail]
+            .prev
+            .expect("Rule should have content")
+    }
+
+    /// Steps the back cursor to the previous value.
+    fn retreat(&mut self) {
+        if self.back_run > 1 {
+            self.back_run -= 1;
+            return;
+        }
+
+        let Some(back) = self.back else {
+            return;
+        };
+
+        if let Some(prev) = self.grammar.symbols[back].prev {
+            self.resolve_to_value_back(prev);
+        } else {
+            self.back = None;
+            self.back_run = 0;
+        }
+    }
 }
 
 impl<'a, T: Hash + Eq + Clone> Iterator for RleSequiturIter<'a, T> {
     type Item = &'a T;
 
     fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
         let current_key = self.current?;
+        self.remaining -= 1;
 
         let value = match &self.grammar.symbols[current_key].symbol {
             Symbol::Value(v) => v,
@@ -166,13 +416,116 @@ impl<'a, T: Hash + Eq + Clone> Iterator for RleSequiturIter<'a, T> {
 
         Some(value)
     }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+
+    fn count(self) -> usize {
+        self.remaining
+    }
+
+    fn last(mut self) -> Option<Self::Item> {
+        self.next_back()
+    }
+
+    /// Skips `n` values, stepping over whole runs and rule expansions where
+    /// possible.
+    fn nth(&mut self, n: usize) -> Option<Self::Item> {
+        if n >= self.remaining {
+            self.remaining = 0;
+            return None;
+        }
+        if n > 0 {
+            self.skip_forward(n);
+            self.remaining -= n;
+        }
+        self.next()
+    }
+}
+
+impl<T: Hash + Eq + Clone> DoubleEndedIterator for RleSequiturIter<'_, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let back_key = self.back?;
+        self.remaining -= 1;
+
+        let value = match &self.grammar.symbols[back_key].symbol {
+            Symbol::Value(v) => v,
+            _ => unreachable!("back should always be a Value symbol"),
+        };
+
+        self.retreat();
+
+        Some(value)
+    }
+}
+
+impl<T: Hash + Eq + Clone> ExactSizeIterator for RleSequiturIter<'_, T> {}
+
+impl<T: Hash + Eq + Clone> FusedIterator for RleSequiturIter<'_, T> {}
+
+/// Iterator over the maximal runs of the reconstructed sequence.
+///
+/// Yields `(value, count)` pairs without expanding runs element by element.
+/// Adjacent runs of the same value are merged, so consecutive pairs always
+/// hold different values unless a count would overflow `u64`.
+pub struct RleRunIter<'a, T> {
+    iter: RleSequiturIter<'a, T>,
+}
+
+impl<'a, T: Hash + Eq + Clone> Iterator for RleRunIter<'a, T> {
+    type Item = (&'a T, u64);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next_run()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.iter.remaining;
+        (remaining.min(1), Some(remaining))
+    }
 }
 
+impl<T: Hash + Eq + Clone> FusedIterator for RleRunIter<'_, T> {}
+
 impl<T: Hash + Eq + Clone> SequiturRle<T> {
     /// Returns an iterator over the reconstructed sequence.
     pub fn iter(&self) -> RleSequiturIter<'_, T> {
         RleSequiturIter::new(self)
     }
+
+    /// Returns an iterator over the reconstructed sequence after checking that
+    /// the grammar is consistent, so iteration can't panic or stop early.
+    ///
+    /// The check walks the whole grammar, so prefer [`iter`](Self::iter) for
+    /// grammars that were built rather than restored.
+    pub fn try_iter(&self) -> Result<RleSequiturIter<'_, T>, SequiturError> {
+        let Some(&head) = self.grammar.rule_index.get(&0) else {
+            return Err(SequiturError::CorruptGrammar("rule 0 is missing".into()));
+        };
+        self.grammar.validate(&[(head, self.length)], Some(0))?;
+        Ok(self.iter())
+    }
+
+    /// Returns an iterator over the runs of the reconstructed sequence.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use sequitur_rs::SequiturRle;
+    ///
+    /// let mut seq = SequiturRle::new();
+    /// seq.extend("aaabccccaaab".chars());
+    ///
+    /// let runs: Vec<(char, u64)> = seq.iter_runs().map(|(&c, n)| (c, n)).collect();
+    /// assert_eq!(runs, [('a', 3), ('b', 1), ('c', 4), ('a', 3), ('b', 1)]);
+    /// ```
+    pub fn iter_runs(&self) -> RleRunIter<'_, T> {
+        RleRunIter { iter: self.iter() }
+    }
 }
 
 impl<'a, T: Hash + Eq + Clone> IntoIterator for &'a SequiturRle<T> {
@@ -267,4 +620,56 @@ mod tests {
         let collected: Vec<&i32> = (&seq).into_iter().collect();
         assert_eq!(collected, vec![&1, &2, &3]);
     }
+
+    #[test]
+    fn test_iter_rev() {
+        let mut seq = SequiturRle::new();
+        seq.extend("aaabbbabababaaabbb".chars());
+        let reversed: String = seq.iter().rev().collect();
+        assert_eq!(reversed, "bbbaaababababbbaaa");
+    }
+
+    #[test]
+    fn test_iter_both_ends_within_run() {
+        let mut seq = SequiturRle::new();
+        seq.extend("xaaaaay".chars());
+        let mut iter = seq.iter();
+        assert_eq!(iter.next_back(), Some(&'y'));
+        assert_eq!(iter.next(), Some(&'x'));
+        assert_eq!(iter.next_back(), Some(&'a'));
+        assert_eq!(iter.next(), Some(&'a'));
+        assert_eq!(iter.count(), 3);
+    }
+
+    #[test]
+    fn test_iter_exact_size_and_nth() {
+        let mut seq = SequiturRle::new();
+        let input = "ab".repeat(300) + &"c".repeat(1000) + "d";
+        seq.extend(input.chars());
+
+        let mut iter = seq.iter();
+        assert_eq!(iter.len(), input.len());
+        assert_eq!(iter.nth(3), Some(&'b'));
+        assert_eq!(iter.nth(700), Some(&'c'));
+        assert_eq!(iter.len(), input.len() - 705);
+        assert_eq!(iter.next_back(), Some(&'d'));
+        assert_eq!(iter.nth(893), Some(&'c'));
+        assert_eq!(iter.next(), Some(&'c'));
+        assert_eq!(iter.next(), None);
+    }
+
+    #[test]
+    fn test_iter_runs_merges_across_rules() {
+        // "ab" becomes a rule, so the b's after each "ab" sit in another node.
+        let mut seq = SequiturRle::new();
+        seq.extend("abbbabbbab".chars());
+        assert!(seq.rules().len() > 1);
+
+        let runs: Vec<(char, u64)> = seq.iter_runs().map(|(&c, n)| (c, n)).collect();
+        assert_eq!(
+            runs,
+            [('a', 1), ('b', 3), ('a', 1), ('b', 3), ('a', 1), ('b', 1)]
+        );
+        assert_eq!(SequiturRle::<char>::new().iter_runs().next(), None);
+    }
 }
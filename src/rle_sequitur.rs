@@ -1,3 +1,4 @@
+use crate::error::SequiturError;
 use crate::rle_grammar::RleGrammar;
 use crate::rle_symbol::RleSymbolNode;
 use crate::symbol::Symbol;
@@ -25,7 +26,7 @@ pub struct SequiturRle<T> {
     pub(crate) sequence_end: DefaultKey,
 
     /// Number of values added (counting run lengths)
-    length: usize,
+    pub(crate) length: usize,
 }
 
 impl<T: Hash + Eq + Clone> SequiturRle<T> {
@@ -62,17 +63,55 @@ impl<T: Hash + Eq + Clone> SequiturRle<T> {
     /// If the previous symbol is the same value, its run count is incremented
     /// instead of creating a new node.
     pub fn push(&mut self, value: T) {
+        self.push_run(value, 1);
+    }
+
+    /// Adds `count` copies of a value to the sequence.
+    ///
+    /// The copies are stored as a single node, or added to the previous node's
+    /// run if it holds the same value, and the grammar is updated once rather
+    /// than once per copy. This lets run-length encoded input be ingested
+    /// directly. A run that would exceed `u64::MAX` continues in a new node.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the length of the sequence would overflow `usize`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use sequitur_rs::SequiturRle;
+    ///
+    /// let mut seq = SequiturRle::new();
+    /// seq.push_run('x', 1_000_000);
+    /// seq.push_run('y', 3);
+    ///
+    /// assert_eq!(seq.len(), 1_000_003);
+    /// assert_eq!(seq.stats().grammar_nodes, 2);
+    /// ```
+    pub fn push_run(&mut self, value: T, count: u64) {
+        if count == 0 {
+            return;
+        }
         let tail_key = self.sequence_end;
         let prev_key = self.grammar.symbols[tail_key].prev;
+        self.length = usize::try_from(count)
+            .ok()
+            .and_then(|count| self.length.checked_add(count))
+            .expect("sequence length overflows usize");
 
         // Check if we can extend the previous symbol's run
         if let Some(prev) = prev_key {
-            if let Symbol::Value(ref prev_val) = self.grammar.symbols[prev].symbol {
+            let node = &mut self.grammar.symbols[prev];
+            if let Symbol::Value(ref prev_val) = node.symbol {
                 if prev_val == &value {
-                    // Same value - just increment the run count
-                    self.grammar.symbols[prev].run += 1;
-                    self.length += 1;
-                    return;
+                    // Same value - add to the run count, or start a new node
+                    // holding the same value if the count would overflow
+                    if let Some(run) = node.run.checked_add(count) {
+                        node.run = run;
+                        self.recheck_last_digram();
+                        return;
+                    }
                 }
             }
         }
@@ -81,7 +120,7 @@ impl<T: Hash + Eq + Clone> SequiturRle<T> {
         let new_key = self
             .grammar
             .symbols
-            .insert(RleSymbolNode::new(Symbol::Value(value)));
+            .insert(RleSymbolNode::with_run(Symbol::Value(value), count));
 
         self.grammar.symbols[new_key].next = Some(tail_key);
         self.grammar.symbols[new_key].prev = prev_key;
@@ -91,8 +130,6 @@ impl<T: Hash + Eq + Clone> SequiturRle<T> {
             self.grammar.symbols[prev].next = Some(new_key);
         }
 
-        self.length += 1;
-
         // Check for digram if not the first symbol
         if let Some(prev) = prev_key {
             if !matches!(self.grammar.symbols[prev].symbol, Symbol::RuleHead { .. }) {
@@ -101,6 +138,15 @@ impl<T: Hash + Eq + Clone> SequiturRle<T> {
         }
     }
 
+    /// Extends the sequence with `(value, count)` runs.
+    ///
+    /// Equivalent to calling [`push_run`](Self::push_run) for each pair.
+    pub fn extend_runs<I: IntoIterator<Item = (T, u64)>>(&mut self, iter: I) {
+        for (value, count) in iter {
+            self.push_run(value, count);
+        }
+    }
+
     /// Extends the sequence with multiple values.
     pub fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
         for value in iter {
@@ -108,6 +154,27 @@ impl<T: Hash + Eq + Clone> SequiturRle<T> {
         }
     }
 
+    /// Adds a value to the sequence, or returns an error instead of running out
+    /// of rule IDs or overflowing the length.
+    ///
+    /// The grammar is unchanged when an error is returned.
+    pub fn try_push(&mut self, value: T) -> Result<(), SequiturError> {
+        self.grammar.check_capacity(self.length, 1)?;
+        self.push(value);
+        Ok(())
+    }
+
+    /// Extends the sequence with multiple values, stopping at the first value
+    /// that [`try_push`](Self::try_push) rejects.
+    ///
+    /// Values before the rejected one remain in the sequence.
+    pub fn try_extend<I: IntoIterator<Item = T>>(&mut self, iter: I) -> Result<(), SequiturError> {
+        for value in iter {
+            self.try_push(value)?;
+        }
+        Ok(())
+    }
+
     /// Returns the number of values added to the sequence.
     pub fn len(&self) -> usize {
         self.length
@@ -133,7 +200,7 @@ impl<T: Hash + Eq + Clone> SequiturRle<T> {
             while let Some(key) = current {
                 if let Some(next) = self.grammar.symbols[key].next {
                     total_nodes += 1;
-                    total_run_sum += self.grammar.symbols[key].run as u64;
+                    total_run_sum += self.grammar.symbols[key].run;
                     current = Some(next);
                 } else {
                     break;
@@ -151,12 +218,15 @@ impl<T: Hash + Eq + Clone> SequiturRle<T> {
 
     /// Signals that the current run has finished and digram checking should occur.
     ///
-    /// Call this when you know a run of repeated symbols has ended and you want
-    /// to trigger grammar restructuring. This is optional - the grammar will
-    /// still be correct without calling this, but it may delay some optimizations.
+    /// [`push`](Self::push) and [`push_run`](Self::push_run) now re-check the
+    /// last digram whenever they extend a run, so this is never needed.
+    #[deprecated(note = "runs are re-checked automatically when they are extended")]
     pub fn end_run(&mut self) {
-        // This is called to signal that the current run is finished
-        // and we should check for digram patterns
+        self.recheck_last_digram();
+    }
+
+    /// Re-checks the digram ending at the last symbol after its run changed.
+    fn recheck_last_digram(&mut self) {
         let tail_key = self.sequence_end;
         if let Some(prev) = self.grammar.symbols[tail_key].prev {
             if !matches!(self.grammar.symbols[prev].symbol, Symbol::RuleHead { .. }) {
@@ -165,7 +235,6 @@ impl<T: Hash + Eq + Clone> SequiturRle<T> {
                         self.grammar.symbols[prev_prev].symbol,
                         Symbol::RuleHead { .. }
                     ) {
-                        // Re-check the last digram
                         self.grammar.link_made(prev_prev);
                     }
                 }
@@ -216,6 +285,7 @@ impl<T: Hash + Eq + Clone> Default for SequiturRle<T> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::id_gen::IdGenerator;
 
     #[test]
     fn test_new() {
@@ -304,4 +374,105 @@ mod tests {
         let stats = seq.stats();
         assert_eq!(stats.grammar_nodes, 1);
     }
+
+    #[test]
+    fn test_push_run() {
+        let mut seq = SequiturRle::new();
+        seq.push_run('a', 5);
+        seq.push_run('a', 3);
+        seq.push_run('b', 0);
+        seq.push('b');
+
+        assert_eq!(seq.len(), 9);
+        assert_eq!(seq.stats().grammar_nodes, 2);
+        assert_eq!(seq.iter().collect::<String>(), "aaaaaaaab");
+    }
+
+    #[test]
+    fn test_extend_runs() {
+        // A bitmap row, already run-length encoded
+        let row = [(0u8, 40), (1, 12), (0, 40), (1, 12), (0, 40), (1, 12)];
+        let mut seq = SequiturRle::new();
+        seq.extend_runs(row);
+
+        let expected: Vec<u8> = row
+            .iter()
+            .flat_map(|&(value, count)| std::iter::repeat_n(value, count as usize))
+            .collect();
+        assert_eq!(seq.len(), expected.len());
+        assert!(seq.iter().eq(expected.iter()));
+        assert!(seq.rules().len() > 1);
+    }
+
+    #[test]
+    fn test_runs_past_u32_max() {
+        let big = u64::from(u32::MAX) + 10;
+        let mut seq = SequiturRle::new();
+        seq.push_run('a', big);
+        seq.push_run('a', big);
+        seq.push('b');
+
+        assert_eq!(seq.len() as u64, 2 * big + 1);
+        assert_eq!(seq.stats().grammar_nodes, 2);
+        assert_eq!(seq.stats().grammar_symbols_expanded, 2 * big + 1);
+        assert_eq!(
+            seq.iter_runs().collect::<Vec<_>>(),
+            [(&'a', 2 * big), (&'b', 1)]
+        );
+
+        let mut iter = seq.iter();
+        assert_eq!(iter.nth(2 * big as usize - 1), Some(&'a'));
+        assert_eq!(iter.next(), Some(&'b'));
+        assert_eq!(iter.next(), None);
+    }
+
+    #[test]
+    fn test_rules_over_runs_past_u32_max() {
+        // Two similar digrams whose runs differ force a rule over split nodes.
+        let big = u64::from(u32::MAX) * 2;
+        let runs = [('a', big), ('b', 1), ('c', 1), ('a', big + 7), ('b', 3)];
+        let mut seq = SequiturRle::new();
+        seq.extend_runs(runs);
+
+        assert!(seq.rules().len() > 1);
+        let total: u64 = runs.iter().map(|&(_, n)| n).sum();
+        assert_eq!(seq.len() as u64, total);
+        let decoded: Vec<(char, u64)> = seq.iter_runs().map(|(&c, n)| (c, n)).collect();
+        assert_eq!(decoded, runs);
+    }
+
+    #[test]
+    fn test_run_saturation_splits_nodes() {
+        let mut seq = SequiturRle::new();
+        seq.push_run('a', 4);
+        seq.push_run('b', 1);
+
+        // Bring the first run to the limit, then push past it.
+        let rule_0_head = seq.rules()[&0];
+        let first = seq.grammar.symbols[rule_0_head].next.unwrap();
+        let second = seq.grammar.symbols[first].next.unwrap();
+        seq.grammar.symbols[second].run = u64::MAX - 1;
+        seq.push_run('b', 5);
+
+        let third = seq.grammar.symbols[second].next.unwrap();
+        assert_eq!(seq.grammar.symbols[second].run, u64::MAX - 1);
+        assert_eq!(seq.grammar.symbols[third].run, 5);
+        assert_eq!(seq.grammar.symbols[third].next, Some(seq.sequence_end));
+        assert!(!seq.grammar.try_merge_with_next(second));
+    }
+
+    #[test]
+    fn test_try_push() {
+        let mut seq = SequiturRle::new();
+        seq.try_extend("aabcaabc".chars()).unwrap();
+        assert_eq!(seq.iter().collect::<String>(), "aabcaabc");
+
+        seq.length = usize::MAX;
+        assert_eq!(seq.try_push('d'), Err(SequiturError::CapacityExceeded));
+
+        let mut seq = SequiturRle::new();
+        seq.grammar.id_gen = IdGenerator::starting_at(u32::MAX - 10);
+        assert_eq!(seq.try_push('a'), Err(SequiturError::RuleIdsExhausted));
+        assert!(seq.is_empty());
+    }
 }
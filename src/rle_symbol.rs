@@ -11,7 +11,7 @@ use std::hash::Hash;
 pub(crate) struct RleSymbolNode<T> {
     pub symbol: Symbol<T>,
     /// Number of consecutive occurrences (1 = single occurrence)
-    pub run: u32,
+    pub run: u64,
     pub prev: Option<DefaultKey>,
     pub next: Option<DefaultKey>,
 }
@@ -26,7 +26,7 @@ impl<T> RleSymbolNode<T> {
         }
     }
 
-    pub(crate) fn with_run(symbol: Symbol<T>, run: u32) -> Self {
+    pub(crate) fn with_run(symbol: Symbol<T>, run: u64) -> Self {
         Self {
             symbol,
             run,
@@ -1,3 +1,5 @@
+use crate::builder::GrammarConfig;
+use crate::error::SequiturError;
 use crate::grammar::Grammar;
 use crate::symbol::{Symbol, SymbolNode};
 use ahash::AHashMap as HashMap;
@@ -10,6 +12,7 @@ use std::hash::Hash;
 /// while enforcing two constraints:
 /// 1. Digram Uniqueness: No digram appears more than once
 /// 2. Rule Utility: Every rule is used at least twice
+#[derive(Clone)]
 pub struct Sequitur<T> {
     /// Core grammar storage (shared implementation with SequiturDocuments)
     pub(crate) grammar: Grammar<T>,
@@ -18,7 +21,7 @@ pub struct Sequitur<T> {
     pub(crate) sequence_end: DefaultKey,
 
     /// Number of values added
-    length: usize,
+    pub(crate) length: usize,
 }
 
 impl<T: Hash + Eq + Clone> Sequitur<T> {
@@ -26,7 +29,12 @@ impl<T: Hash + Eq + Clone> Sequitur<T> {
     ///
     /// Initializes with Rule 0 (the main sequence).
     pub fn new() -> Self {
-        let mut grammar = Grammar::new();
+        Self::with_config(GrammarConfig::default())
+    }
+
+    /// Creates a new empty Sequitur instance with the given constraints.
+    pub(crate) fn with_config(config: GrammarConfig) -> Self {
+        let mut grammar = Grammar::with_config(config);
 
         // Create Rule 0 (main sequence)
         let rule_id = grammar.id_gen.get();
@@ -98,6 +106,27 @@ impl<T: Hash + Eq + Clone> Sequitur<T> {
         }
     }
 
+    /// Adds a value to the sequence, or returns an error instead of running out
+    /// of rule IDs or overflowing the length.
+    ///
+    /// The grammar is unchanged when an error is returned.
+    pub fn try_push(&mut self, value: T) -> Result<(), SequiturError> {
+        self.grammar.check_capacity(self.length, 1)?;
+        self.push(value);
+        Ok(())
+    }
+
+    /// Extends the sequence with multiple values, stopping at the first value
+    /// that [`try_push`](Self::try_push) rejects.
+    ///
+    /// Values before the rejected one remain in the sequence.
+    pub fn try_extend<I: IntoIterator<Item = T>>(&mut self, iter: I) -> Result<(), SequiturError> {
+        for value in iter {
+            self.try_push(value)?;
+        }
+        Ok(())
+    }
+
     /// Returns the number of values added to the sequence.
     pub fn len(&self) -> usize {
         self.length
@@ -169,6 +198,7 @@ impl<T: Hash + Eq + Clone> Default for Sequitur<T> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::id_gen::IdGenerator;
 
     #[test]
     fn test_new() {
@@ -227,4 +257,43 @@ mod tests {
         assert!(matches!(tail_node.symbol, Symbol::RuleTail));
         assert_eq!(tail_key, seq.sequence_end);
     }
+
+    #[test]
+    fn test_try_push() {
+        let mut seq = Sequitur::new();
+        seq.try_extend("abcabc".chars()).unwrap();
+        assert_eq!(seq.iter().collect::<String>(), "abcabc");
+
+        seq.length = usize::MAX;
+        assert_eq!(seq.try_push('d'), Err(SequiturError::CapacityExceeded));
+    }
+
+    #[test]
+    fn test_try_push_rule_ids_exhausted() {
+        let mut seq = Sequitur::new();
+        seq.grammar.id_gen = IdGenerator::starting_at(u32::MAX - 10);
+        assert_eq!(seq.try_push('a'), Err(SequiturError::RuleIdsExhausted));
+        assert!(seq.is_empty());
+    }
+
+    #[test]
+    fn test_run_digram_reindexed_when_rule_created() {
+        // "2 1" becomes a rule and takes the indexed "1 1" of the run with it;
+        // the remaining "1 1" must still be found by the trailing "1 1".
+        let input = [2, 1, 1, 1, 2, 0, 2, 0, 2, 1, 0, 1, 1];
+        let mut seq = Sequitur::new();
+        seq.extend(input);
+        assert!(seq.iter().eq(input.iter()));
+
+        let head = seq.rules()[&0];
+        let keys: Vec<_> = seq.grammar.body_keys(head).collect();
+        let ones = keys
+            .windows(2)
+            .filter(|pair| {
+                pair.iter()
+                    .all(|&key| matches!(seq.grammar.symbols[key].symbol, Symbol::Value(1)))
+            })
+            .count();
+        assert!(ones <= 1, "\"1 1\" should have become a rule");
+    }
 }
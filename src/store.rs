@@ -0,0 +1,682 @@
+use crate::documents::SequiturDocuments;
+use crate::encoding::{corrupt, u32_at, Reader};
+use crate::error::SequiturError;
+use crate::frozen::{validate_rules, FrozenRules};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+/// Log of the operations since the last checkpoint.
+const LOG_FILE: &str = "log";
+
+/// Latest checkpoint of the shared grammar.
+const CHECKPOINT_FILE: &str = "checkpoint";
+
+/// Checkpoint being written, renamed over [`CHECKPOINT_FILE`] once complete.
+const CHECKPOINT_TMP_FILE: &str = "checkpoint.tmp";
+
+/// Identifies checkpoint files.
+const MAGIC: &[u8; 4] = b"SQCP";
+
+/// Version of the checkpoint layout.
+const VERSION: u32 = 1;
+
+/// Log records after which a checkpoint is taken by default.
+const DEFAULT_CHECKPOINT_INTERVAL: usize = 1024;
+
+/// Log record storing a value under a key.
+const PUT: u8 = 1;
+
+/// Log record deleting a key.
+const DELETE: u8 = 2;
+
+/// Bytes before the payload of a log record: its length and checksum.
+const RECORD_HEADER_LEN: usize = 12;
+
+/// A durable, compressed key-value store for byte strings.
+///
+/// Values are documents of a [`SequiturDocuments`] keyed by string, so
+/// content repeated within and across values is stored once in the shared
+/// grammar. Every change is appended to a log in the store's directory and
+/// synced before it is applied; every
+/// [`checkpoint_interval`](Self::set_checkpoint_interval) records, the
+/// grammar is written to a checkpoint file and the log starts over.
+///
+/// Opening a store loads the last checkpoint and replays the log on top of
+/// it. A record cut short or garbled by a crash, and anything after it, is
+/// dropped, so the store recovers every change that [`put`](Self::put) or
+/// [`delete`](Self::delete) returned for. Replaying is idempotent, so a crash
+/// between writing a checkpoint and clearing the log loses nothing either.
+///
+/// The store assumes it is the only process using its directory.
+///
+/// # Example
+///
+/// ```
+/// use sequitur_rs::BlobStore;
+///
+/// let dir = std::env::temp_dir().join(format!("blob-store-doc-{}", std::process::id()));
+/// # let _ = std::fs::remove_dir_all(&dir);
+/// {
+///     let mut store = BlobStore::open(&dir).unwrap();
+///     store.put("a", b"hello hello hello").unwrap();
+///     store.put("b", b"hello world").unwrap();
+///     store.delete("a").unwrap();
+/// }
+///
+/// let store = BlobStore::open(&dir).unwrap();
+/// assert_eq!(store.list(), ["b"]);
+/// assert_eq!(store.get("b"), Some(b"hello world".to_vec()));
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+pub struct BlobStore {
+    docs: SequiturDocuments<u8, String>,
+    dir: PathBuf,
+    /// Log opened for appending
+    log: File,
+    /// Records in the log since the last checkpoint
+    logged: usize,
+    /// Whether values were replaced or deleted since the grammar was last
+    /// restored
+    detached: bool,
+    checkpoint_interval: usize,
+}
+
+impl BlobStore {
+    /// Opens the store in `dir`, creating the directory if needed, and
+    /// recovers its contents.
+    ///
+    /// Returns an error of kind [`io::ErrorKind::InvalidData`] if the
+    /// checkpoint is corrupt.
+    pub fn open(dir: impl AsRef<Path>) -> io::Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        // A checkpoint that was never renamed into place is incomplete.
+        match fs::remove_file(dir.join(CHECKPOINT_TMP_FILE)) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+            _ => {}
+        }
+
+        let mut docs = match fs::read(dir.join(CHECKPOINT_FILE)) {
+            Ok(bytes) => read_checkpoint(&bytes).map_err(invalid_data)?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => SequiturDocuments::new(),
+            Err(err) => return Err(err),
+        };
+
+        let mut log = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(dir.join(LOG_FILE))?;
+        let mut bytes = Vec::new();
+        log.read_to_end(&mut bytes)?;
+        let (records, valid_len) = parse_log(&bytes);
+        // As after a put or delete, restoring the grammar is left to the next
+        // checkpoint.
+        let mut detached = false;
+        for (op, key, value) in &records {
+            detached |= apply(&mut docs, *op, key, value);
+        }
+        if valid_len < bytes.len() {
+            log.set_len(valid_len as u64)?;
+            log.sync_all()?;
+        }
+
+        Ok(Self {
+            docs,
+            dir,
+            log,
+            logged: records.len(),
+            detached,
+            checkpoint_interval: DEFAULT_CHECKPOINT_INTERVAL,
+        })
+    }
+
+    /// Sets how many logged changes trigger a checkpoint; `0` disables
+    /// automatic checkpoints.
+    pub fn set_checkpoint_interval(&mut self, records: usize) {
+        self.checkpoint_interval = records;
+    }
+
+    /// Stores `value` under `key`, replacing any previous value.
+    ///
+    /// A replaced value is deleted along with the rules only it used. Rules it
+    /// leaves with a single use are inlined at the next
+    /// [`checkpoint`](Self::checkpoint), so this takes time proportional to
+    /// the old and new values rather than to everything stored.
+    pub fn put(&mut self, key: impl Into<String>, value: &[u8]) -> io::Result<()> {
+        let key = key.into();
+        self.log_record(PUT, &key, value)?;
+        self.detached |= apply(&mut self.docs, PUT, &key, value);
+        self.maybe_checkpoint()
+    }
+
+    /// Returns the value stored under `key`, or `None` if there is none.
+    pub fn get(&self, key: &str) -> Option<Vec<u8>> {
+        let iter = self.docs.iter_document(&key.to_owned())?;
+        Some(iter.copied().collect())
+    }
+
+    /// Deletes the value stored under `key`, returning whether there was one.
+    ///
+    /// Like a replaced value, the value is deleted along with the rules only
+    /// it used, and the rest of the grammar is left for the next checkpoint.
+    pub fn delete(&mut self, key: &str) -> io::Result<bool> {
+        if self.docs.document_len(&key.to_owned()).is_none() {
+            return Ok(false);
+        }
+        self.log_record(DELETE, key, &[])?;
+        apply(&mut self.docs, DELETE, key, &[]);
+        self.detached = true;
+        self.maybe_checkpoint()?;
+        Ok(true)
+    }
+
+    /// Returns every key, in ascending order.
+    pub fn list(&self) -> Vec<&str> {
+        let mut keys: Vec<_> = self.docs.document_ids().map(String::as_str).collect();
+        keys.sort_unstable();
+        keys
+    }
+
+    /// Returns the number of stored values.
+    pub fn len(&self) -> usize {
+        self.docs.num_documents()
+    }
+
+    /// Returns true if no values are stored.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the documents holding the values, for statistics and
+    /// inspection.
+    pub fn documents(&self) -> &SequiturDocuments<u8, String> {
+        &self.docs
+    }
+
+    /// Writes the grammar to a new checkpoint and clears the log.
+    ///
+    /// If values were replaced or deleted since the last checkpoint, the
+    /// grammar is restored first, which takes time proportional to everything
+    /// stored, like writing it. The checkpoint is written to a temporary file,
+    /// synced and renamed into place, so a crash leaves either the old or the
+    /// new checkpoint.
+    pub fn checkpoint(&mut self) -> io::Result<()> {
+        if self.detached {
+            self.docs.restore_constraints();
+            self.detached = false;
+        }
+        let tmp = self.dir.join(CHECKPOINT_TMP_FILE);
+        let mut file = File::create(&tmp)?;
+        file.write_all(&write_checkpoint(&self.docs))?;
+        file.sync_all()?;
+        fs::rename(&tmp, self.dir.join(CHECKPOINT_FILE))?;
+        // Not every platform can open a directory to sync the rename.
+        if let Ok(dir) = File::open(&self.dir) {
+            let _ = dir.sync_all();
+        }
+
+        self.log.set_len(0)?;
+        self.log.sync_all()?;
+        self.logged = 0;
+        Ok(())
+    }
+
+    fn log_record(&mut self, op: u8, key: &str, value: &[u8]) -> io::Result<()> {
+        let key_len = u32::try_from(key.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "key is too long"))?;
+        let mut payload = Vec::with_capacity(5 + key.len() + value.len());
+        payload.push(op);
+        payload.extend_from_slice(&key_len.to_le_bytes());
+        payload.extend_from_slice(key.as_bytes());
+        payload.extend_from_slice(value);
+        let payload_len = u32::try_from(payload.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "value is too long"))?;
+
+        let mut record = Vec::with_capacity(RECORD_HEADER_LEN + payload.len());
+        record.extend_from_slice(&payload_len.to_le_bytes());
+        record.extend_from_slice(&checksum(&payload).to_le_bytes());
+        record.extend_from_slice(&payload);
+        let len = self.log.metadata()?.len();
+        if let Err(err) = self
+            .log
+            .write_all(&record)
+            .and_then(|()| self.log.sync_data())
+        {
+            // Replay stops at a partial record, so it would hide every later
+            // one.
+            self.truncate_log(len)?;
+            return Err(err);
+        }
+        self.logged += 1;
+        Ok(())
+    }
+
+    /// Cuts the log back to `len` bytes, dropping a partially written record.
+    fn truncate_log(&mut self, len: u64) -> io::Result<()> {
+        self.log.set_len(len)?;
+        self.log.sync_all()
+    }
+
+    fn maybe_checkpoint(&mut self) -> io::Result<()> {
+        if self.checkpoint_interval > 0 && self.logged >= self.checkpoint_interval {
+            self.checkpoint()?;
+        }
+        Ok(())
+    }
+}
+
+/// Applies a logged change, returning whether it replaced or deleted a value.
+///
+/// A replaced or deleted value may leave rules with a single use, until the
+/// grammar is restored with [`SequiturDocuments::restore_constraints`], which
+/// several changes can share.
+fn apply(docs: &mut SequiturDocuments<u8, String>, op: u8, key: &str, value: &[u8]) -> bool {
+    let key = key.to_owned();
+    let detached = docs.detach_document(&key).is_some();
+    if op == PUT {
+        // Empty values are documents too.
+        docs.create_document(key.clone());
+        docs.extend_document(key, value.iter().copied());
+    }
+    detached
+}
+
+/// A logged change: operation, key and value.
+type Record<'a> = (u8, String, &'a [u8]);
+
+/// Splits a log into its complete, intact records, returning them with the
+/// length of the prefix they occupy.
+fn parse_log(bytes: &[u8]) -> (Vec<Record<'_>>, usize) {
+    let mut records = Vec::new();
+    let mut pos = 0;
+    while let Some((record, len)) = parse_record(&bytes[pos..]) {
+        records.push(record);
+        pos += len;
+    }
+    (records, pos)
+}
+
+fn parse_record(bytes: &[u8]) -> Option<(Record<'_>, usize)> {
+    let mut reader = Reader::new(bytes);
+    let payload_len = reader.u32().ok()? as usize;
+    let expected = u64::from_le_bytes(reader.take(8).ok()?.try_into().ok()?);
+    let payload = reader.take(payload_len).ok()?;
+    if checksum(payload) != expected {
+        return None;
+    }
+
+    let mut reader = Reader::new(payload);
+    let op = reader.take(1).ok()?[0];
+    let key_len = reader.u32().ok()? as usize;
+    let key = String::from_utf8(reader.take(key_len).ok()?.to_vec()).ok()?;
+    if op != PUT && op != DELETE {
+        return None;
+    }
+    Some(((op, key, reader.rest()), RECORD_HEADER_LEN + payload_len))
+}
+
+/// Serializes the documents: a header, the keys, then the flattened grammar
+/// with the documents as its first rules, followed by a checksum.
+fn write_checkpoint(docs: &SequiturDocuments<u8, String>) -> Vec<u8> {
+    let (keys, heads): (Vec<_>, Vec<_>) = docs
+        .documents
+        .iter()
+        .map(|(key, info)| (key, info.head))
+        .unzip();
+    let rules = docs.grammar.flatten(&heads);
+
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(MAGIC);
+    bytes.extend_from_slice(&VERSION.to_le_bytes());
+    for count in [
+        keys.len(),
+        rules.values.len(),
+        rules.codes.len(),
+        rules.lengths.len(),
+    ] {
+        bytes.extend_from_slice(&(count as u64).to_le_bytes());
+    }
+    for key in keys {
+        bytes.extend_from_slice(&(key.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(key.as_bytes());
+    }
+    bytes.extend_from_slice(&rules.values);
+    for code in &rules.codes {
+        bytes.extend_from_slice(&code.to_le_bytes());
+    }
+    for &offset in &rules.offsets {
+        bytes.extend_from_slice(&(offset as u64).to_le_bytes());
+    }
+    let sum = checksum(&bytes);
+    bytes.extend_from_slice(&sum.to_le_bytes());
+    bytes
+}
+
+/// Rebuilds the documents written by [`write_checkpoint`].
+fn read_checkpoint(bytes: &[u8]) -> Result<SequiturDocuments<u8, String>, SequiturError> {
+    let Some(body_len) = bytes.len().checked_sub(8) else {
+        return Err(corrupt("checkpoint is truncated"));
+    };
+    let (body, sum) = bytes.split_at(body_len);
+    if checksum(body).to_le_bytes() != sum {
+        return Err(corrupt("checkpoint checksum mismatch"));
+    }
+
+    let mut reader = Reader::new(body);
+    if reader.take(4)? != MAGIC {
+        return Err(corrupt("not a checkpoint"));
+    }
+    let version = reader.u32()?;
+    if version != VERSION {
+        return Err(corrupt(format!("unsupported version {version}")));
+    }
+    let num_docs = reader.usize()?;
+    let num_values = reader.usize()?;
+    let num_codes = reader.usize()?;
+    let num_rules = reader.usize()?;
+    if num_docs > num_rules || num_rules >= u32::MAX as usize {
+        return Err(corrupt("invalid rule count"));
+    }
+
+    let mut docs = SequiturDocuments::new();
+    // Document `i` is rule `i` of the flattened grammar.
+    let mut keys = Vec::with_capacity(num_docs);
+    let mut tails = Vec::with_capacity(num_docs);
+    for _ in 0..num_docs {
+        let key_len = reader.u32()? as usize;
+        let key = String::from_utf8(reader.take(key_len)?.to_vec())
+            .map_err(|_| corrupt("key is not UTF-8"))?;
+        if docs.documents.contains_key(&key) {
+            return Err(corrupt(format!("duplicate key {key:?}")));
+        }
+        docs.create_document(key.clone());
+        tails.push(docs.documents[&key].tail);
+        keys.push(key);
+    }
+
+    let values = reader.take(num_values)?.to_vec();
+    let code_table = reader.take_table(num_codes, 4)?;
+    let codes: Vec<u32> = (0..num_codes).map(|i| u32_at(code_table, i)).collect();
+    let offsets = (0..=num_rules)
+        .map(|_| reader.usize())
+        .collect::<Result<Vec<_>, _>>()?;
+    if !reader.rest().is_empty() {
+        return Err(corrupt("trailing bytes"));
+    }
+    validate_rules(|i| codes[i], num_codes, &offsets, num_docs, num_values)?;
+    let rules = FrozenRules::new(values, codes, offsets, num_docs)
+        .ok_or_else(|| corrupt("document length overflows usize"))?;
+    docs.grammar.unflatten(&rules, &tails);
+    for (key, &length) in keys.iter().zip(&rules.lengths) {
+        if let Some(info) = docs.documents.get_mut(key) {
+            info.length = length;
+        }
+    }
+    docs.restore_constraints();
+    Ok(docs)
+}
+
+/// FNV-1a hash guarding log records and checkpoints against torn writes.
+fn checksum(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+fn invalid_data(err: SequiturError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::symbol::Symbol;
+
+    /// A fresh directory for one test.
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("blob-store-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn fill(store: &mut BlobStore) {
+        store
+            .put("index.html", b"<html><body>hello</body></html>")
+            .unwrap();
+        store
+            .put("about.html", b"<html><body>about</body></html>")
+            .unwrap();
+        store.put("empty", b"").unwrap();
+        store
+            .put("index.html", b"<html><body>hello again</body></html>")
+            .unwrap();
+        store.put("tmp", b"scratch").unwrap();
+        assert!(store.delete("tmp").unwrap());
+        assert!(!store.delete("missing").unwrap());
+    }
+
+    fn assert_filled(store: &BlobStore) {
+        assert_eq!(store.list(), ["about.html", "empty", "index.html"]);
+        assert_eq!(
+            store.get("index.html").unwrap(),
+            b"<html><body>hello again</body></html>"
+        );
+        assert_eq!(
+            store.get("about.html").unwrap(),
+            b"<html><body>about</body></html>"
+        );
+        assert_eq!(store.get("empty").unwrap(), b"");
+        assert_eq!(store.get("tmp"), None);
+        // Rule counts match the remaining values, however they were replayed.
+        assert!(store.docs.try_iter_document(&"empty".into()).is_ok());
+    }
+
+    #[test]
+    fn test_recover_from_log() {
+        let dir = test_dir("log");
+        let mut store = BlobStore::open(&dir).unwrap();
+        fill(&mut store);
+        assert_filled(&store);
+        drop(store);
+
+        let store = BlobStore::open(&dir).unwrap();
+        assert_filled(&store);
+        assert_eq!(store.logged, 6);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_recover_from_checkpoint_and_log() {
+        let dir = test_dir("checkpoint");
+        let mut store = BlobStore::open(&dir).unwrap();
+        store.set_checkpoint_interval(4);
+        fill(&mut store);
+        assert_eq!(store.logged, 2);
+        drop(store);
+
+        let mut store = BlobStore::open(&dir).unwrap();
+        assert_filled(&store);
+        store.checkpoint().unwrap();
+        assert_eq!(fs::metadata(dir.join(LOG_FILE)).unwrap().len(), 0);
+        drop(store);
+
+        let store = BlobStore::open(&dir).unwrap();
+        assert_filled(&store);
+        assert!(store.documents().try_iter_document(&"empty".into()).is_ok());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_torn_record_is_dropped() {
+        let dir = test_dir("torn");
+        let mut store = BlobStore::open(&dir).unwrap();
+        fill(&mut store);
+        store.put("last", b"written before the crash").unwrap();
+        drop(store);
+
+        // The crash cut the last record short.
+        let log = dir.join(LOG_FILE);
+        let len = fs::metadata(&log).unwrap().len();
+        OpenOptions::new()
+            .write(true)
+            .open(&log)
+            .unwrap()
+            .set_len(len - 5)
+            .unwrap();
+
+        let mut store = BlobStore::open(&dir).unwrap();
+        assert_filled(&store);
+        store.put("next", b"after recovery").unwrap();
+        drop(store);
+
+        let store = BlobStore::open(&dir).unwrap();
+        assert_eq!(store.get("next").unwrap(), b"after recovery");
+        assert_eq!(store.get("last"), None);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_failed_write_is_rolled_back() {
+        let dir = test_dir("rollback");
+        let mut store = BlobStore::open(&dir).unwrap();
+        fill(&mut store);
+
+        // A write that failed partway left part of a record behind.
+        let log = dir.join(LOG_FILE);
+        let len = fs::metadata(&log).unwrap().len();
+        let mut file = OpenOptions::new().append(true).open(&log).unwrap();
+        file.write_all(&[0xff; 7]).unwrap();
+        drop(file);
+        store.truncate_log(len).unwrap();
+
+        store.put("next", b"after the failed write").unwrap();
+        drop(store);
+
+        let store = BlobStore::open(&dir).unwrap();
+        assert_eq!(store.list(), ["about.html", "empty", "index.html", "next"]);
+        assert_eq!(store.get("next").unwrap(), b"after the failed write");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_garbled_record_stops_replay() {
+        let dir = test_dir("garbled");
+        let mut store = BlobStore::open(&dir).unwrap();
+        fill(&mut store);
+        let before = fs::metadata(dir.join(LOG_FILE)).unwrap().len() as usize;
+        store.put("x", b"one").unwrap();
+        store.put("y", b"two").unwrap();
+        drop(store);
+
+        let mut bytes = fs::read(dir.join(LOG_FILE)).unwrap();
+        *bytes.last_mut().unwrap() ^= 0xff;
+        bytes[before + RECORD_HEADER_LEN] ^= 0xff;
+        fs::write(dir.join(LOG_FILE), &bytes).unwrap();
+
+        let store = BlobStore::open(&dir).unwrap();
+        assert_filled(&store);
+        assert_eq!(
+            fs::metadata(dir.join(LOG_FILE)).unwrap().len() as usize,
+            before
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_crash_during_checkpoint() {
+        let dir = test_dir("crash");
+        let mut store = BlobStore::open(&dir).unwrap();
+        fill(&mut store);
+        let log = fs::read(dir.join(LOG_FILE)).unwrap();
+
+        // Crash after the checkpoint was renamed but before the log was
+        // cleared: the whole log is replayed over the new checkpoint.
+        store.checkpoint().unwrap();
+        drop(store);
+        fs::write(dir.join(LOG_FILE), &log).unwrap();
+        // A later checkpoint was still being written.
+        fs::write(dir.join(CHECKPOINT_TMP_FILE), b"SQCP partial").unwrap();
+
+        let store = BlobStore::open(&dir).unwrap();
+        assert_filled(&store);
+        assert!(!dir.join(CHECKPOINT_TMP_FILE).exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_corrupt_checkpoint_is_an_error() {
+        let dir = test_dir("corrupt");
+        let mut store = BlobStore::open(&dir).unwrap();
+        fill(&mut store);
+        store.checkpoint().unwrap();
+        drop(store);
+
+        let mut bytes = fs::read(dir.join(CHECKPOINT_FILE)).unwrap();
+        bytes[20] ^= 1;
+        fs::write(dir.join(CHECKPOINT_FILE), &bytes).unwrap();
+        let err = BlobStore::open(&dir).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_replacing_defers_restore_to_checkpoint() {
+        let dir = test_dir("replace");
+        let page = |i: usize, round: usize| format!("<p>shared page body</p><p>{i}/{round}</p>");
+        let mut store = BlobStore::open(&dir).unwrap();
+        store.set_checkpoint_interval(0);
+        for round in 0..3 {
+            for i in 0..10 {
+                store.put(i.to_string(), page(i, round).as_bytes()).unwrap();
+            }
+            assert!(store.delete(&(3 * round).to_string()).unwrap());
+        }
+        assert!(store.detached);
+        assert!(store.docs.try_iter_document(&"1".into()).is_ok());
+        for i in (0..10).filter(|&i| i != 6) {
+            assert_eq!(store.get(&i.to_string()).unwrap(), page(i, 2).into_bytes());
+        }
+        assert_eq!(store.get("6"), None);
+
+        store.checkpoint().unwrap();
+        assert!(!store.detached);
+        for &head in store.docs.rules().values() {
+            let Symbol::RuleHead { count, .. } = store.docs.grammar.symbols[head].symbol else {
+                unreachable!();
+            };
+            assert!(count >= 2);
+        }
+        drop(store);
+
+        let store = BlobStore::open(&dir).unwrap();
+        assert_eq!(store.len(), 9);
+        assert_eq!(store.get("9").unwrap(), page(9, 2).into_bytes());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_checkpoint_shares_content() {
+        let page = |i: usize| format!("<html><head>shared header</head><body>{i}</body></html>");
+        let mut docs = SequiturDocuments::new();
+        for i in 0..50 {
+            docs.extend_document(i.to_string(), page(i).into_bytes());
+        }
+
+        let bytes = write_checkpoint(&docs);
+        let total: usize = (0..50).map(|i| page(i).len()).sum();
+        assert!(bytes.len() < total);
+
+        let read = read_checkpoint(&bytes).unwrap();
+        for i in 0..50 {
+            let value: Vec<u8> = read
+                .iter_document(&i.to_string())
+                .unwrap()
+                .copied()
+                .collect();
+            assert_eq!(value, page(i).into_bytes());
+        }
+        assert_eq!(read.rules().len(), docs.rules().len());
+    }
+}
@@ -33,7 +33,7 @@ pub(crate) enum Symbol<T> {
 /// A node in the doubly-linked list of symbols.
 ///
 /// Replaces C++'s intrusive linked list with safe SlotMap-based indices.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub(crate) struct SymbolNode<T> {
     pub symbol: Symbol<T>,
     pub prev: Option<DefaultKey>,
@@ -1,6 +1,10 @@
+use crate::documents::SequiturDocuments;
+use crate::merge::DocConflict;
+use crate::repair::Algorithm;
 use crate::sequitur::Sequitur;
-use crate::symbol::Symbol;
+use crate::symbol::{Symbol, SymbolHash};
 use proptest::prelude::*;
+use std::collections::HashMap;
 
 /// Extracts all digrams from the entire grammar for testing digram uniqueness.
 #[allow(dead_code)]
@@ -56,6 +60,82 @@ fn get_rule_count<T>(seq: &Sequitur<T>, head_key: slotmap::DefaultKey) -> u32 {
     }
 }
 
+/// Checks the constraints of a grammar built with `min_rule_uses(k)` and
+/// `min_rule_length(n)`: rule counts match the actual uses and are at least `k`,
+/// every rule expands to at least `n` values, and every digram occurrence is
+/// tracked by the digram index.
+fn check_relaxed_invariants<T: Clone + Eq + std::hash::Hash>(
+    seq: &Sequitur<T>,
+    min_uses: u32,
+    min_length: usize,
+) -> Result<(), TestCaseError> {
+    let grammar = &seq.grammar;
+    let refs = grammar.rule_refs(&[]);
+    let lengths = grammar.rule_lengths();
+
+    for (&rule_id, &head_key) in seq.rules() {
+        if rule_id == 0 {
+            continue;
+        }
+        let count = get_rule_count(seq, head_key);
+        prop_assert_eq!(count as usize, refs.get(&rule_id).map_or(0, Vec::len));
+        prop_assert!(count >= min_uses);
+        prop_assert!(lengths[&rule_id] >= min_length);
+    }
+
+    for &head_key in seq.rules().values() {
+        let keys: Vec<_> = grammar.body_keys(head_key).collect();
+        for pair in keys.windows(2) {
+            let digram = (
+                SymbolHash::from_symbol(&grammar.symbols[pair[0]].symbol),
+                SymbolHash::from_symbol(&grammar.symbols[pair[1]].symbol),
+            );
+            let tracked = grammar.digram_index.get(&digram) == Some(&pair[0])
+                || grammar
+                    .pending_digrams
+                    .get(&digram)
+                    .is_some_and(|pending| pending.contains(&pair[0]));
+            prop_assert!(tracked, "untracked digram occurrence");
+        }
+    }
+    Ok(())
+}
+
+/// Checks that no digram occurs `min_uses` times without overlapping in a
+/// grammar built by pushing with `min_rule_uses(min_uses)`, since such a repeat
+/// should have become a rule.
+fn check_repeats_are_rules<T: Clone + Eq + std::hash::Hash>(
+    seq: &Sequitur<T>,
+    min_uses: u32,
+) -> Result<(), TestCaseError> {
+    let grammar = &seq.grammar;
+    // Second key of each counted occurrence, by digram
+    let mut occurrences: HashMap<(SymbolHash, SymbolHash), Vec<slotmap::DefaultKey>> =
+        HashMap::new();
+    for &head_key in seq.rules().values() {
+        let keys: Vec<_> = grammar.body_keys(head_key).collect();
+        for pair in keys.windows(2) {
+            let digram = (
+                SymbolHash::from_symbol(&grammar.symbols[pair[0]].symbol),
+                SymbolHash::from_symbol(&grammar.symbols[pair[1]].symbol),
+            );
+            let seconds = occurrences.entry(digram).or_default();
+            // An occurrence overlapping the previous one, as in `aaa`, doesn't count.
+            if seconds.last() != Some(&pair[0]) {
+                seconds.push(pair[1]);
+            }
+        }
+    }
+    for seconds in occurrences.values() {
+        prop_assert!(
+            seconds.len() < min_uses as usize,
+            "digram repeated {} times without becoming a rule",
+            seconds.len()
+        );
+    }
+    Ok(())
+}
+
 proptest! {
     /// Property 1: Roundtrip fidelity
     /// The reconstructed sequence must exactly match the input.
@@ -153,6 +233,412 @@ proptest! {
 
         prop_assert_eq!(result1, result2);
     }
+
+    /// Property 7: Re-Pair roundtrip and rule utility
+    /// The offline grammar reconstructs the input and keeps every rule used twice.
+    #[test]
+    fn prop_repair_roundtrip(input in prop::collection::vec(0u8..4, 0..300)) {
+        let seq = Sequitur::repair(input.clone());
+
+        let reconstructed: Vec<u8> = seq.iter().copied().collect();
+        prop_assert_eq!(reconstructed, input.clone());
+        prop_assert_eq!(seq.len(), input.len());
+
+        for (&rule_id, &head_key) in seq.rules() {
+            if rule_id != 0 {
+                prop_assert!(get_rule_count(&seq, head_key) >= 2);
+            }
+        }
+    }
+
+    /// Property 8: Pushing after Re-Pair
+    /// A Re-Pair grammar can be extended online like any other.
+    #[test]
+    fn prop_repair_then_push(
+        prefix in prop::collection::vec(0u8..4, 0..100),
+        suffix in prop::collection::vec(0u8..4, 0..100),
+    ) {
+        let mut seq = Sequitur::repair(prefix.clone());
+        seq.extend(suffix.clone());
+
+        let expected: Vec<u8> = prefix.into_iter().chain(suffix).collect();
+        let reconstructed: Vec<u8> = seq.iter().copied().collect();
+        prop_assert_eq!(reconstructed, expected);
+    }
+
+    /// Property 9: Optimization preserves the sequence and never grows the grammar
+    /// The grammar can still be extended afterwards.
+    #[test]
+    fn prop_optimize(
+        prefix in prop::collection::vec(0u8..4, 0..300),
+        suffix in prop::collection::vec(0u8..4, 0..100),
+    ) {
+        let mut seq = Sequitur::new();
+        seq.extend(prefix.clone());
+
+        let report = seq.optimize();
+        prop_assert!(report.cost_after <= report.cost_before);
+        let reconstructed: Vec<u8> = seq.iter().copied().collect();
+        prop_assert_eq!(reconstructed, prefix.clone());
+
+        for (&rule_id, &head_key) in seq.rules() {
+            if rule_id != 0 {
+                prop_assert!(get_rule_count(&seq, head_key) >= 2);
+            }
+        }
+
+        seq.extend(suffix.clone());
+        let expected: Vec<u8> = prefix.into_iter().chain(suffix).collect();
+        let reconstructed: Vec<u8> = seq.iter().copied().collect();
+        prop_assert_eq!(reconstructed, expected);
+    }
+
+    /// Property 10: Minimum rule utility
+    /// With `min_rule_uses(k)`, every rule is used at least `k` times and the
+    /// sequence still roundtrips, including after optimization.
+    #[test]
+    fn prop_min_rule_uses(
+        min_uses in 3u32..=4,
+        prefix in prop::collection::vec(0u8..4, 0..300),
+        suffix in prop::collection::vec(0u8..4, 0..100),
+    ) {
+        let mut seq = Sequitur::builder().min_rule_uses(min_uses).build();
+        seq.extend(prefix.clone());
+
+        let reconstructed: Vec<u8> = seq.iter().copied().collect();
+        prop_assert_eq!(reconstructed, prefix.clone());
+        check_relaxed_invariants(&seq, min_uses, 2)?;
+        check_repeats_are_rules(&seq, min_uses)?;
+
+        seq.optimize();
+        check_relaxed_invariants(&seq, min_uses, 2)?;
+
+        seq.extend(suffix.clone());
+        let expected: Vec<u8> = prefix.into_iter().chain(suffix).collect();
+        let reconstructed: Vec<u8> = seq.iter().copied().collect();
+        prop_assert_eq!(reconstructed, expected);
+        check_relaxed_invariants(&seq, min_uses, 2)?;
+    }
+
+    /// Property 11: Minimum rule length
+    /// With `min_rule_length(n)`, every rule expands to at least `n` values.
+    #[test]
+    fn prop_min_rule_length(
+        min_length in 3usize..=6,
+        min_uses in 2u32..=3,
+        input in prop::collection::vec(0u8..3, 0..300),
+    ) {
+        let mut seq = Sequitur::builder()
+            .min_rule_uses(min_uses)
+            .min_rule_length(min_length)
+            .build();
+        seq.extend(input.clone());
+
+        let reconstructed: Vec<u8> = seq.iter().copied().collect();
+        prop_assert_eq!(reconstructed, input);
+        check_relaxed_invariants(&seq, min_uses, min_length)?;
+    }
+
+    /// Property 12: Parallel construction
+    /// Merged chunk grammars reconstruct the input and satisfy both constraints.
+    #[test]
+    fn prop_build_parallel(
+        input in prop::collection::vec(0u8..4, 0..400),
+        threads in 1usize..6,
+    ) {
+        let seq = Sequitur::build_parallel(&input, threads);
+
+        let reconstructed: Vec<u8> = seq.iter().copied().collect();
+        prop_assert_eq!(reconstructed, input.clone());
+        prop_assert_eq!(seq.len(), input.len());
+
+        for (&rule_id, &head_key) in seq.rules() {
+            if rule_id != 0 {
+                prop_assert!(get_rule_count(&seq, head_key) >= 2);
+            }
+        }
+
+        let pairs: Vec<[slotmap::DefaultKey; 2]> = seq
+            .rules()
+            .values()
+            .flat_map(|&head| {
+                let keys: Vec<_> = seq.grammar.body_keys(head).collect();
+                keys.windows(2).map(|pair| [pair[0], pair[1]]).collect::<Vec<_>>()
+            })
+            .collect();
+        let symbol = |key| &seq.grammar.symbols[key].symbol;
+        for (i, first) in pairs.iter().enumerate() {
+            for second in &pairs[i + 1..] {
+                // Overlapping repeats such as "aaa" are the one allowed exception.
+                let overlapping = first[1] == second[0] || second[1] == first[0];
+                let equal = symbol(first[0]).equals(symbol(second[0]))
+                    && symbol(first[1]).equals(symbol(second[1]));
+                prop_assert!(!equal || overlapping);
+            }
+        }
+    }
+
+    /// Property 13: Merging document collections
+    /// Every merged document reconstructs according to the conflict resolution,
+    /// and every rule of the merged grammar is used at least twice.
+    #[test]
+    fn prop_merge_documents(
+        left in prop::collection::vec((0u8..4, prop::collection::vec(0u8..3, 0..60)), 0..6),
+        right in prop::collection::vec((0u8..4, prop::collection::vec(0u8..3, 0..60)), 0..6),
+        resolution in 0u8..4,
+    ) {
+        let build = |docs: &[(u8, Vec<u8>)]| {
+            let mut collection = SequiturDocuments::new();
+            let mut expected: HashMap<u8, Vec<u8>> = HashMap::new();
+            for (doc_id, values) in docs {
+                if values.is_empty() {
+                    continue;
+                }
+                collection.extend_document(*doc_id, values.iter().copied());
+                expected.entry(*doc_id).or_default().extend(values);
+            }
+            (collection, expected)
+        };
+        let (mut merged, mut expected) = build(&left);
+        let (other, incoming) = build(&right);
+
+        merged.merge(other, |&doc_id| match resolution {
+            0 => DocConflict::KeepExisting,
+            1 => DocConflict::Replace,
+            2 => DocConflict::Append,
+            _ => DocConflict::Rename(doc_id + 100),
+        });
+
+        for (doc_id, values) in incoming {
+            match (expected.get_mut(&doc_id), resolution) {
+                (None, _) => {
+                    expected.insert(doc_id, values);
+                }
+                (Some(_), 0) => {}
+                (Some(existing), 1) => *existing = values,
+                (Some(existing), 2) => existing.extend(values),
+                (Some(_), _) => {
+                    expected.insert(doc_id + 100, values);
+                }
+            }
+        }
+
+        prop_assert_eq!(merged.num_documents(), expected.len());
+        for (doc_id, values) in &expected {
+            let reconstructed: Vec<u8> = merged.iter_document(doc_id).unwrap().copied().collect();
+            prop_assert_eq!(&reconstructed, values);
+            prop_assert_eq!(merged.document_len(doc_id), Some(values.len()));
+        }
+        for &head_key in merged.rules().values() {
+            if let Symbol::RuleHead { count, .. } = merged.grammar.symbols[head_key].symbol {
+                prop_assert!(count >= 2);
+            }
+        }
+        if let Some(doc_id) = expected.keys().next() {
+            prop_assert!(merged.try_iter_document(doc_id).is_ok());
+        }
+
+        // Only the incoming symbols are replayed, so the digram index must
+        // still cover every digram of the receiving grammar.
+        let grammar = &merged.grammar;
+        let heads = merged
+            .rules()
+            .values()
+            .chain(merged.documents.values().map(|info| &info.head));
+        let mut occurrences: HashMap<(SymbolHash, SymbolHash), Vec<slotmap::DefaultKey>> =
+            HashMap::new();
+        for &head in heads {
+            let keys: Vec<_> = grammar.body_keys(head).collect();
+            for pair in keys.windows(2) {
+                let digram = (
+                    SymbolHash::from_symbol(&grammar.symbols[pair[0]].symbol),
+                    SymbolHash::from_symbol(&grammar.symbols[pair[1]].symbol),
+                );
+                occurrences.entry(digram).or_default().push(pair[0]);
+            }
+        }
+        for (digram, firsts) in &occurrences {
+            // Only overlapping occurrences, as in `aaa`, may repeat.
+            let overlapping = firsts.len() == 2
+                && (grammar.symbols[firsts[0]].next == Some(firsts[1])
+                    || grammar.symbols[firsts[1]].next == Some(firsts[0]));
+            prop_assert!(firsts.len() == 1 || overlapping, "repeated digram");
+            prop_assert!(
+                grammar.digram_index.get(digram).is_some_and(|key| firsts.contains(key)),
+                "unindexed digram"
+            );
+        }
+    }
+
+    /// Property 14: Double-ended iteration
+    /// Taking values from both ends in any order yields the sequence, for both
+    /// a single sequence and a document.
+    #[test]
+    fn prop_double_ended(
+        input in prop::collection::vec(0u8..4, 0..300),
+        from_back in prop::collection::vec(any::<bool>(), 0..350),
+    ) {
+        let mut seq = Sequitur::new();
+        seq.extend(input.iter().copied());
+        let mut docs = SequiturDocuments::new();
+        docs.extend_document(0, input.iter().rev().copied());
+        docs.extend_document(1, input.iter().copied());
+
+        prop_assert!(seq.iter().rev().eq(input.iter().rev()));
+        if let Some(iter) = docs.iter_document(&1) {
+            prop_assert!(iter.rev().eq(input.iter().rev()));
+        }
+
+        let mut iter = seq.iter();
+        let (mut front, mut back) = (Vec::new(), Vec::new());
+        for &take_back in &from_back {
+            let value = if take_back { iter.next_back() } else { iter.next() };
+            match (value, take_back) {
+                (Some(&value), true) => back.push(value),
+                (Some(&value), false) => front.push(value),
+                (None, _) => break,
+            }
+        }
+        front.extend(iter.copied());
+        front.extend(back.into_iter().rev());
+        prop_assert_eq!(front, input);
+    }
+
+    /// Property 15: Exact size and skipping
+    /// The reported length always matches what is left, and `nth` lands on the
+    /// same value as stepping through the sequence.
+    #[test]
+    fn prop_exact_size_and_nth(
+        input in prop::collection::vec(0u8..4, 0..400),
+        steps in prop::collection::vec((0usize..60, any::<bool>()), 0..20),
+    ) {
+        let mut seq = Sequitur::new();
+        seq.extend(input.iter().copied());
+
+        let mut iter = seq.iter();
+        let mut expected = input.iter();
+        prop_assert_eq!(iter.len(), input.len());
+        for &(n, back) in &steps {
+            if back {
+                prop_assert_eq!(iter.nth_back(n), expected.nth_back(n));
+            } else {
+                prop_assert_eq!(iter.nth(n), expected.nth(n));
+            }
+            prop_assert_eq!(iter.len(), expected.len());
+        }
+        prop_assert!(iter.eq(expected));
+    }
+
+    /// Property 16: Consistency
+    /// Every construction path leaves a grammar that passes the checks behind
+    /// `try_iter`.
+    #[test]
+    fn prop_grammars_validate(
+        input in prop::collection::vec(0u8..4, 0..300),
+        min_uses in 2u32..=3,
+    ) {
+        let mut seq = Sequitur::new();
+        seq.extend(input.iter().copied());
+        prop_assert!(seq.try_iter().is_ok());
+        seq.optimize();
+        prop_assert!(seq.try_iter().is_ok());
+
+        let mut relaxed = Sequitur::builder().min_rule_uses(min_uses).build();
+        relaxed.extend(input.iter().copied());
+        prop_assert!(relaxed.try_iter().is_ok());
+
+        let repair = Sequitur::compress(input.iter().copied(), Algorithm::RePair);
+        prop_assert!(repair.try_iter().is_ok());
+        let parallel = Sequitur::build_parallel(&input, 3);
+        prop_assert!(parallel.try_iter().is_ok());
+
+        let mut docs = SequiturDocuments::new();
+        docs.extend_document(0, input.iter().copied());
+        let mut other = SequiturDocuments::new();
+        other.extend_document(1, input.iter().rev().copied());
+        docs.merge(other, |_| DocConflict::KeepExisting);
+        for doc in 0..2 {
+            prop_assert!(docs.try_iter_document(&doc).is_ok());
+        }
+    }
+
+    /// Property 17: Compaction
+    /// Compacting leaves the sequence and grammar size unchanged, numbers the
+    /// rules densely, and later pushes behave as if it never happened.
+    #[test]
+    fn prop_compact(
+        input in prop::collection::vec(0u8..4, 0..300),
+        more in prop::collection::vec(0u8..4, 0..100),
+    ) {
+        let mut seq = Sequitur::new();
+        seq.extend(input.iter().copied());
+        let mut fresh = seq.clone();
+
+        seq.compact();
+        prop_assert!(seq.iter().eq(input.iter()));
+        prop_assert_eq!(seq.grammar.symbols.len(), fresh.grammar.symbols.len());
+        let mut ids: Vec<u32> = seq.rules().keys().copied().collect();
+        ids.sort_unstable();
+        prop_assert!(ids.iter().copied().eq(0..ids.len() as u32));
+
+        seq.extend(more.iter().copied());
+        fresh.extend(more.iter().copied());
+        prop_assert!(seq.iter().eq(input.iter().chain(&more)));
+        prop_assert_eq!(seq.rules().len(), fresh.rules().len());
+        prop_assert_eq!(seq.grammar.symbols.len(), fresh.grammar.symbols.len());
+        prop_assert!(seq.try_iter().is_ok());
+    }
+
+    /// Property 18: Merging relaxed document collections
+    /// Merging collections built with `min_rule_uses(k)`, appending to or
+    /// replacing documents, keeps every document and every rule used at least
+    /// `k` times, and the result keeps growing.
+    #[test]
+    fn prop_merge_relaxed(
+        left in prop::collection::vec((0u8..4, prop::collection::vec(0u8..3, 1..60)), 0..6),
+        right in prop::collection::vec((0u8..4, prop::collection::vec(0u8..3, 1..60)), 0..6),
+        min_uses in 2u32..5,
+        min_length in 2usize..4,
+        replace: bool,
+    ) {
+        let build = |docs: &[(u8, Vec<u8>)]| {
+            let mut collection = Sequitur::builder()
+                .min_rule_uses(min_uses)
+                .min_rule_length(min_length)
+                .build_documents();
+            let mut expected: HashMap<u8, Vec<u8>> = HashMap::new();
+            for (doc_id, values) in docs {
+                collection.extend_document(*doc_id, values.iter().copied());
+                expected.entry(*doc_id).or_default().extend(values);
+            }
+            (collection, expected)
+        };
+        let (mut merged, mut expected) = build(&left);
+        let (other, incoming) = build(&right);
+
+        merged.merge(other, |_| if replace { DocConflict::Replace } else { DocConflict::Append });
+        for (doc_id, values) in incoming {
+            if replace {
+                expected.insert(doc_id, values);
+            } else {
+                expected.entry(doc_id).or_default().extend(values);
+            }
+        }
+        for doc_id in 0..2 {
+            merged.extend_document(doc_id, [0, 1, 2, 0, 1, 2, 0, 1]);
+            expected.entry(doc_id).or_default().extend([0, 1, 2, 0, 1, 2, 0, 1]);
+        }
+
+        for (doc_id, values) in &expected {
+            prop_assert!(merged.iter_document(doc_id).unwrap().eq(values.iter()));
+            prop_assert!(merged.try_iter_document(doc_id).is_ok());
+        }
+        for &head_key in merged.rules().values() {
+            if let Symbol::RuleHead { count, .. } = merged.grammar.symbols[head_key].symbol {
+                prop_assert!(count >= min_uses);
+            }
+        }
+    }
 }
 
 /// Bolero fuzz test: No panics on arbitrary input
@@ -1,4 +1,6 @@
+use crate::rle_documents::SequiturDocumentsRle;
 use crate::rle_sequitur::SequiturRle;
+use crate::sequitur::Sequitur;
 use crate::symbol::Symbol;
 use proptest::prelude::*;
 
@@ -86,6 +88,147 @@ proptest! {
             run_len, stats.grammar_nodes
         );
     }
+
+    /// Property 6: Double-ended iteration
+    /// Taking values from both ends in any order yields the sequence, including
+    /// from the middle of runs and repeated rules.
+    #[test]
+    fn prop_rle_double_ended(
+        input in prop::collection::vec(0u8..3, 1..300),
+        from_back in prop::collection::vec(any::<bool>(), 0..350),
+    ) {
+        let mut seq = SequiturRle::new();
+        seq.extend(input.iter().copied());
+        let mut docs = SequiturDocumentsRle::new();
+        docs.extend_document(0, input.iter().rev().copied());
+        docs.extend_document(1, input.iter().copied());
+
+        prop_assert!(seq.iter().rev().eq(input.iter().rev()));
+        prop_assert!(docs.iter_document(&1).unwrap().rev().eq(input.iter().rev()));
+
+        let mut iter = docs.iter_document(&1).unwrap();
+        let (mut front, mut back) = (Vec::new(), Vec::new());
+        for &take_back in &from_back {
+            let value = if take_back { iter.next_back() } else { iter.next() };
+            match (value, take_back) {
+                (Some(&value), true) => back.push(value),
+                (Some(&value), false) => front.push(value),
+                (None, _) => break,
+            }
+        }
+        front.extend(iter.copied());
+        front.extend(back.into_iter().rev());
+        prop_assert_eq!(front, input);
+    }
+
+    /// Property 7: Exact size and skipping
+    /// `nth` lands on the same value as stepping through the sequence, even
+    /// when skipping into the middle of runs and repeated rules.
+    #[test]
+    fn prop_rle_exact_size_and_nth(
+        input in prop::collection::vec(0u8..3, 1..400),
+        steps in prop::collection::vec((0usize..60, any::<bool>()), 0..20),
+    ) {
+        let mut seq = SequiturRle::new();
+        seq.extend(input.iter().copied());
+        let mut docs = SequiturDocumentsRle::new();
+        docs.extend_document(0, input.iter().copied());
+
+        let mut iter = seq.iter();
+        let mut doc_iter = docs.iter_document(&0).unwrap();
+        let mut expected = input.iter();
+        for &(n, back) in &steps {
+            if back {
+                let value = expected.nth_back(n);
+                prop_assert_eq!(iter.nth_back(n), value);
+                prop_assert_eq!(doc_iter.nth_back(n), value);
+            } else {
+                let value = expected.nth(n);
+                prop_assert_eq!(iter.nth(n), value);
+                prop_assert_eq!(doc_iter.nth(n), value);
+            }
+            prop_assert_eq!(iter.len(), expected.len());
+            prop_assert_eq!(doc_iter.len(), expected.len());
+        }
+        prop_assert!(iter.eq(expected.clone()));
+        prop_assert!(doc_iter.eq(expected));
+    }
+
+    /// Property 8: Run iteration
+    /// Runs are maximal, and expanding them reconstructs the input.
+    #[test]
+    fn prop_rle_iter_runs(input in prop::collection::vec(0u8..3, 0..400)) {
+        let mut seq = SequiturRle::new();
+        seq.extend(input.iter().copied());
+        let mut docs = SequiturDocumentsRle::new();
+        docs.extend_document(0, input.iter().copied());
+
+        let mut expected: Vec<(u8, u64)> = Vec::new();
+        for &value in &input {
+            match expected.last_mut() {
+                Some((last, run)) if *last == value => *run += 1,
+                _ => expected.push((value, 1)),
+            }
+        }
+
+        let runs: Vec<(u8, u64)> = seq.iter_runs().map(|(&v, n)| (v, n)).collect();
+        prop_assert_eq!(&runs, &expected);
+        if let Some(doc_runs) = docs.iter_document_runs(&0) {
+            let doc_runs: Vec<(u8, u64)> = doc_runs.map(|(&v, n)| (v, n)).collect();
+            prop_assert_eq!(&doc_runs, &expected);
+        }
+    }
+
+    /// Property 9: Bulk run insertion
+    /// Pushing runs reconstructs their expansion and keeps rule utility.
+    #[test]
+    fn prop_rle_extend_runs(runs in prop::collection::vec((0u8..3, 0u64..20), 0..60)) {
+        let mut seq = SequiturRle::new();
+        seq.extend_runs(runs.iter().copied());
+
+        let expected: Vec<u8> = runs
+            .iter()
+            .flat_map(|&(value, count)| std::iter::repeat_n(value, count as usize))
+            .collect();
+        prop_assert_eq!(seq.len(), expected.len());
+        prop_assert!(seq.iter().eq(expected.iter()));
+
+        for (&rule_id, &head_key) in seq.rules() {
+            if rule_id != 0 {
+                prop_assert!(get_rule_count(&seq, head_key) >= 2);
+            }
+        }
+    }
+
+    /// Property 10: Conversion
+    /// Converting between plain and RLE grammars in either direction keeps the
+    /// sequence, rule utility and a consistent grammar.
+    #[test]
+    fn prop_rle_conversion(runs in prop::collection::vec((0u8..3, 1u64..20), 0..60)) {
+        let expected: Vec<u8> = runs
+            .iter()
+            .flat_map(|&(value, count)| std::iter::repeat_n(value, count as usize))
+            .collect();
+
+        let mut plain = Sequitur::new();
+        plain.extend(expected.iter().copied());
+        let rle = SequiturRle::from(plain);
+        prop_assert_eq!(rle.len(), expected.len());
+        prop_assert!(rle.try_iter().is_ok());
+        prop_assert!(rle.iter().eq(expected.iter()));
+        for (&rule_id, &head_key) in rle.rules() {
+            if rule_id != 0 {
+                prop_assert!(get_rule_count(&rle, head_key) >= 2);
+            }
+        }
+
+        let mut rle = SequiturRle::new();
+        rle.extend_runs(runs.iter().copied());
+        let plain = Sequitur::from(rle);
+        prop_assert_eq!(plain.len(), expected.len());
+        prop_assert!(plain.try_iter().is_ok());
+        prop_assert!(plain.iter().eq(expected.iter()));
+    }
 }
 
 /// Bolero fuzz test: No panics on arbitrary input
@@ -0,0 +1,303 @@
+use crate::interner::Interner;
+use crate::sequitur::{CompressionStats, Sequitur};
+use crate::symbol::Symbol;
+use ahash::AHashMap as HashMap;
+
+/// Word-level Sequitur front-end for natural-language text.
+///
+/// Input text is split into tokens (words, runs of whitespace and individual
+/// punctuation characters), each distinct token is interned to a `u32` ID, and
+/// the IDs are fed to a [`Sequitur<u32>`]. The discovered rules can then be
+/// listed as text with [`phrases`](Self::phrases).
+///
+/// # Example
+///
+/// ```
+/// use sequitur_rs::TextSequitur;
+///
+/// let mut text = TextSequitur::new();
+/// text.push_str("the cat sat on the mat. the cat sat on the hat.");
+///
+/// assert_eq!(text.text(), "the cat sat on the mat. the cat sat on the hat.");
+/// assert!(text
+///     .phrases()
+///     .iter()
+///     .any(|phrase| phrase.text.contains("cat sat on the")));
+/// ```
+pub struct TextSequitur {
+    /// Grammar over token IDs
+    sequitur: Sequitur<u32>,
+
+    /// Token text for each token ID
+    tokens: Interner<String>,
+}
+
+/// A rule of a [`TextSequitur`] grammar, mapped back to text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Phrase {
+    /// ID of the underlying grammar rule
+    pub rule_id: u32,
+    /// Full text the rule expands to
+    pub text: String,
+    /// Number of references to this rule in the grammar
+    pub uses: u32,
+    /// Right-hand side of the rule, one entry per symbol
+    pub parts: Vec<PhrasePart>,
+}
+
+/// One symbol on the right-hand side of a [`Phrase`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PhrasePart {
+    /// A single token
+    Token(String),
+    /// A nested phrase, identified by its rule ID
+    Phrase(u32),
+}
+
+impl TextSequitur {
+    /// Creates a new empty instance.
+    pub fn new() -> Self {
+        Self {
+            sequitur: Sequitur::new(),
+            tokens: Interner::new(),
+        }
+    }
+
+    /// Tokenizes `text` and appends the tokens to the sequence.
+    ///
+    /// Each call is tokenized independently, so a word split across two calls
+    /// becomes two tokens.
+    pub fn push_str(&mut self, text: &str) {
+        for token in tokenize(text) {
+            let id = self.tokens.intern(token);
+            self.sequitur.push(id);
+        }
+    }
+
+    /// Returns the number of tokens added.
+    pub fn len(&self) -> usize {
+        self.sequitur.len()
+    }
+
+    /// Returns true if no tokens have been added.
+    pub fn is_empty(&self) -> bool {
+        self.sequitur.is_empty()
+    }
+
+    /// Returns the number of distinct tokens seen.
+    pub fn num_distinct_tokens(&self) -> usize {
+        self.tokens.len()
+    }
+
+    /// Returns the text of a token ID.
+    pub fn token(&self, id: u32) -> Option<&str> {
+        self.tokens.resolve(id).map(String::as_str)
+    }
+
+    /// Returns an iterator over the reconstructed token stream.
+    pub fn tokens(&self) -> impl Iterator<Item = &str> + '_ {
+        self.sequitur.iter().map(|&id| self.token_text(id))
+    }
+
+    /// Reconstructs the full text.
+    pub fn text(&self) -> String {
+        self.tokens().collect()
+    }
+
+    /// Returns the underlying grammar over token IDs.
+    pub fn sequitur(&self) -> &Sequitur<u32> {
+        &self.sequitur
+    }
+
+    /// Returns compression statistics, counted in tokens.
+    pub fn stats(&self) -> CompressionStats {
+        self.sequitur.stats()
+    }
+
+    /// Lists every rule except Rule 0 as a phrase, ordered by rule ID.
+    ///
+    /// `parts` refers to nested phrases by rule ID, so the hierarchy can be
+    /// walked by looking those IDs up in the returned list.
+    pub fn phrases(&self) -> Vec<Phrase> {
+        let grammar = &self.sequitur.grammar;
+        let mut texts: HashMap<u32, String> = HashMap::default();
+        let mut phrases = Vec::with_capacity(grammar.rule_index.len().saturating_sub(1));
+
+        for (&rule_id, &head) in &grammar.rule_index {
+            if rule_id == 0 {
+                continue;
+            }
+            let Symbol::RuleHead { count, .. } = grammar.symbols[head].symbol else {
+                unreachable!("rule index should only point at RuleHeads");
+            };
+
+            let parts = grammar
+                .body_keys(head)
+                .map(|key| match grammar.symbols[key].symbol {
+                    Symbol::Value(id) => PhrasePart::Token(self.token_text(id).to_string()),
+                    Symbol::RuleRef { rule_id } => PhrasePart::Phrase(rule_id),
+                    _ => unreachable!("rule bodies only contain values and rule references"),
+                })
+                .collect();
+
+            phrases.push(Phrase {
+                rule_id,
+                text: self.rule_text(rule_id, &mut texts).to_string(),
+                uses: count,
+                parts,
+            });
+        }
+
+        phrases.sort_by_key(|phrase| phrase.rule_id);
+        phrases
+    }
+
+    /// Returns the expansion of a rule, memoizing every rule it visits.
+    fn rule_text<'a>(&self, rule_id: u32, texts: &'a mut HashMap<u32, String>) -> &'a str {
+        if !texts.contains_key(&rule_id) {
+            let grammar = &self.sequitur.grammar;
+            let head = grammar.rule_index[&rule_id];
+            let mut text = String::new();
+            for key in grammar.body_keys(head) {
+                match grammar.symbols[key].symbol {
+                    Symbol::Value(id) => text.push_str(self.token_text(id)),
+                    Symbol::RuleRef { rule_id } => text.push_str(self.rule_text(rule_id, texts)),
+                    _ => unreachable!("rule bodies only contain values and rule references"),
+                }
+            }
+            texts.insert(rule_id, text);
+        }
+        &texts[&rule_id]
+    }
+
+    #[inline]
+    fn token_text(&self, id: u32) -> &str {
+        self.tokens
+            .resolve(id)
+            .expect("token IDs in the grammar should be interned")
+    }
+}
+
+impl Default for TextSequitur {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Character classes that make up tokens.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum TokenClass {
+    Word,
+    Whitespace,
+    Punctuation,
+}
+
+impl TokenClass {
+    fn of(c: char) -> Self {
+        if c.is_alphanumeric() {
+            TokenClass::Word
+        } else if c.is_whitespace() {
+            TokenClass::Whitespace
+        } else {
+            TokenClass::Punctuation
+        }
+    }
+}
+
+/// Splits text into maximal runs of word characters, maximal runs of whitespace,
+/// and single punctuation characters.
+fn tokenize(text: &str) -> impl Iterator<Item = &str> {
+    let mut rest = text;
+    std::iter::from_fn(move || {
+        let mut chars = rest.char_indices();
+        let (_, first) = chars.next()?;
+        let class = TokenClass::of(first);
+
+        let end = if class == TokenClass::Punctuation {
+            first.len_utf8()
+        } else {
+            chars
+                .find(|&(_, c)| TokenClass::of(c) != class)
+                .map_or(rest.len(), |(i, _)| i)
+        };
+
+        let (token, tail) = rest.split_at(end);
+        rest = tail;
+        Some(token)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tokenize() {
+        let tokens: Vec<_> = tokenize("Hello,  world!! ok").collect();
+        assert_eq!(
+            tokens,
+            vec!["Hello", ",", "  ", "world", "!", "!", " ", "ok"]
+        );
+    }
+
+    #[test]
+    fn test_tokenize_unicode() {
+        let tokens: Vec<_> = tokenize("naïve café—ok").collect();
+        assert_eq!(tokens, vec!["naïve", " ", "café", "—", "ok"]);
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let input = "It was the best of times, it was the worst of times.";
+        let mut text = TextSequitur::new();
+        text.push_str(input);
+
+        assert_eq!(text.text(), input);
+        assert_eq!(text.len(), tokenize(input).count());
+    }
+
+    #[test]
+    fn test_tokens_are_interned() {
+        let mut text = TextSequitur::new();
+        text.push_str("a b a b a");
+
+        // "a", " ", "b"
+        assert_eq!(text.num_distinct_tokens(), 3);
+        assert_eq!(text.token(0), Some("a"));
+    }
+
+    #[test]
+    fn test_phrase_hierarchy() {
+        let mut text = TextSequitur::new();
+        text.push_str("to be or not to be, to be or not to be");
+
+        let phrases = text.phrases();
+        assert!(!phrases.is_empty());
+
+        let by_id: HashMap<u32, &Phrase> = phrases.iter().map(|p| (p.rule_id, p)).collect();
+        for phrase in &phrases {
+            assert!(phrase.uses >= 2);
+
+            // Each phrase's text is the concatenation of its parts.
+            let rebuilt: String = phrase
+                .parts
+                .iter()
+                .map(|part| match part {
+                    PhrasePart::Token(token) => token.as_str(),
+                    PhrasePart::Phrase(id) => by_id[id].text.as_str(),
+                })
+                .collect();
+            assert_eq!(rebuilt, phrase.text);
+        }
+
+        assert!(phrases.iter().any(|p| p.text.ends_with("or not to be")));
+    }
+
+    #[test]
+    fn test_empty() {
+        let text = TextSequitur::new();
+        assert!(text.is_empty());
+        assert_eq!(text.text(), "");
+        assert!(text.phrases().is_empty());
+    }
+}
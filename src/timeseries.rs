@@ -0,0 +1,485 @@
+use crate::occurrence::RuleOccurrence;
+use crate::rle_sequitur::SequiturRle;
+use std::fmt;
+
+/// Configuration for SAX (Symbolic Aggregate approXimation) discretization.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SaxConfig {
+    /// Length of the sliding window; one word is produced per window position
+    pub window: usize,
+    /// Number of PAA segments, i.e. letters per word
+    pub paa_segments: usize,
+    /// Number of letters in the alphabet (2 to 26)
+    pub alphabet_size: usize,
+    /// Windows whose standard deviation is below this are treated as flat
+    /// instead of being z-normalized
+    pub normalization_threshold: f64,
+    /// How consecutive similar words are collapsed
+    pub numerosity_reduction: NumerosityReduction,
+}
+
+impl Default for SaxConfig {
+    fn default() -> Self {
+        Self {
+            window: 32,
+            paa_segments: 4,
+            alphabet_size: 4,
+            normalization_threshold: 0.01,
+            numerosity_reduction: NumerosityReduction::Exact,
+        }
+    }
+}
+
+/// Strategy for collapsing consecutive SAX words.
+///
+/// Collapsed words are stored as runs in a [`SequiturRle`] grammar, so every
+/// window still occupies one position in the expanded word sequence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NumerosityReduction {
+    /// Only identical consecutive words are collapsed
+    Exact,
+    /// A word is also collapsed into the previous one when their MINDIST is zero,
+    /// i.e. no pair of letters differs by more than one
+    Mindist,
+}
+
+/// A SAX word: one letter index (`0..alphabet_size`) per PAA segment.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SaxWord(Vec<u8>);
+
+impl SaxWord {
+    /// Returns the letter indices of the word.
+    pub fn letters(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// Returns true if the MINDIST between the two words is zero.
+    fn mindist_is_zero(&self, other: &SaxWord) -> bool {
+        self.0.len() == other.0.len()
+            && self
+                .0
+                .iter()
+                .zip(&other.0)
+                .all(|(&a, &b)| a.abs_diff(b) <= 1)
+    }
+}
+
+impl fmt::Display for SaxWord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for &letter in &self.0 {
+            write!(f, "{}", char::from(b'a' + letter))?;
+        }
+        Ok(())
+    }
+}
+
+/// An occurrence of a grammar rule, mapped back to the original time series.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RuleInterval {
+    /// ID of the rule
+    pub rule_id: u32,
+    /// Index of the first sample covered
+    pub start: usize,
+    /// Index one past the last sample covered
+    pub end: usize,
+}
+
+/// Grammar induced over the SAX discretization of a numeric time series.
+///
+/// Each sliding window is z-normalized, reduced with PAA and mapped to a
+/// [`SaxWord`]. The words are fed to a [`SequiturRle`], whose run encoding
+/// provides numerosity reduction without losing window positions: position `i`
+/// of the expanded word sequence is the window starting at sample `i`.
+///
+/// # Example
+///
+/// ```
+/// use sequitur_rs::{SaxConfig, SaxGrammar};
+///
+/// let series: Vec<f64> = (0..400).map(|i| ((i as f64) * 0.2).sin()).collect();
+/// let config = SaxConfig {
+///     window: 16,
+///     ..SaxConfig::default()
+/// };
+///
+/// let grammar = SaxGrammar::new(&series, config);
+/// assert_eq!(grammar.num_windows(), 400 - 16 + 1);
+///
+/// // Repeated motifs of the periodic signal show up as rule intervals.
+/// assert!(!grammar.rule_intervals().is_empty());
+/// ```
+pub struct SaxGrammar {
+    config: SaxConfig,
+    series_len: usize,
+    sequitur: SequiturRle<SaxWord>,
+}
+
+impl SaxGrammar {
+    /// Discretizes `series` and builds the grammar over the resulting words.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the window or number of PAA segments is zero, if there are more
+    /// segments than window samples, or if the alphabet size is outside `2..=26`.
+    pub fn new(series: &[f64], config: SaxConfig) -> Self {
+        assert!(config.window > 0, "SAX window must be non-empty");
+        assert!(
+            config.paa_segments > 0 && config.paa_segments <= config.window,
+            "PAA segments must be between 1 and the window length"
+        );
+        assert!(
+            (2..=26).contains(&config.alphabet_size),
+            "SAX alphabet size must be between 2 and 26"
+        );
+
+        let mut sequitur = SequiturRle::new();
+        let mut previous: Option<SaxWord> = None;
+        for word in SaxWords::new(series, &config) {
+            let word = match previous {
+                Some(ref prev)
+                    if config.numerosity_reduction == NumerosityReduction::Mindist
+                        && prev.mindist_is_zero(&word) =>
+                {
+                    prev.clone()
+                }
+                _ => word,
+            };
+            sequitur.push(word.clone());
+            previous = Some(word);
+        }
+
+        Self {
+            config,
+            series_len: series.len(),
+            sequitur,
+        }
+    }
+
+    /// Returns the configuration used for discretization.
+    pub fn config(&self) -> &SaxConfig {
+        &self.config
+    }
+
+    /// Returns the length of the original series.
+    pub fn series_len(&self) -> usize {
+        self.series_len
+    }
+
+    /// Returns the number of sliding windows (and therefore words).
+    pub fn num_windows(&self) -> usize {
+        self.sequitur.len()
+    }
+
+    /// Returns the underlying grammar over SAX words.
+    pub fn sequitur(&self) -> &SequiturRle<SaxWord> {
+        &self.sequitur
+    }
+
+    /// Returns an iterator over the word of every window, in order.
+    pub fn words(&self) -> impl Iterator<Item = &SaxWord> {
+        self.sequitur.iter()
+    }
+
+    /// Maps a rule occurrence over words to the samples its windows cover.
+    pub fn interval(&self, occurrence: &RuleOccurrence) -> RuleInterval {
+        RuleInterval {
+            rule_id: occurrence.rule_id,
+            start: occurrence.start,
+            end: occurrence.end() - 1 + self.config.window,
+        }
+    }
+
+    /// Returns every rule occurrence mapped to an interval of the original
+    /// series, ordered by start.
+    pub fn rule_intervals(&self) -> Vec<RuleInterval> {
+        self.sequitur
+            .rule_occurrences()
+            .iter()
+            .map(|occurrence| self.interval(occurrence))
+            .collect()
+    }
+}
+
+/// Iterator producing the SAX word of each sliding window.
+///
+/// Uses prefix sums so each word costs O(paa_segments) regardless of the window
+/// length.
+struct SaxWords<'a> {
+    series: &'a [f64],
+    config: &'a SaxConfig,
+    breakpoints: Vec<f64>,
+    /// Prefix sums of the centered series
+    sums: Vec<f64>,
+    /// Prefix sums of squares of the centered series
+    squares: Vec<f64>,
+    /// Start of the next window
+    position: usize,
+}
+
+impl<'a> SaxWords<'a> {
+    fn new(series: &'a [f64], config: &'a SaxConfig) -> Self {
+        // Center the series first to limit cancellation in the variance.
+        let mean = series.iter().sum::<f64>() / series.len().max(1) as f64;
+
+        let mut sums = Vec::with_capacity(series.len() + 1);
+        let mut squares = Vec::with_capacity(series.len() + 1);
+        sums.push(0.0);
+        squares.push(0.0);
+        for &x in series {
+            let x = x - mean;
+            sums.push(sums.last().unwrap() + x);
+            squares.push(squares.last().unwrap() + x * x);
+        }
+
+        Self {
+            series,
+            config,
+            breakpoints: breakpoints(config.alphabet_size),
+            sums,
+            squares,
+            position: 0,
+        }
+    }
+
+    /// Sum of the centered series over the real-valued interval `[from, to)`,
+    /// treating each sample as constant over its unit interval.
+    fn sum_between(&self, from: f64, to: f64) -> f64 {
+        self.prefix(to) - self.prefix(from)
+    }
+
+    fn prefix(&self, at: f64) -> f64 {
+        let whole = at.floor() as usize;
+        let frac = at - whole as f64;
+        if whole >= self.series.len() {
+            return self.sums[self.series.len()];
+        }
+        self.sums[whole] + frac * (self.sums[whole + 1] - self.sums[whole])
+    }
+}
+
+impl Iterator for SaxWords<'_> {
+    type Item = SaxWord;
+
+    fn next(&mut self) -> Option<SaxWord> {
+        let window = self.config.window;
+        let start = self.position;
+        if start + window > self.series.len() {
+            return None;
+        }
+        self.position += 1;
+
+        let n = window as f64;
+        let mean = (self.sums[start + window] - self.sums[start]) / n;
+        let variance =
+            ((self.squares[start + window] - self.squares[start]) / n - mean * mean).max(0.0);
+        let std_dev = variance.sqrt();
+
+        let segments = self.config.paa_segments;
+        let segment_len = n / segments as f64;
+        let letters = (0..segments)
+            .map(|i| {
+                let value = if std_dev < self.config.normalization_threshold {
+                    0.0
+                } else {
+                    let from = start as f64 + i as f64 * segment_len;
+                    let to = start as f64 + (i + 1) as f64 * segment_len;
+                    let segment_mean = self.sum_between(from, to) / segment_len;
+                    (segment_mean - mean) / std_dev
+                };
+                self.breakpoints.partition_point(|&b| b <= value) as u8
+            })
+            .collect();
+
+        Some(SaxWord(letters))
+    }
+}
+
+/// Returns the `alphabet_size - 1` breakpoints dividing the standard normal
+/// distribution into equiprobable regions.
+fn breakpoints(alphabet_size: usize) -> Vec<f64> {
+    (1..alphabet_size)
+        .map(|i| inverse_normal_cdf(i as f64 / alphabet_size as f64))
+        .collect()
+}
+
+/// Inverse of the standard normal CDF (Acklam's rational approximation,
+/// relative error below 1.2e-9).
+fn inverse_normal_cdf(p: f64) -> f64 {
+    const A: [f64; 6] = [
+        -3.969_683_028_665_376e1,
+        2.209_460_984_245_205e2,
+        -2.759_285_104_469_687e2,
+        1.383_577_518_672_69e2,
+        -3.066_479_806_614_716e1,
+        2.506_628_277_459_239,
+    ];
+    const B: [f64; 5] = [
+        -5.447_609_879_822_406e1,
+        1.615_858_368_580_409e2,
+        -1.556_989_798_598_866e2,
+        6.680_131_188_771_972e1,
+        -1.328_068_155_288_572e1,
+    ];
+    const C: [f64; 6] = [
+        -7.784_894_002_430_293e-3,
+        -3.223_964_580_411_365e-1,
+        -2.400_758_277_161_838,
+        -2.549_732_539_343_734,
+        4.374_664_141_464_968,
+        2.938_163_982_698_783,
+    ];
+    const D: [f64; 4] = [
+        7.784_695_709_041_462e-3,
+        3.224_671_290_700_398e-1,
+        2.445_134_137_142_996,
+        3.754_408_661_907_416,
+    ];
+    const P_LOW: f64 = 0.02425;
+
+    let tail = |q: f64| {
+        (((((C[0] * q + C[1]) * q + C[2]) * q + C[3]) * q + C[4]) * q + C[5])
+            / ((((D[0] * q + D[1]) * q + D[2]) * q + D[3]) * q + 1.0)
+    };
+
+    if p < P_LOW {
+        tail((-2.0 * p.ln()).sqrt())
+    } else if p <= 1.0 - P_LOW {
+        let q = p - 0.5;
+        let r = q * q;
+        (((((A[0] * r + A[1]) * r + A[2]) * r + A[3]) * r + A[4]) * r + A[5]) * q
+            / (((((B[0] * r + B[1]) * r + B[2]) * r + B[3]) * r + B[4]) * r + 1.0)
+    } else {
+        -tail((-2.0 * (1.0 - p).ln()).sqrt())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(window: usize, paa_segments: usize, alphabet_size: usize) -> SaxConfig {
+        SaxConfig {
+            window,
+            paa_segments,
+            alphabet_size,
+            ..SaxConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_breakpoints() {
+        let b = breakpoints(4);
+        assert_eq!(b.len(), 3);
+        assert!((b[0] + 0.6745).abs() < 1e-4);
+        assert!(b[1].abs() < 1e-9);
+        assert!((b[2] - 0.6745).abs() < 1e-4);
+
+        let b = breakpoints(3);
+        assert!((b[0] + 0.4307).abs() < 1e-4);
+        assert!((b[1] - 0.4307).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_ramp_word() {
+        // A linear ramp maps to increasing letters.
+        let series: Vec<f64> = (0..8).map(f64::from).collect();
+        let cfg = config(8, 4, 4);
+        let words: Vec<_> = SaxWords::new(&series, &cfg).collect();
+        assert_eq!(words.len(), 1);
+        assert_eq!(words[0].to_string(), "abcd");
+    }
+
+    #[test]
+    fn test_fractional_paa() {
+        // 5 samples into 2 segments: each segment covers 2.5 samples.
+        let series = [0.0, 0.0, 1.0, 2.0, 2.0];
+        let cfg = config(5, 2, 2);
+        let words: Vec<_> = SaxWords::new(&series, &cfg).collect();
+        assert_eq!(words[0].to_string(), "ab");
+    }
+
+    #[test]
+    fn test_flat_window_is_not_normalized() {
+        let series = [5.0; 10];
+        let cfg = config(4, 2, 3);
+        let words: Vec<_> = SaxWords::new(&series, &cfg).collect();
+        assert_eq!(words.len(), 7);
+        assert!(words.iter().all(|w| w.to_string() == "bb"));
+    }
+
+    #[test]
+    fn test_short_series_has_no_windows() {
+        let grammar = SaxGrammar::new(&[1.0, 2.0], config(4, 2, 3));
+        assert_eq!(grammar.num_windows(), 0);
+        assert!(grammar.rule_intervals().is_empty());
+    }
+
+    #[test]
+    fn test_numerosity_reduction_keeps_positions() {
+        let series: Vec<f64> = (0..200).map(|i| ((i as f64) * 0.3).sin()).collect();
+        let grammar = SaxGrammar::new(&series, config(10, 4, 4));
+
+        assert_eq!(grammar.num_windows(), series.len() - 10 + 1);
+        assert_eq!(grammar.words().count(), grammar.num_windows());
+        // Runs of identical words are collapsed into single nodes.
+        assert!(grammar.sequitur().stats().grammar_nodes < grammar.num_windows());
+    }
+
+    #[test]
+    fn test_mindist_reduction() {
+        let a = SaxWord(vec![0, 1, 2]);
+        let b = SaxWord(vec![1, 1, 3]);
+        let c = SaxWord(vec![0, 3, 2]);
+        assert!(a.mindist_is_zero(&b));
+        assert!(!a.mindist_is_zero(&c));
+
+        let series: Vec<f64> = (0..300).map(|i| ((i as f64) * 0.1).sin()).collect();
+        let exact = SaxGrammar::new(&series, config(20, 4, 6));
+        let mindist = SaxGrammar::new(
+            &series,
+            SaxConfig {
+                numerosity_reduction: NumerosityReduction::Mindist,
+                ..config(20, 4, 6)
+            },
+        );
+        assert_eq!(exact.num_windows(), mindist.num_windows());
+        assert!(mindist.sequitur().stats().grammar_nodes <= exact.sequitur().stats().grammar_nodes);
+    }
+
+    #[test]
+    fn test_rule_intervals_cover_repeated_motif() {
+        // The same bump pattern occurs three times, separated by noise-free flats.
+        let bump = [0.0, 1.0, 3.0, 1.0, 0.0, -1.0, -3.0, -1.0];
+        let mut series = Vec::new();
+        for gap in [5usize, 9, 13] {
+            series.extend(std::iter::repeat_n(0.0, gap));
+            series.extend_from_slice(&bump);
+        }
+
+        let grammar = SaxGrammar::new(&series, config(4, 2, 3));
+        let words: Vec<_> = grammar.words().cloned().collect();
+        let intervals = grammar.rule_intervals();
+        assert!(!intervals.is_empty());
+
+        for interval in &intervals {
+            assert!(interval.start < interval.end);
+            assert!(interval.end <= series.len());
+            // An interval spans at least one full window.
+            assert!(interval.end - interval.start >= grammar.config().window);
+        }
+
+        // Occurrences of the same rule cover identical word sequences.
+        let occurrences = grammar.sequitur().rule_occurrences();
+        for a in &occurrences {
+            for b in occurrences.iter().filter(|b| b.rule_id == a.rule_id) {
+                assert_eq!(words[a.start..a.end()], words[b.start..b.end()]);
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "alphabet size")]
+    fn test_invalid_alphabet() {
+        SaxGrammar::new(&[0.0; 10], config(4, 2, 1));
+    }
+}
@@ -0,0 +1,491 @@
+use crate::error::SequiturError;
+use crate::grammar::Grammar;
+use crate::rle_grammar::RleGrammar;
+use crate::symbol::Symbol;
+use ahash::AHashMap as HashMap;
+use slotmap::DefaultKey;
+
+/// Rule IDs kept in reserve by the `try_*` methods: a single push creates only
+/// a handful of rules, so refusing to start one with fewer IDs left means it
+/// can never run out halfway.
+const RULE_ID_RESERVE: u64 = 64;
+
+impl<T> Grammar<T> {
+    /// Checks that `additional` more values can be added to a sequence of
+    /// `length` values without exhausting rule IDs or overflowing the length.
+    pub(crate) fn check_capacity(
+        &self,
+        length: usize,
+        additional: usize,
+    ) -> Result<(), SequiturError> {
+        if length.checked_add(additional).is_none() {
+            return Err(SequiturError::CapacityExceeded);
+        }
+        if self.id_gen.available() < RULE_ID_RESERVE {
+            return Err(SequiturError::RuleIdsExhausted);
+        }
+        Ok(())
+    }
+
+    /// Checks the structural consistency the iterators rely on.
+    ///
+    /// `sequences` lists the head of each top-level sequence with its expected
+    /// length, and `root` names the rule, if any, that is a sequence rather
+    /// than a referenced rule. Every rule body must be properly linked and end
+    /// at its own tail, reference only existing rules without cycles, and be
+    /// referenced as often as its count says.
+    pub(crate) fn validate(
+        &self,
+        sequences: &[(DefaultKey, usize)],
+        root: Option<u32>,
+    ) -> Result<(), SequiturError> {
+        let corrupt = |reason: String| Err(SequiturError::CorruptGrammar(reason));
+
+        let mut refs: HashMap<u32, u32> = HashMap::default();
+        let mut children: HashMap<u32, Vec<u32>> = HashMap::default();
+        for (&rule_id, &head) in &self.rule_index {
+            match self.symbols.get(head).map(|node| &node.symbol) {
+                Some(&Symbol::RuleHead { rule_id: id, .. }) if id == rule_id => {}
+                _ => return corrupt(format!("rule {rule_id} has no matching RuleHead")),
+            }
+            let body = self.validate_body(head, root == Some(rule_id), &mut refs)?;
+            children.insert(rule_id, body);
+        }
+        if let Some(root) = root {
+            if !self.rule_index.contains_key(&root) {
+                return corrupt(format!("root rule {root} is missing"));
+            }
+        }
+        for &(head, _) in sequences {
+            if !matches!(
+                self.symbols.get(head).map(|node| &node.symbol),
+                Some(Symbol::RuleHead { .. } | Symbol::DocHead { .. })
+            ) {
+                return corrupt("sequence head is missing".into());
+            }
+            if matches!(self.symbols[head].symbol, Symbol::DocHead { .. }) {
+                self.validate_body(head, true, &mut refs)?;
+            }
+        }
+
+        for (&rule_id, &head) in &self.rule_index {
+            let Symbol::RuleHead { count, .. } = self.symbols[head].symbol else {
+                unreachable!("checked above");
+            };
+            let uses = refs.get(&rule_id).copied().unwrap_or(0);
+            // Only the root may go unreferenced, and then it must stay so
+            if count != uses || (uses == 0) != (root == Some(rule_id)) {
+                return corrupt(format!(
+                    "rule {rule_id} has count {count} but {uses} references"
+                ));
+            }
+        }
+
+        validate_acyclic(&children)?;
+
+        let mut lengths = HashMap::default();
+        for &(head, expected) in sequences {
+            let length: usize = self
+                .body_keys(head)
+                .map(|key| match self.symbols[key].symbol {
+                    Symbol::RuleRef { rule_id } => self.rule_length(rule_id, &mut lengths),
+                    _ => 1,
+                })
+                .sum();
+            if length != expected {
+                return corrupt(format!(
+                    "sequence expands to {length} values but records {expected}"
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Walks a body from its head to its tail, checking links and symbols,
+    /// counting references in `refs` and returning the referenced rules. Only
+    /// sequences may be empty.
+    fn validate_body(
+        &self,
+        head: DefaultKey,
+        sequence: bool,
+        refs: &mut HashMap<u32, u32>,
+    ) -> Result<Vec<u32>, SequiturError> {
+        let corrupt = |reason: &str| Err(SequiturError::CorruptGrammar(reason.into()));
+        let tail = match self.symbols[head].symbol {
+            Symbol::RuleHead { tail, .. } | Symbol::DocHead { tail } => tail,
+            _ => return corrupt("body doesn't start at a head"),
+        };
+
+        let mut children = Vec::new();
+        let mut prev = head;
+        // A well-formed body can't be longer than the whole grammar.
+        for _ in 0..self.symbols.len() {
+            let Some(key) = self.symbols[prev].next else {
+                return corrupt("body ends without a tail");
+            };
+            let Some(node) = self.symbols.get(key) else {
+                return corrupt("link to a removed symbol");
+            };
+            if node.prev != Some(prev) {
+                return corrupt("inconsistent prev and next links");
+            }
+            match node.symbol {
+                Symbol::Value(_) => {}
+                Symbol::RuleRef { rule_id } => {
+                    if !self.rule_index.contains_key(&rule_id) {
+                        return corrupt("reference to a missing rule");
+                    }
+                    *refs.entry(rule_id).or_insert(0) += 1;
+                    children.push(rule_id);
+                }
+                Symbol::RuleTail | Symbol::DocTail if key == tail => {
+                    if prev == head && !sequence {
+                        return corrupt("empty body");
+                    }
+                    return Ok(children);
+                }
+                _ => return corrupt("sentinel inside a body"),
+            }
+            prev = key;
+        }
+        corrupt("body links form a cycle")
+    }
+}
+
+impl<T> RleGrammar<T> {
+    /// Checks that `additional` more values can be added to a sequence of
+    /// `length` values without exhausting rule IDs or overflowing the length.
+    pub(crate) fn check_capacity(
+        &self,
+        length: usize,
+        additional: usize,
+    ) -> Result<(), SequiturError> {
+        if length.checked_add(additional).is_none() {
+            return Err(SequiturError::CapacityExceeded);
+        }
+        if self.id_gen.available() < RULE_ID_RESERVE {
+            return Err(SequiturError::RuleIdsExhausted);
+        }
+        Ok(())
+    }
+
+    /// Checks the structural consistency the iterators rely on.
+    ///
+    /// Like [`Grammar::validate`], except that a rule's count is the sum of the
+    /// runs of its references, every run must be at least 1, and lengths count
+    /// runs.
+    pub(crate) fn validate(
+        &self,
+        sequences: &[(DefaultKey, usize)],
+        root: Option<u32>,
+    ) -> Result<(), SequiturError> {
+        let corrupt = |reason: String| Err(SequiturError::CorruptGrammar(reason));
+
+        let mut refs: HashMap<u32, u64> = HashMap::default();
+        let mut children: HashMap<u32, Vec<u32>> = HashMap::default();
+        for (&rule_id, &head) in &self.rule_index {
+            match self.symbols.get(head).map(|node| &node.symbol) {
+                Some(&Symbol::RuleHead { rule_id: id, .. }) if id == rule_id => {}
+                _ => return corrupt(format!("rule {rule_id} has no matching RuleHead")),
+            }
+            let body = self.validate_body(head, root == Some(rule_id), &mut refs)?;
+            children.insert(rule_id, body);
+        }
+        if let Some(root) = root {
+            if !self.rule_index.contains_key(&root) {
+                return corrupt(format!("root rule {root} is missing"));
+            }
+        }
+        for &(head, _) in sequences {
+            if !matches!(
+                self.symbols.get(head).map(|node| &node.symbol),
+                Some(Symbol::RuleHead { .. } | Symbol::DocHead { .. })
+            ) {
+                return corrupt("sequence head is missing".into());
+            }
+            if matches!(self.symbols[head].symbol, Symbol::DocHead { .. }) {
+                self.validate_body(head, true, &mut refs)?;
+            }
+        }
+
+        for (&rule_id, &head) in &self.rule_index {
+            let Symbol::RuleHead { count, .. } = self.symbols[head].symbol else {
+                unreachable!("checked above");
+            };
+            // Counts saturate, as they only need to tell single uses apart
+            let uses = refs.get(&rule_id).copied().unwrap_or(0);
+            // Only the root may go unreferenced, and then it must stay so
+            if u64::from(count) != uses.min(u64::from(u32::MAX))
+                || (uses == 0) != (root == Some(rule_id))
+            {
+                return corrupt(format!(
+                    "rule {rule_id} has count {count} but {uses} references"
+                ));
+            }
+        }
+
+        validate_acyclic(&children)?;
+
+        let mut lengths = HashMap::default();
+        for &(head, expected) in sequences {
+            let length = self.checked_body_length(head, &mut lengths);
+            if length != Some(expected) {
+                return corrupt(format!(
+                    "sequence expands to {} values but records {expected}",
+                    length.map_or("too many".into(), |length| length.to_string())
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the expanded length of a body, or `None` if it overflows `usize`.
+    fn checked_body_length(
+        &self,
+        head: DefaultKey,
+        lengths: &mut HashMap<u32, Option<usize>>,
+    ) -> Option<usize> {
+        let mut length = 0usize;
+        for key in self.body_keys(head) {
+            let node = &self.symbols[key];
+            let unit = match node.symbol {
+                Symbol::RuleRef { rule_id } => match lengths.get(&rule_id) {
+                    Some(&len) => len,
+                    None => {
+                        let len = self.checked_body_length(self.rule_index[&rule_id], lengths);
+                        lengths.insert(rule_id, len);
+                        len
+                    }
+                }?,
+                _ => 1,
+            };
+            let run = usize::try_from(node.run).ok()?;
+            length = length.checked_add(unit.checked_mul(run)?)?;
+        }
+        Some(length)
+    }
+
+    /// Walks a body from its head to its tail, checking links, symbols and
+    /// runs, adding each reference's run to `refs` and returning the
+    /// referenced rules. Only sequences may be empty.
+    fn validate_body(
+        &self,
+        head: DefaultKey,
+        sequence: bool,
+        refs: &mut HashMap<u32, u64>,
+    ) -> Result<Vec<u32>, SequiturError> {
+        let corrupt = |reason: &str| Err(SequiturError::CorruptGrammar(reason.into()));
+        let tail = match self.symbols[head].symbol {
+            Symbol::RuleHead { tail, .. } | Symbol::DocHead { tail } => tail,
+            _ => return corrupt("body doesn't start at a head"),
+        };
+
+        let mut children = Vec::new();
+        let mut prev = head;
+        // A well-formed body can't be longer than the whole grammar.
+        for _ in 0..self.symbols.len() {
+            let Some(key) = self.symbols[prev].next else {
+                return corrupt("body ends without a tail");
+            };
+            let Some(node) = self.symbols.get(key) else {
+                return corrupt("link to a removed symbol");
+            };
+            if node.prev != Some(prev) {
+                return corrupt("inconsistent prev and next links");
+            }
+            match node.symbol {
+                Symbol::Value(_) | Symbol::RuleRef { .. } if node.run == 0 => {
+                    return corrupt("empty run");
+                }
+                Symbol::Value(_) => {}
+                Symbol::RuleRef { rule_id } => {
+                    if !self.rule_index.contains_key(&rule_id) {
+                        return corrupt("reference to a missing rule");
+                    }
+                    let uses = refs.entry(rule_id).or_insert(0);
+                    *uses = uses.saturating_add(node.run);
+                    children.push(rule_id);
+                }
+                Symbol::RuleTail | Symbol::DocTail if key == tail => {
+                    if prev == head && !sequence {
+                        return corrupt("empty body");
+                    }
+                    return Ok(children);
+                }
+                _ => return corrupt("sentinel inside a body"),
+            }
+            prev = key;
+        }
+        corrupt("body links form a cycle")
+    }
+}
+
+/// Checks that no rule expands to itself.
+fn validate_acyclic(children: &HashMap<u32, Vec<u32>>) -> Result<(), SequiturError> {
+    // 1 = on the current path, 2 = finished
+    let mut state: HashMap<u32, u8> = HashMap::default();
+    for &start in children.keys() {
+        if state.contains_key(&start) {
+            continue;
+        }
+        state.insert(start, 1);
+        let mut stack = vec![(start, 0)];
+        while let Some((rule_id, index)) = stack.pop() {
+            let Some(&child) = children[&rule_id].get(index) else {
+                state.insert(rule_id, 2);
+                continue;
+            };
+            stack.push((rule_id, index + 1));
+            match state.get(&child) {
+                Some(1) => {
+                    return Err(SequiturError::CorruptGrammar(format!(
+                        "rule {child} expands to itself"
+                    )))
+                }
+                Some(_) => {}
+                None => {
+                    state.insert(child, 1);
+                    stack.push((child, 0));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::SequiturError;
+    use crate::symbol::Symbol;
+    use crate::{Sequitur, SequiturDocuments, SequiturDocumentsRle, SequiturRle};
+
+    fn corrupt<T>(result: Result<T, SequiturError>) -> bool {
+        matches!(result, Err(SequiturError::CorruptGrammar(_)))
+    }
+
+    fn sample() -> Sequitur<char> {
+        let mut seq = Sequitur::new();
+        seq.extend("abcabcabdabd".chars());
+        seq
+    }
+
+    #[test]
+    fn test_valid_grammars() {
+        assert!(sample().try_iter().is_ok());
+        assert!(Sequitur::<u8>::new().try_iter().is_ok());
+
+        let mut docs = SequiturDocuments::new();
+        docs.extend_document(1, "xyzxyz".chars());
+        docs.extend_document(2, "xyz".chars());
+        assert!(docs.try_iter_document(&1).unwrap().is_some());
+        assert!(docs.try_iter_document(&3).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_missing_rule() {
+        let mut seq = sample();
+        let rule_id = *seq.rules().keys().find(|&&id| id != 0).unwrap();
+        seq.grammar.rule_index.remove(&rule_id);
+        assert!(corrupt(seq.try_iter()));
+    }
+
+    #[test]
+    fn test_wrong_count() {
+        let mut seq = sample();
+        let (_, &head) = seq.rules().iter().find(|(&id, _)| id != 0).unwrap();
+        if let Symbol::RuleHead { count, .. } = &mut seq.grammar.symbols[head].symbol {
+            *count += 1;
+        }
+        assert!(corrupt(seq.try_iter()));
+    }
+
+    #[test]
+    fn test_cycle() {
+        let mut seq = sample();
+        let (&rule_id, &head) = seq.rules().iter().find(|(&id, _)| id != 0).unwrap();
+        // Point the rule's first symbol back at the rule itself.
+        let first = seq.grammar.symbols[head].next.unwrap();
+        seq.grammar.decrement_if_rule(first);
+        seq.grammar.symbols[first].symbol = Symbol::RuleRef { rule_id };
+        seq.grammar.increment_if_rule(first);
+        assert!(corrupt(seq.try_iter()));
+    }
+
+    #[test]
+    fn test_wrong_length() {
+        let mut seq = sample();
+        seq.length += 1;
+        assert!(corrupt(seq.try_iter()));
+    }
+
+    #[test]
+    fn test_broken_links() {
+        let mut seq = sample();
+        let head = seq.rules()[&0];
+        let first = seq.grammar.symbols[head].next.unwrap();
+        seq.grammar.symbols[first].prev = None;
+        assert!(corrupt(seq.try_iter()));
+    }
+
+    fn rle_sample() -> SequiturRle<char> {
+        let mut seq = SequiturRle::new();
+        seq.extend("aaabcbcbcaaabcbcbc".chars());
+        seq
+    }
+
+    #[test]
+    fn test_valid_rle_grammars() {
+        assert!(rle_sample().try_iter().is_ok());
+        assert!(SequiturRle::<u8>::new().try_iter().is_ok());
+
+        let mut docs = SequiturDocumentsRle::new();
+        docs.extend_document(1, "xxyzxxyz".chars());
+        docs.extend_document(2, "xxyz".chars());
+        assert!(docs.try_iter_document(&1).unwrap().is_some());
+        assert!(docs.try_iter_document(&3).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_rle_wrong_count() {
+        let mut seq = rle_sample();
+        let (_, &head) = seq.rules().iter().find(|(&id, _)| id != 0).unwrap();
+        if let Symbol::RuleHead { count, .. } = &mut seq.grammar.symbols[head].symbol {
+            *count += 1;
+        }
+        assert!(corrupt(seq.try_iter()));
+    }
+
+    #[test]
+    fn test_rle_empty_run() {
+        let mut seq = rle_sample();
+        let head = seq.rules()[&0];
+        let first = seq.grammar.symbols[head].next.unwrap();
+        seq.grammar.symbols[first].run = 0;
+        assert!(corrupt(seq.try_iter()));
+    }
+
+    #[test]
+    fn test_rle_wrong_length() {
+        let mut seq = rle_sample();
+        seq.length += 1;
+        assert!(corrupt(seq.try_iter()));
+
+        let mut docs = SequiturDocumentsRle::new();
+        docs.extend_document(1, "xxyzxxyz".chars());
+        docs.documents.get_mut(&1).unwrap().length = usize::MAX;
+        assert!(corrupt(docs.try_iter_document(&1)));
+    }
+
+    #[test]
+    fn test_missing_root_iterates_nothing() {
+        let mut seq = sample();
+        seq.grammar.rule_index.remove(&0);
+        assert_eq!(seq.iter().count(), 0);
+        assert!(corrupt(seq.try_iter()));
+
+        let mut rle = rle_sample();
+        rle.grammar.rule_index.remove(&0);
+        assert_eq!(rle.iter().count(), 0);
+        assert!(corrupt(rle.try_iter()));
+    }
+}